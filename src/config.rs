@@ -1,22 +1,499 @@
 //! Configuration for span generators
 
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use core::time::Duration;
+
 use crate::level::Level;
 
-/// Config that determines the ouput of the span generator
+/// Determines how span lines are rendered
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Format {
+    /// An indented tree with vertical bars and enter/exit glyphs, e.g. `┌parse`.
+    #[default]
+    Tree,
+    /// One `<depth> <marker> <name>` line per event without any indentation, e.g. `2 E parse` on
+    /// enter and `2 X parse` on exit. The line can be parsed with `split_whitespace`: names
+    /// containing whitespace or quotes are wrapped in double quotes, with `"` and `\` escaped by a
+    /// backslash.
+    Flat,
+    /// One JSON object per line, e.g. `{"event":"enter","name":"fib(5)","depth":0}` on enter and
+    /// `{"event":"exit","name":"fib(5)","depth":0}` on exit. Exit objects additionally carry
+    /// `early_return`, `duration_us` and `metrics` fields when those are in use.
+    JsonLines,
+    /// The Trace Event Format of `chrome://tracing` and Perfetto: a JSON array with a `"ph":"B"`
+    /// event on enter and a `"ph":"E"` event on exit, e.g.
+    /// `{"name":"fib(5)","ph":"B","ts":12,"pid":1,"tid":1}`. `ts` is the time in microseconds
+    /// since the first event. The array is opened by the first event and closed by
+    /// `Spanner::write_footer`. Spans deeper than `Config::max_depth` are omitted without a
+    /// marker, and early returns, timing and metrics are not rendered.
+    ChromeTrace,
+    /// Brendan Gregg's folded stacks for flamegraph tools such as `inferno-flamegraph`: nothing is
+    /// written on enter, and on exit a line with the names of the open spans separated by `;`
+    /// and the self time of the span in microseconds, e.g. `main;parse;lex 120`. The self time
+    /// excludes the time spent in child spans. Spans that are filtered out by their level, rate or
+    /// `Config::max_depth` are not part of any stack, so their time counts towards the self time
+    /// of the enclosing span. Semicolons in names are replaced with `:`.
+    FoldedStacks,
+    /// A nested Markdown bullet list with a single bullet per span, written on enter, e.g.
+    /// `  - parse` for a span at depth 1, and nothing on exit. Every depth is indented by two
+    /// spaces, which Markdown renders as a nested list, so traces can be pasted into issues
+    /// without the box-drawing characters of `Format::Tree`. Names are written verbatim, and early
+    /// returns, timing and metrics are not rendered.
+    Markdown,
+    /// A self-contained HTML document where every span is a collapsible
+    /// `<details><summary>name</summary> ... </details>` element nesting the elements of its
+    /// children, so subtrees can be expanded and collapsed in a browser. The element is opened on
+    /// enter and closed on exit, indented by two spaces per depth. The document is opened by the
+    /// first span and closed by `Spanner::write_footer`. HTML special characters in names are
+    /// escaped, spans deeper than `Config::max_depth` are omitted without a marker, and early
+    /// returns, timing and metrics are not rendered.
+    Html,
+    /// Comma-separated values with a row per span, written on exit, under a
+    /// `name,depth,enter_ts_us,exit_ts_us,duration_us` header that is written before the first
+    /// row, e.g. `parse,1,120,4520,4400`. Timestamps are in microseconds since the spanner was
+    /// created, and the span is timed even if `Config::timing` is disabled. Names containing
+    /// commas, quotes or line breaks are quoted, with quotes doubled. Spans deeper than
+    /// `Config::max_depth` are omitted without a marker. Rows are only written by `Spanner`, as
+    /// `LocalSpanner` does not time spans.
+    Csv,
+}
+
+impl Format {
+    /// Checks whether lines can be decorated with suffixes such as timing, metrics or fold
+    /// markers.
+    #[cfg(feature = "std")]
+    pub(crate) const fn supports_suffixes(self) -> bool {
+        matches!(self, Self::Tree | Self::Flat)
+    }
+}
+
+/// Determines the timestamp that is prepended to every line
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum TimestampMode {
+    /// No timestamp.
+    #[default]
+    None,
+    /// The time since the spanner was created in milliseconds, e.g. `[12.345ms] `.
+    Relative,
+    /// The UTC wall-clock time, e.g. `[13:37:02.123] `.
+    Wall,
+}
+
+/// Determines the markers that wrap every span so that its subtree can be folded in an editor or
+/// a pager. The open marker is appended to the enter line and the close marker to the exit line,
+/// so folds nest exactly like spans.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum FoldStyle {
+    /// Vim's default `{{{` and `}}}` markers, used with `:set foldmethod=marker`.
+    Vim,
+    /// Custom open and close markers.
+    Custom {
+        /// The marker appended to enter lines.
+        open: &'static str,
+        /// The marker appended to exit lines.
+        close: &'static str,
+    },
+}
+
+impl FoldStyle {
+    /// Returns the open and close markers of the style.
+    #[must_use]
+    pub const fn markers(&self) -> (&'static str, &'static str) {
+        match self {
+            Self::Vim => ("{{{", "}}}"),
+            Self::Custom { open, close } => (open, close),
+        }
+    }
+}
+
+/// A terminal color, see `Config::color_map`.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    /// A code of the ANSI 256-color palette.
+    Fixed(u8),
+}
+
+impl Color {
+    /// Writes the escape sequence that sets the foreground color to `self`.
+    pub(crate) fn write_escape<W: core::fmt::Write>(self, w: &mut W) -> core::fmt::Result {
+        let code = match self {
+            Self::Black => 0,
+            Self::Red => 1,
+            Self::Green => 2,
+            Self::Yellow => 3,
+            Self::Blue => 4,
+            Self::Magenta => 5,
+            Self::Cyan => 6,
+            Self::White => 7,
+            Self::Fixed(code) => return write!(w, "\x1b[38;5;{code}m"),
+        };
+        write!(w, "\x1b[3{code}m")
+    }
+}
+
+/// A preset of box-drawing glyphs that sets the enter glyph, the exit glyph and the vertical bars
+/// consistently, see `Config::with_theme`. Unlike the default mixed set of bars, every theme uses
+/// the same vertical bar at every depth.
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Theme {
+    /// `+` corners and `|` bars, for terminals without Unicode support.
+    Ascii,
+    /// `┌`/`└` corners and `│` bars.
+    Light,
+    /// `┏`/`┗` corners and `┃` bars.
+    Heavy,
+    /// `╭`/`╰` corners and `│` bars.
+    Rounded,
+    /// `╔`/`╚` corners and `║` bars.
+    Double,
+}
+
+impl Theme {
+    /// Returns the enter and exit glyphs of the theme.
+    #[must_use]
+    pub const fn corners(self) -> (char, char) {
+        match self {
+            Self::Ascii => ('+', '+'),
+            Self::Light => ('┌', '└'),
+            Self::Heavy => ('┏', '┗'),
+            Self::Rounded => ('╭', '╰'),
+            Self::Double => ('╔', '╚'),
+        }
+    }
+
+    /// Returns the function that maps every depth to the vertical bar of the theme.
+    #[must_use]
+    pub const fn depthmap(self) -> fn(usize) -> char {
+        match self {
+            Self::Ascii => ascii_depthmap,
+            Self::Light | Self::Rounded => light_depthmap,
+            Self::Heavy => heavy_depthmap,
+            Self::Double => double_depthmap,
+        }
+    }
+}
+
+/// A predicate over span names, see `Config::with_name_filter`.
+///
+/// The predicate is shared between the clones of a config. Two filters are equal if they share
+/// the same predicate.
+#[derive(Clone)]
+pub struct NameFilter(Arc<dyn Fn(&str) -> bool + Send + Sync>);
+
+impl NameFilter {
+    /// Creates a new `NameFilter` from a predicate.
+    ///
+    /// # Parameters
+    /// - `filter`: The predicate that returns `true` for the names of the spans to show.
+    pub fn new(filter: impl Fn(&str) -> bool + Send + Sync + 'static) -> Self {
+        Self(Arc::new(filter))
+    }
+
+    /// Checks whether `name` passes the filter.
+    ///
+    /// # Parameters
+    /// - `name`: The name of a span.
+    #[must_use]
+    pub fn matches(&self, name: &str) -> bool {
+        (self.0)(name)
+    }
+}
+
+impl PartialEq for NameFilter {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for NameFilter {}
+
+impl core::fmt::Debug for NameFilter {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("NameFilter(..)")
+    }
+}
+
+/// The largest tab width accepted by `Config::validate`, and the width larger tab widths are
+/// clamped to when rendering.
+pub const MAX_TABWIDTH: usize = 256;
+
+/// The largest skip accepted by `Config::validate`. A larger skip only ever displays the guide of
+/// the top-level depth, as no realistic trace is that deep.
+pub const MAX_SKIP: usize = 256;
+
+/// The error returned by `Config::validate` for a config that produces nonsensical output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConfigError {
+    /// `tabwidth` is larger than `MAX_TABWIDTH`.
+    TabwidthTooLarge {
+        /// The configured tab width.
+        tabwidth: usize,
+    },
+    /// `name_column` is not right of `glyph_column`, so names can never end at it.
+    NameColumnBeforeGlyph {
+        /// The configured name column.
+        name_column: usize,
+        /// The configured glyph column.
+        glyph_column: usize,
+    },
+    /// `max_rate` is `Some(0)`, which suppresses every span.
+    ZeroMaxRate,
+    /// `sampling` is `Some(0)`, which is not a sampling interval.
+    ZeroSampling,
+    /// `skip` is larger than `MAX_SKIP`.
+    SkipTooLarge {
+        /// The configured skip.
+        skip: usize,
+    },
+}
+
+impl core::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::TabwidthTooLarge { tabwidth } => {
+                write!(f, "tab width {tabwidth} is larger than {MAX_TABWIDTH}")
+            }
+            Self::NameColumnBeforeGlyph {
+                name_column,
+                glyph_column,
+            } => write!(
+                f,
+                "name column {name_column} is not right of glyph column {glyph_column}"
+            ),
+            Self::ZeroMaxRate => f.write_str("a max rate of 0 suppresses every span"),
+            Self::ZeroSampling => f.write_str("a sampling interval of 0 samples no span"),
+            Self::SkipTooLarge { skip } => write!(f, "skip {skip} is larger than {MAX_SKIP}"),
+        }
+    }
+}
+
+impl core::error::Error for ConfigError {}
+
+/// Config that determines the ouput of the span generator
+///
+/// `Config` is `Clone` but not `Copy`, as `Config::name_filter` holds a closure shared between the
+/// clones, so clone a config to configure several spanners with it.
+///
+/// The options that shape the indented tree, such as `tabwidth`, `skip`, `depthmap`, `labels`,
+/// `name_column`, `color`, `color_map`, `compact_leaves`, `indent_str` and `tree_connectors`, only
+/// apply to `Format::Tree`. The options that also apply to `Format::Flat` say so.
+///
+/// With the `serde` feature enabled, `Config` can be serialized and deserialized, e.g. loaded from
+/// a `config.toml`. Missing fields keep their default values. Functions can not be serialized, so
+/// `depthmap` is represented by the name of a preset, `"default"` or the name of the `Theme` it
+/// comes from, e.g. `"heavy"`, and `color` by `"rainbow"`; serializing a config with a custom
+/// function or a name filter fails.
+///
+/// Deserializing a config leaks its labels, custom fold markers, `indent_str`, `newline` and
+/// `line_prefix`, as `Config` holds them as `&'static str`s. Deserialize a config once, e.g. at
+/// startup, and clone it rather than deserializing it again for every spanner.
+#[derive(Clone, Eq, PartialEq, Debug)]
+#[allow(unpredictable_function_pointer_comparisons)]
+// Every flag is an independent option with its own builder method, so they do not form a state
+// machine that an enum could replace
+#[allow(clippy::struct_excessive_bools)]
 pub struct Config<T = fn(usize) -> char>
 where
     T: Fn(usize) -> char,
 {
-    /// Determines how many spaces is added per depth. Default is `2`.
+    /// Determines how many spaces is added per depth. A tab width of `0` disables indentation and
+    /// vertical bars altogether. Tab widths larger than `MAX_TABWIDTH` are clamped to it when
+    /// rendering, so that extreme values can neither overflow nor allocate absurd amounts of
+    /// memory. Default is `2`.
     pub tabwidth: usize,
     /// Determines the frequency of vertical bars. `skip: 2` means a bar is placed every 2 spans. The skip value of 0 means that no bars are displayed. Default is `2`.
     pub skip: usize,
     /// Function that maps the depth of a span to a vertical bar character.
     pub depthmap: T,
     /// Determines the minumum level for the spans. Spans with level below the `level` are
-    /// ignored. Deafult is `Level::Info`
+    /// ignored. This is the initial level of a `Spanner`, which can be changed at runtime with
+    /// `Spanner::set_level`. Deafult is `Level::Info`
     pub level: Level,
+    /// Determines how long emitted span lines are retained for `Spanner::dump_window`. Lines
+    /// older than the window are pruned. `None` disables retention. Default is `None`.
+    pub time_window: Option<Duration>,
+    /// Aligns every enter/exit glyph to this column by padding the indentation with spaces.
+    /// Prefixes already wider than the column are left as they are. Default is `None`.
+    pub glyph_column: Option<usize>,
+    /// Caps the number of spans entered per second. Spans over the limit are suppressed as a whole:
+    /// they produce no lines and do not change the depth. Default is `None`.
+    pub max_rate: Option<u32>,
+    /// Renders only every Nth top-level span, starting with the first. The other top-level spans
+    /// and their whole subtrees are suppressed as a whole, like spans over `max_rate`. Default is
+    /// `None`.
+    pub sampling: Option<u32>,
+    /// The glyph that replaces the exit glyph of spans marked with `Span::mark_early_return`.
+    /// Default is `'↵'`.
+    pub early_return_char: char,
+    /// Determines how span lines are rendered. Default is `Format::Tree`.
+    pub format: Format,
+    /// Prepends a `[name] ` label of the current thread to every line. The label falls back to the
+    /// thread id, e.g. `[ThreadId(2)] `, when the thread is unnamed. The current thread is read
+    /// on every write. Default is `false`.
+    pub thread_labels: bool,
+    /// Escapes control characters in span names, e.g. the escape character becomes the literal
+    /// text `\x1b`, so that names coming from untrusted input cannot inject ANSI escape sequences
+    /// or break lines. Names are rendered verbatim when disabled, which is faster but unsafe for
+    /// untrusted names. Default is `false`.
+    pub sanitize_names: bool,
+    /// Records the depth of every entered span so that a compact run-length encoded depth profile
+    /// can be rendered with `Spanner::overview`. Default is `false`.
+    pub overview: bool,
+    /// Records every emitted line with its timestamp and thread so that the traces of several
+    /// spanners can be combined with `merge_timestamped`. Default is `false`.
+    pub record_events: bool,
+    /// Appends fold markers to enter and exit lines. Default is `None`.
+    pub fold_markers: Option<FoldStyle>,
+    /// Appends the wall-clock duration of the span to its exit message, e.g. `└fib(5) (1.2ms)`.
+    /// Default is `false`.
+    pub timing: bool,
+    /// Function that maps the depth of a span to an ANSI 256-color code. When set, vertical bars
+    /// and span names are wrapped in `\x1b[38;5;Nm ... \x1b[0m` escape sequences. Default is
+    /// `None`.
+    pub color: Option<fn(usize) -> u8>,
+    /// Determines the maximum depth that is rendered. Spans entered deeper are still tracked but
+    /// produce no lines; a single `… (truncated)` marker is written where they start. Default is
+    /// `None`.
+    pub max_depth: Option<usize>,
+    /// The glyph that is placed before the name in enter messages. Default is `'┌'`.
+    pub enter_char: char,
+    /// The glyph that is placed before the name in exit messages. Default is `'└'`.
+    pub exit_char: char,
+    /// Labels that are appended after the name of enter and exit messages respectively, separated
+    /// by a space, e.g. `┌Span(0) entered` and `└Span(0) dropped`. Default is `None`.
+    pub labels: Option<(&'static str, &'static str)>,
+    /// Determines the maximum width of span names in characters, or in columns with the
+    /// `unicode-width` feature. Longer names are truncated on character boundaries and end with
+    /// `…`, which counts towards the width. Default is `None`.
+    pub max_name_width: Option<usize>,
+    /// Records every entered span as a node of a tree that can be inspected with
+    /// `Spanner::into_tree` instead of matching the rendered output. Default is `false`.
+    pub record_tree: bool,
+    /// Accumulates the number of times each span name was entered and the time spent in its spans,
+    /// which can be inspected with `Spanner::stats`. Default is `false`.
+    pub collect_stats: bool,
+    /// Tracks the depth of every thread separately, so that the indentation of each line reflects
+    /// the depth of the thread that emitted it instead of the depth shared by all threads. Lines
+    /// of different threads still interleave, so it is best combined with `thread_labels`.
+    /// Default is `false`.
+    pub thread_local_depth: bool,
+    /// Pads span names with spaces so that they end at this column, counted in characters from the
+    /// start of the line, or in columns with the `unicode-width` feature, so that the content
+    /// following the names, such as labels, timing or metrics, lines up across depths. Names that
+    /// already end past the column are not truncated and are followed by the content right away.
+    /// Lines are padded even if nothing follows the name. Default is `None`.
+    pub name_column: Option<usize>,
+    /// Appends the source location of the `spf!` invocation or the method call that entered a
+    /// span to its enter message, e.g. `┌parse (src/main.rs:42)`. Only applies to `Format::Tree`
+    /// and `Format::Flat`. Default is `false`.
+    pub location: bool,
+    /// Overrides the minimum level of spans entered with `Spanner::enter_target` for the listed
+    /// targets. Spans of other targets and spans entered without a target use the level of the
+    /// spanner. Default is no overrides.
+    pub target_levels: BTreeMap<String, Level>,
+    /// Hides the spans whose names do not pass the filter, like spans below the minimum level: they
+    /// produce no lines and do not change the depth. Default is `None`.
+    pub name_filter: Option<NameFilter>,
+    /// Function that maps the depth of a span to a guide string, e.g. two characters wide, which
+    /// replaces `depthmap` when set. Every indentation segment stays `tabwidth` characters wide, so
+    /// guides shorter than the tab width are padded with spaces and longer guides are cut to it;
+    /// the tab width should be at least as wide as the guides. Default is `None`.
+    pub depthmap_str: Option<fn(usize) -> &'static str>,
+    /// Writes a blank line before every span entered at depth zero except the first one, so that
+    /// independent top-level operations are easy to tell apart. Only applies to `Format::Tree` and
+    /// `Format::Flat`. Default is `false`.
+    pub top_level_separator: bool,
+    /// Writes a summary line with the number of entered spans and the maximum depth they reached,
+    /// e.g. `— 127 spans, max depth 9 —`, when the `Spanner` is dropped. Only applies to
+    /// `Format::Tree` and `Format::Flat`. Default is `false`.
+    pub summary: bool,
+    /// Writes a `⚠ depth N exceeded` line when a span is entered deeper than this depth, e.g. to
+    /// catch runaway recursion early. The warning is written once per crossing: it is written again
+    /// only after the depth dropped back to the threshold. Only applies to `Format::Tree` and
+    /// `Format::Flat`. Default is `None`.
+    pub depth_warning: Option<usize>,
+    /// Renders spans without children on a single line with `leaf_char` as the glyph, e.g.
+    /// `─fib(1)`, instead of a pair of enter and exit lines. The enter message of every span is
+    /// held back until a child span is entered or the span is dropped, so it is written late.
+    /// Default is `false`.
+    pub compact_leaves: bool,
+    /// The glyph that is placed before the name of spans without children when
+    /// `compact_leaves` is enabled. Default is `'─'`.
+    pub leaf_char: char,
+    /// Prepends a timestamp to every line, before the thread label and the indentation, e.g. to
+    /// correlate spans with other logs. Default is `TimestampMode::None`.
+    pub timestamps: TimestampMode,
+    /// Collapses consecutive sibling spans with the same name into the first one, whose exit line
+    /// is annotated with the number of spans, e.g. `└fib(2) ×4`. The repeated spans and their
+    /// children produce no lines. The exit line of every span is held back until it is known
+    /// whether a repeat follows it, so it is written late. Only applies to `Format::Tree` and
+    /// `Format::Flat`. Default is `false`.
+    pub collapse_repeats: bool,
+    /// Function that maps the depth of a span to the color of its whole line, including the
+    /// indentation, mirroring `depthmap`. When set, every line is wrapped in the escape sequence of
+    /// the color and `\x1b[0m`, and `color` is ignored. Default is `None`.
+    pub color_map: Option<fn(usize) -> Color>,
+    /// Only uses the colors of `color` and `color_map` when the output is a terminal and the
+    /// `NO_COLOR` environment variable is not set, following the <https://no-color.org> convention.
+    /// Only `StdoutSpanner` and `StderrSpanner` can write to a terminal, so files, vectors and
+    /// other writers never get colors in this mode. Colors are always used when it is disabled.
+    /// Default is `false`.
+    pub auto_color: bool,
+    /// The string drawn at every depth instead of a vertical bar padded to `tabwidth`, e.g.
+    /// `"│ "` for the continuous vertical rules of a classic tree. It replaces `depthmap`,
+    /// `depthmap_str` and `tabwidth`: the indentation of a depth is the string itself, so its guide
+    /// character is part of it, and the glyph of a span at depth `n` is placed after `n` copies of
+    /// it. Depths that `skip` does not display are indented with as many spaces as the string is
+    /// wide. Default is `None`.
+    pub indent_str: Option<&'static str>,
+    /// Renders spans as a classic tree with a single line per span, connecting every span to its
+    /// parent with `├─ `, or `└─ ` if it is the last child, below `│  ` rules of the ancestors
+    /// that have more children. As it is only known which span is the last child once its parent
+    /// is dropped, the whole tree of a top-level span is held back and written when it is dropped.
+    /// Exit lines are not written, and the options that decorate enter and exit lines, such as
+    /// `skip`, `depthmap`, `timing`, `compact_leaves` or `collapse_repeats`, do not apply. Spans
+    /// are nested by the order they are entered and dropped in, like with `record_tree`. Default
+    /// is `false`.
+    pub tree_connectors: bool,
+    /// Writes the enter line of every span. Disabling it produces an exit-only trace, in which the
+    /// indentation still shows the call structure. Only applies to `Format::Tree` and
+    /// `Format::Flat`, as the other formats need both lines of a span. Default is `true`.
+    pub enter_lines: bool,
+    /// Writes the exit line of every span. Disabling it produces a more compact enter-only trace,
+    /// in which the indentation still shows the call structure. Only applies to `Format::Tree` and
+    /// `Format::Flat`, as the other formats need both lines of a span. Default is `true`.
+    pub exit_lines: bool,
+    /// The sequence that ends every written line, e.g. `"\r\n"` for Windows-friendly files. It
+    /// also ends the summary, the separators and the headers and footers of the structured
+    /// formats. Default is `"\n"`.
+    pub newline: &'static str,
+    /// The number of spaces prepended to every line, including the summary, the warnings and the
+    /// footers, e.g. to nest the whole trace under an existing indentation level. Default is `0`.
+    pub indent_offset: usize,
+    /// The string prepended to every line before the indent offset and the timestamp, e.g.
+    /// `"SPAN | "` to grep the spans out of mixed logs. Default is `None`.
+    pub line_prefix: Option<&'static str>,
 }
 
 impl Config {
@@ -30,6 +507,42 @@ impl Config {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// Creates a `Config` instance with default values and the level read from the
+    /// `SPANNIFY_LEVEL` environment variable, like `RUST_LOG`.
+    ///
+    /// Accepts `trace`, `debug`, `info`, `warn` and `error`, ignoring case and surrounding
+    /// whitespace. Falls back to `Level::Info` if the variable is unset or can not be parsed.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::{config::Config, core::StdoutSpanner};
+    ///
+    /// // SPANNIFY_LEVEL=debug cargo run
+    /// let spanner = StdoutSpanner::new().with_config(Config::from_env());
+    /// ```
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn from_env() -> Self {
+        let value = std::env::var(LEVEL_ENV_VAR).ok();
+        Self::default().with_level(level_from_env(value.as_deref()))
+    }
+
+    /// Creates a `Config` instance with default values that colors every depth, cycling through
+    /// red, orange, yellow, green, blue and purple.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// use spannify::{config::Config, core::StdoutSpanner};
+    ///
+    /// let spanner = StdoutSpanner::new().with_config(Config::rainbow());
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn rainbow() -> Self {
+        Self::default().with_color(Some(rainbow_color))
+    }
 }
 
 impl<T> Config<T>
@@ -54,9 +567,88 @@ where
             skip: self.skip,
             depthmap,
             level: self.level,
+            time_window: self.time_window,
+            glyph_column: self.glyph_column,
+            max_rate: self.max_rate,
+            sampling: self.sampling,
+            early_return_char: self.early_return_char,
+            format: self.format,
+            thread_labels: self.thread_labels,
+            sanitize_names: self.sanitize_names,
+            overview: self.overview,
+            record_events: self.record_events,
+            fold_markers: self.fold_markers,
+            timing: self.timing,
+            color: self.color,
+            max_depth: self.max_depth,
+            enter_char: self.enter_char,
+            exit_char: self.exit_char,
+            labels: self.labels,
+            max_name_width: self.max_name_width,
+            record_tree: self.record_tree,
+            collect_stats: self.collect_stats,
+            thread_local_depth: self.thread_local_depth,
+            name_column: self.name_column,
+            location: self.location,
+            target_levels: self.target_levels,
+            name_filter: self.name_filter,
+            depthmap_str: self.depthmap_str,
+            top_level_separator: self.top_level_separator,
+            summary: self.summary,
+            depth_warning: self.depth_warning,
+            compact_leaves: self.compact_leaves,
+            leaf_char: self.leaf_char,
+            timestamps: self.timestamps,
+            collapse_repeats: self.collapse_repeats,
+            color_map: self.color_map,
+            auto_color: self.auto_color,
+            indent_str: self.indent_str,
+            tree_connectors: self.tree_connectors,
+            enter_lines: self.enter_lines,
+            exit_lines: self.exit_lines,
+            newline: self.newline,
+            indent_offset: self.indent_offset,
+            line_prefix: self.line_prefix,
         }
     }
 
+    /// Replaces the enter glyph, the exit glyph and the depthmap with the ones of a theme.
+    ///
+    /// # Parameters
+    /// - `theme`: The theme to use.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::{Config, Theme};
+    ///
+    /// let config = Config::new().with_theme(Theme::Rounded);
+    /// assert_eq!(config.enter_char, '╭');
+    /// assert_eq!((config.depthmap)(1), '│');
+    /// ```
+    #[must_use]
+    pub fn with_theme(self, theme: Theme) -> Config {
+        let (enter_char, exit_char) = theme.corners();
+        self.with_depthmap(theme.depthmap())
+            .with_enter_char(enter_char)
+            .with_exit_char(exit_char)
+    }
+
+    /// Replaces the tab width value.
+    ///
+    /// # Parameters
+    /// - `tabwidth`: The new number of columns added per depth.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_tabwidth(4).with_skip(2);
+    /// ```
+    #[must_use]
+    pub fn with_tabwidth(self, tabwidth: usize) -> Self {
+        Self { tabwidth, ..self }
+    }
+
     /// Replaces the skip value.
     ///
     /// # Parameters
@@ -88,23 +680,1380 @@ where
     pub fn with_level(self, level: Level) -> Self {
         Self { level, ..self }
     }
-}
 
-impl Default for Config {
-    fn default() -> Self {
+    /// Replaces the time window value.
+    ///
+    /// # Parameters
+    /// - `time_window`: How long emitted lines are retained, or `None` to disable retention.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    /// use std::time::Duration;
+    ///
+    /// let config = Config::new().with_time_window(Some(Duration::from_secs(5)));
+    /// ```
+    #[must_use]
+    pub fn with_time_window(self, time_window: Option<Duration>) -> Self {
         Self {
-            tabwidth: 2,
-            skip: 2,
-            depthmap: default_depthmap,
-            level: Level::Info,
+            time_window,
+            ..self
         }
     }
-}
-/// Default function that maps depth to a vertical bar character.
-///
-/// Cycles through the characters `['|', '¦', '┆', '┊']` based on depth.
-const fn default_depthmap(depth: usize) -> char {
-    /// A list of vertical bars that we cycle through by default
-    const DEPTHMAP: [char; 4] = ['|', '¦', '┆', '┊'];
-    DEPTHMAP[depth % DEPTHMAP.len()]
+
+    /// Replaces the glyph column value.
+    ///
+    /// # Parameters
+    /// - `glyph_column`: The column to align the glyphs to, or `None` to place them right after
+    ///   the indentation.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_glyph_column(Some(8));
+    /// ```
+    #[must_use]
+    pub fn with_glyph_column(self, glyph_column: Option<usize>) -> Self {
+        Self {
+            glyph_column,
+            ..self
+        }
+    }
+
+    /// Replaces the max rate value.
+    ///
+    /// # Parameters
+    /// - `max_rate`: The maximum number of spans per second, or `None` to disable rate limiting.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_max_rate(Some(1000));
+    /// ```
+    #[must_use]
+    pub fn with_max_rate(self, max_rate: Option<u32>) -> Self {
+        Self { max_rate, ..self }
+    }
+
+    /// Replaces the sampling value.
+    ///
+    /// # Parameters
+    /// - `sampling`: Renders only every Nth top-level span, or `None` to render all of them.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_sampling(Some(100));
+    /// ```
+    #[must_use]
+    pub fn with_sampling(self, sampling: Option<u32>) -> Self {
+        Self { sampling, ..self }
+    }
+
+    /// Replaces the early return char value.
+    ///
+    /// # Parameters
+    /// - `early_return_char`: The new glyph for early-returned spans.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_early_return_char('<');
+    /// ```
+    #[must_use]
+    pub fn with_early_return_char(self, early_return_char: char) -> Self {
+        Self {
+            early_return_char,
+            ..self
+        }
+    }
+
+    /// Replaces the format value.
+    ///
+    /// # Parameters
+    /// - `format`: The new output format.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::{Config, Format};
+    ///
+    /// let config = Config::new().with_format(Format::Flat);
+    /// ```
+    #[must_use]
+    pub fn with_format(self, format: Format) -> Self {
+        Self { format, ..self }
+    }
+
+    /// Replaces the thread labels value.
+    ///
+    /// # Parameters
+    /// - `thread_labels`: Whether to label lines with the current thread.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_thread_labels(true);
+    /// ```
+    #[must_use]
+    pub fn with_thread_labels(self, thread_labels: bool) -> Self {
+        Self {
+            thread_labels,
+            ..self
+        }
+    }
+
+    /// Replaces the sanitize names value.
+    ///
+    /// # Parameters
+    /// - `sanitize_names`: Whether to escape control characters in span names.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_sanitize_names(true);
+    /// ```
+    #[must_use]
+    pub fn with_sanitize_names(self, sanitize_names: bool) -> Self {
+        Self {
+            sanitize_names,
+            ..self
+        }
+    }
+
+    /// Replaces the overview value.
+    ///
+    /// # Parameters
+    /// - `overview`: Whether to record the depth profile.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_overview(true);
+    /// ```
+    #[must_use]
+    pub fn with_overview(self, overview: bool) -> Self {
+        Self { overview, ..self }
+    }
+
+    /// Replaces the record events value.
+    ///
+    /// # Parameters
+    /// - `record_events`: Whether to record timestamped events.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_record_events(true);
+    /// ```
+    #[must_use]
+    pub fn with_record_events(self, record_events: bool) -> Self {
+        Self {
+            record_events,
+            ..self
+        }
+    }
+
+    /// Replaces the fold markers value.
+    ///
+    /// # Parameters
+    /// - `fold_markers`: The fold marker style, or `None` to disable fold markers.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::{Config, FoldStyle};
+    ///
+    /// let config = Config::new().with_fold_markers(Some(FoldStyle::Vim));
+    /// ```
+    #[must_use]
+    pub fn with_fold_markers(self, fold_markers: Option<FoldStyle>) -> Self {
+        Self {
+            fold_markers,
+            ..self
+        }
+    }
+
+    /// Replaces the timing value.
+    ///
+    /// # Parameters
+    /// - `timing`: Whether to display span durations.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_timing(true);
+    /// ```
+    #[must_use]
+    pub fn with_timing(self, timing: bool) -> Self {
+        Self { timing, ..self }
+    }
+
+    /// Replaces the color value.
+    ///
+    /// # Parameters
+    /// - `color`: The function mapping depth to a 256-color code, or `None` to disable colors.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_color(Some(|depth| if depth % 2 == 0 { 33 } else { 39 }));
+    /// ```
+    #[must_use]
+    pub fn with_color(self, color: Option<fn(usize) -> u8>) -> Self {
+        Self { color, ..self }
+    }
+
+    /// Replaces the max depth value.
+    ///
+    /// # Parameters
+    /// - `max_depth`: The maximum rendered depth, or `None` to render every depth.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_max_depth(Some(8));
+    /// ```
+    #[must_use]
+    pub fn with_max_depth(self, max_depth: Option<usize>) -> Self {
+        Self { max_depth, ..self }
+    }
+
+    /// Replaces the enter char value.
+    ///
+    /// # Parameters
+    /// - `enter_char`: The new enter glyph.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_enter_char('+');
+    /// ```
+    #[must_use]
+    pub fn with_enter_char(self, enter_char: char) -> Self {
+        Self { enter_char, ..self }
+    }
+
+    /// Replaces the exit char value.
+    ///
+    /// # Parameters
+    /// - `exit_char`: The new exit glyph.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_exit_char('+');
+    /// ```
+    #[must_use]
+    pub fn with_exit_char(self, exit_char: char) -> Self {
+        Self { exit_char, ..self }
+    }
+
+    /// Replaces the labels value.
+    ///
+    /// # Parameters
+    /// - `labels`: The enter and exit labels, or `None` to display bare names.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_labels(Some(("entered", "dropped")));
+    /// ```
+    #[must_use]
+    pub fn with_labels(self, labels: Option<(&'static str, &'static str)>) -> Self {
+        Self { labels, ..self }
+    }
+
+    /// Replaces the max name width value.
+    ///
+    /// # Parameters
+    /// - `max_name_width`: The maximum name width, or `None` to never truncate names.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_max_name_width(Some(32));
+    /// ```
+    #[must_use]
+    pub fn with_max_name_width(self, max_name_width: Option<usize>) -> Self {
+        Self {
+            max_name_width,
+            ..self
+        }
+    }
+
+    /// Replaces the record tree value.
+    ///
+    /// # Parameters
+    /// - `record_tree`: Whether to record the tree of spans.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_record_tree(true);
+    /// ```
+    #[must_use]
+    pub fn with_record_tree(self, record_tree: bool) -> Self {
+        Self {
+            record_tree,
+            ..self
+        }
+    }
+
+    /// Replaces the collect stats value.
+    ///
+    /// # Parameters
+    /// - `collect_stats`: Whether to collect per-name statistics.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_collect_stats(true);
+    /// ```
+    #[must_use]
+    pub fn with_collect_stats(self, collect_stats: bool) -> Self {
+        Self {
+            collect_stats,
+            ..self
+        }
+    }
+
+    /// Replaces the thread local depth value.
+    ///
+    /// # Parameters
+    /// - `thread_local_depth`: Whether to track the depth per thread.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_thread_local_depth(true);
+    /// ```
+    #[must_use]
+    pub fn with_thread_local_depth(self, thread_local_depth: bool) -> Self {
+        Self {
+            thread_local_depth,
+            ..self
+        }
+    }
+
+    /// Replaces the name column value.
+    ///
+    /// # Parameters
+    /// - `name_column`: The column names are padded to, or `None` to disable padding.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_name_column(Some(24));
+    /// ```
+    #[must_use]
+    pub fn with_name_column(self, name_column: Option<usize>) -> Self {
+        Self {
+            name_column,
+            ..self
+        }
+    }
+
+    /// Replaces the location value.
+    ///
+    /// # Parameters
+    /// - `location`: Whether to append the source location to enter messages.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_location(true);
+    /// ```
+    #[must_use]
+    pub fn with_location(self, location: bool) -> Self {
+        Self { location, ..self }
+    }
+
+    /// Sets the minimum level of the spans of `target`, replacing its previous level.
+    ///
+    /// # Parameters
+    /// - `target`: The target of the spans.
+    /// - `level`: The minimum level of the spans of the target.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::{config::Config, level::Level};
+    ///
+    /// let config = Config::new()
+    ///     .with_target_level("parser", Level::Debug)
+    ///     .with_target_level("lexer", Level::Warn);
+    /// assert_eq!(config.target_level("parser"), Some(Level::Debug));
+    /// assert_eq!(config.target_level("codegen"), None);
+    /// ```
+    #[must_use]
+    pub fn with_target_level(mut self, target: impl Into<String>, level: Level) -> Self {
+        self.target_levels.insert(target.into(), level);
+        self
+    }
+
+    /// Returns the minimum level of the spans of `target`, or `None` if the target has no
+    /// override.
+    ///
+    /// # Parameters
+    /// - `target`: The target of the spans.
+    #[must_use]
+    pub fn target_level(&self, target: &str) -> Option<Level> {
+        self.target_levels.get(target).copied()
+    }
+
+    /// Hides the spans whose names do not pass `filter`, e.g. to focus on a part of a big trace.
+    ///
+    /// The filter is called with the name of every span that passes the level filter, so names
+    /// are formatted even for the spans that end up hidden.
+    ///
+    /// # Parameters
+    /// - `filter`: The predicate that returns `true` for the names of the spans to show.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let focus = String::from("fib");
+    /// let config = Config::new().with_name_filter(move |name| name.contains(focus.as_str()));
+    /// ```
+    #[must_use]
+    pub fn with_name_filter(self, filter: impl Fn(&str) -> bool + Send + Sync + 'static) -> Self {
+        Self {
+            name_filter: Some(NameFilter::new(filter)),
+            ..self
+        }
+    }
+
+    /// Returns the function that colors the vertical bars and the names, which is ignored when
+    /// `Config::color_map` colors whole lines.
+    pub(crate) fn guide_color(&self) -> Option<fn(usize) -> u8> {
+        self.color.filter(|_| self.color_map.is_none())
+    }
+
+    /// Checks whether a span named `name` passes `Config::name_filter`.
+    pub(crate) fn passes_name_filter(&self, name: core::fmt::Arguments) -> bool {
+        self.name_filter.as_ref().is_none_or(|filter| {
+            name.as_str().map_or_else(
+                || filter.matches(&name.to_string()),
+                |name| filter.matches(name),
+            )
+        })
+    }
+
+    /// Replaces the function that maps depth to a guide string, which takes precedence over
+    /// `Config::depthmap`. See `Config::depthmap_str` for how guides are fit into the tab width.
+    ///
+    /// # Parameters
+    /// - `depthmap_str`: A function to map depth to a guide string, or `None` to use
+    ///   `Config::depthmap` again.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new()
+    ///     .with_tabwidth(3)
+    ///     .with_depthmap_str(Some(|depth| if depth % 2 == 0 { "||" } else { "::" }));
+    /// ```
+    #[must_use]
+    pub fn with_depthmap_str(self, depthmap_str: Option<fn(usize) -> &'static str>) -> Self {
+        Self {
+            depthmap_str,
+            ..self
+        }
+    }
+
+    /// Checks the config for values that produce nonsensical output, e.g. a tab width wider than
+    /// any terminal or a name column left of the glyph column.
+    ///
+    /// Validation is opt-in: a config is never validated by `Spanner::with_config`, only by
+    /// `Config::validated` and `Spanner::with_writer_and_config`.
+    ///
+    /// # Errors
+    /// Returns the first problem found in the config.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::{Config, ConfigError};
+    ///
+    /// assert!(Config::new().validate().is_ok());
+    /// assert_eq!(
+    ///     Config::new().with_max_rate(Some(0)).validate(),
+    ///     Err(ConfigError::ZeroMaxRate)
+    /// );
+    /// ```
+    pub const fn validate(&self) -> Result<(), ConfigError> {
+        if self.tabwidth > MAX_TABWIDTH {
+            return Err(ConfigError::TabwidthTooLarge {
+                tabwidth: self.tabwidth,
+            });
+        }
+        if let (Some(name_column), Some(glyph_column)) = (self.name_column, self.glyph_column) {
+            if name_column <= glyph_column {
+                return Err(ConfigError::NameColumnBeforeGlyph {
+                    name_column,
+                    glyph_column,
+                });
+            }
+        }
+        if matches!(self.max_rate, Some(0)) {
+            return Err(ConfigError::ZeroMaxRate);
+        }
+        if matches!(self.sampling, Some(0)) {
+            return Err(ConfigError::ZeroSampling);
+        }
+        if self.skip > MAX_SKIP {
+            return Err(ConfigError::SkipTooLarge { skip: self.skip });
+        }
+        Ok(())
+    }
+
+    /// Validates the config with `Config::validate` and returns it, so that a config built with
+    /// the builder methods can fail fast.
+    ///
+    /// # Errors
+    /// Returns the first problem found in the config.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::{Config, ConfigError};
+    ///
+    /// let config = Config::new().with_skip(3).validated()?;
+    /// assert_eq!(
+    ///     Config::new().with_sampling(Some(0)).validated(),
+    ///     Err(ConfigError::ZeroSampling)
+    /// );
+    /// # Ok::<(), ConfigError>(())
+    /// ```
+    pub fn validated(self) -> Result<Self, ConfigError> {
+        self.validate().map(|()| self)
+    }
+
+    /// Replaces the top level separator value.
+    ///
+    /// # Parameters
+    /// - `top_level_separator`: Whether to separate top-level spans with blank lines.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_top_level_separator(true);
+    /// ```
+    #[must_use]
+    pub fn with_top_level_separator(self, top_level_separator: bool) -> Self {
+        Self {
+            top_level_separator,
+            ..self
+        }
+    }
+
+    /// Replaces the summary value.
+    ///
+    /// # Parameters
+    /// - `summary`: Whether to write a summary line when the spanner is dropped.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_summary(true);
+    /// ```
+    #[must_use]
+    pub fn with_summary(self, summary: bool) -> Self {
+        Self { summary, ..self }
+    }
+
+    /// Replaces the depth warning value.
+    ///
+    /// # Parameters
+    /// - `depth_warning`: The depth above which a warning is written, or `None` to disable it.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_depth_warning(Some(200));
+    /// ```
+    #[must_use]
+    pub fn with_depth_warning(self, depth_warning: Option<usize>) -> Self {
+        Self {
+            depth_warning,
+            ..self
+        }
+    }
+
+    /// Replaces the compact leaves value.
+    ///
+    /// # Parameters
+    /// - `compact_leaves`: Whether to render spans without children on a single line.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_compact_leaves(true);
+    /// ```
+    #[must_use]
+    pub fn with_compact_leaves(self, compact_leaves: bool) -> Self {
+        Self {
+            compact_leaves,
+            ..self
+        }
+    }
+
+    /// Replaces the leaf char value.
+    ///
+    /// # Parameters
+    /// - `leaf_char`: The new glyph of spans without children.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_leaf_char('-');
+    /// ```
+    #[must_use]
+    pub fn with_leaf_char(self, leaf_char: char) -> Self {
+        Self { leaf_char, ..self }
+    }
+
+    /// Replaces the timestamps value.
+    ///
+    /// # Parameters
+    /// - `timestamps`: The new timestamp mode.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::{Config, TimestampMode};
+    ///
+    /// let config = Config::new().with_timestamps(TimestampMode::Relative);
+    /// ```
+    #[must_use]
+    pub fn with_timestamps(self, timestamps: TimestampMode) -> Self {
+        Self { timestamps, ..self }
+    }
+
+    /// Replaces the collapse repeats value.
+    ///
+    /// # Parameters
+    /// - `collapse_repeats`: Whether to collapse consecutive sibling spans with the same name.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_collapse_repeats(true);
+    /// ```
+    #[must_use]
+    pub fn with_collapse_repeats(self, collapse_repeats: bool) -> Self {
+        Self {
+            collapse_repeats,
+            ..self
+        }
+    }
+
+    /// Replaces the color map value.
+    ///
+    /// # Parameters
+    /// - `color_map`: The function mapping depth to a color, or `None` to disable it.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::{Color, Config};
+    ///
+    /// let config = Config::new().with_color_map(Some(|depth| {
+    ///     if depth % 2 == 0 {
+    ///         Color::Blue
+    ///     } else {
+    ///         Color::Fixed(208)
+    ///     }
+    /// }));
+    /// ```
+    #[must_use]
+    pub fn with_color_map(self, color_map: Option<fn(usize) -> Color>) -> Self {
+        Self { color_map, ..self }
+    }
+
+    /// Enables colors only when they are appropriate, see `Config::auto_color`. If no colors are
+    /// configured, the colors of `Config::rainbow` are used.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// use spannify::{config::Config, core::StdoutSpanner};
+    ///
+    /// // Colored in a terminal, plain when piped into a file or when `NO_COLOR` is set
+    /// let spanner = StdoutSpanner::new().with_config(Config::new().with_auto_color());
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn with_auto_color(self) -> Self {
+        let color = match (self.color, self.color_map) {
+            (None, None) => Some(rainbow_color as fn(usize) -> u8),
+            (color, _) => color,
+        };
+        Self {
+            auto_color: true,
+            color,
+            ..self
+        }
+    }
+
+    /// Removes the colors if `Config::auto_color` is enabled and they are not appropriate, because
+    /// the output is not a terminal, as told by `terminal`, or `NO_COLOR` is set.
+    #[must_use]
+    pub(crate) fn resolve_auto_color(self, terminal: bool) -> Self {
+        #[cfg(feature = "std")]
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        #[cfg(not(feature = "std"))]
+        let no_color = false;
+        if !self.auto_color || (terminal && !no_color) {
+            return self;
+        }
+        Self {
+            color: None,
+            color_map: None,
+            ..self
+        }
+    }
+
+    /// Sets the string drawn at every depth, see `Config::indent_str`.
+    ///
+    /// # Parameters
+    /// - `indent_str`: The indentation of a single depth, including its guide character.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_indent_str("│ ");
+    /// ```
+    #[must_use]
+    pub fn with_indent_str(self, indent_str: &'static str) -> Self {
+        Self {
+            indent_str: Some(indent_str),
+            ..self
+        }
+    }
+
+    /// Replaces the tree connectors value.
+    ///
+    /// # Parameters
+    /// - `tree_connectors`: Whether to render spans as a tree with connectors.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_tree_connectors(true);
+    /// ```
+    #[must_use]
+    pub fn with_tree_connectors(self, tree_connectors: bool) -> Self {
+        Self {
+            tree_connectors,
+            ..self
+        }
+    }
+
+    /// Replaces the enter lines value.
+    ///
+    /// # Parameters
+    /// - `enter_lines`: Whether to write the enter lines of spans.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_enter_lines(false);
+    /// ```
+    #[must_use]
+    pub fn with_enter_lines(self, enter_lines: bool) -> Self {
+        Self {
+            enter_lines,
+            ..self
+        }
+    }
+
+    /// Replaces the exit lines value.
+    ///
+    /// # Parameters
+    /// - `exit_lines`: Whether to write the exit lines of spans.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_exit_lines(false);
+    /// ```
+    #[must_use]
+    pub fn with_exit_lines(self, exit_lines: bool) -> Self {
+        Self { exit_lines, ..self }
+    }
+
+    /// Replaces the newline value.
+    ///
+    /// # Parameters
+    /// - `newline`: The sequence that ends every written line.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_newline("\r\n");
+    /// ```
+    #[must_use]
+    pub fn with_newline(self, newline: &'static str) -> Self {
+        Self { newline, ..self }
+    }
+
+    /// Replaces the indent offset value.
+    ///
+    /// # Parameters
+    /// - `indent_offset`: The number of spaces prepended to every line.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_indent_offset(4);
+    /// ```
+    #[must_use]
+    pub fn with_indent_offset(self, indent_offset: usize) -> Self {
+        Self {
+            indent_offset,
+            ..self
+        }
+    }
+
+    /// Sets the string prepended to every line, see `Config::line_prefix`.
+    ///
+    /// # Parameters
+    /// - `line_prefix`: The prefix of every line.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::config::Config;
+    ///
+    /// let config = Config::new().with_line_prefix("SPAN | ");
+    /// ```
+    #[must_use]
+    pub fn with_line_prefix(self, line_prefix: &'static str) -> Self {
+        Self {
+            line_prefix: Some(line_prefix),
+            ..self
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            tabwidth: 2,
+            skip: 2,
+            depthmap: default_depthmap,
+            level: Level::Info,
+            time_window: None,
+            glyph_column: None,
+            max_rate: None,
+            sampling: None,
+            early_return_char: '↵',
+            format: Format::Tree,
+            thread_labels: false,
+            sanitize_names: false,
+            overview: false,
+            record_events: false,
+            fold_markers: None,
+            timing: false,
+            color: None,
+            max_depth: None,
+            enter_char: '┌',
+            exit_char: '└',
+            labels: None,
+            max_name_width: None,
+            record_tree: false,
+            collect_stats: false,
+            thread_local_depth: false,
+            name_column: None,
+            location: false,
+            target_levels: BTreeMap::new(),
+            name_filter: None,
+            depthmap_str: None,
+            top_level_separator: false,
+            summary: false,
+            depth_warning: None,
+            compact_leaves: false,
+            leaf_char: '─',
+            timestamps: TimestampMode::None,
+            collapse_repeats: false,
+            color_map: None,
+            auto_color: false,
+            indent_str: None,
+            tree_connectors: false,
+            enter_lines: true,
+            exit_lines: true,
+            newline: "\n",
+            indent_offset: 0,
+            line_prefix: None,
+        }
+    }
+}
+/// The environment variable read by `Config::from_env`
+#[cfg(feature = "std")]
+const LEVEL_ENV_VAR: &str = "SPANNIFY_LEVEL";
+
+/// Parses the value of the level environment variable, falling back to `Level::Info`.
+#[cfg(feature = "std")]
+fn level_from_env(value: Option<&str>) -> Level {
+    value
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(Level::Info)
+}
+
+/// Default function that maps depth to a vertical bar character.
+///
+/// Cycles through the characters `['|', '¦', '┆', '┊']` based on depth.
+const fn default_depthmap(depth: usize) -> char {
+    /// A list of vertical bars that we cycle through by default
+    const DEPTHMAP: [char; 4] = ['|', '¦', '┆', '┊'];
+    DEPTHMAP[depth % DEPTHMAP.len()]
+}
+
+/// The depthmap of `Theme::Ascii`.
+const fn ascii_depthmap(_depth: usize) -> char {
+    '|'
+}
+
+/// The depthmap of `Theme::Light` and `Theme::Rounded`.
+const fn light_depthmap(_depth: usize) -> char {
+    '│'
+}
+
+/// The depthmap of `Theme::Heavy`.
+const fn heavy_depthmap(_depth: usize) -> char {
+    '┃'
+}
+
+/// The depthmap of `Theme::Double`.
+const fn double_depthmap(_depth: usize) -> char {
+    '║'
+}
+
+/// Function that maps depth to a 256-color code used by `Config::rainbow`.
+const fn rainbow_color(depth: usize) -> u8 {
+    /// Red, orange, yellow, green, blue and purple 256-color codes
+    const COLORS: [u8; 6] = [196, 208, 226, 46, 33, 129];
+    COLORS[depth % COLORS.len()]
+}
+
+#[cfg(feature = "serde")]
+mod serialization {
+    use serde::{ser, Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::{default_depthmap, rainbow_color, Config, FoldStyle, Format, Theme, TimestampMode};
+    use crate::level::Level;
+    use alloc::borrow::ToOwned;
+    use alloc::boxed::Box;
+    use alloc::collections::BTreeMap;
+    use alloc::string::String;
+    use alloc::vec::Vec;
+    use core::time::Duration;
+
+    /// The named functions that a serialized `Config::depthmap` can refer to.
+    #[derive(Clone, Copy, Serialize, Deserialize, Default)]
+    #[serde(rename_all = "snake_case")]
+    enum DepthmapPreset {
+        #[default]
+        Default,
+        Ascii,
+        Light,
+        Heavy,
+        Double,
+    }
+
+    impl DepthmapPreset {
+        /// Every preset.
+        const ALL: [Self; 5] = [
+            Self::Default,
+            Self::Ascii,
+            Self::Light,
+            Self::Heavy,
+            Self::Double,
+        ];
+
+        /// Returns the function of the preset.
+        const fn function(self) -> fn(usize) -> char {
+            match self {
+                Self::Default => default_depthmap,
+                Self::Ascii => Theme::Ascii.depthmap(),
+                Self::Light => Theme::Light.depthmap(),
+                Self::Heavy => Theme::Heavy.depthmap(),
+                Self::Double => Theme::Double.depthmap(),
+            }
+        }
+    }
+
+    /// The named functions that a serialized `Config::color` can refer to.
+    #[derive(Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    enum ColorPreset {
+        Rainbow,
+    }
+
+    /// `FoldStyle` with owned markers.
+    #[derive(Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    enum FoldRepr {
+        Vim,
+        Custom { open: String, close: String },
+    }
+
+    /// The serialized form of `Config`.
+    #[derive(Serialize, Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    // Mirrors the flags of `Config` one to one, so that they serialize as plain booleans
+    #[allow(clippy::struct_excessive_bools)]
+    struct ConfigRepr {
+        tabwidth: usize,
+        skip: usize,
+        depthmap: DepthmapPreset,
+        level: Level,
+        time_window: Option<Duration>,
+        glyph_column: Option<usize>,
+        max_rate: Option<u32>,
+        sampling: Option<u32>,
+        early_return_char: char,
+        format: Format,
+        thread_labels: bool,
+        sanitize_names: bool,
+        overview: bool,
+        record_events: bool,
+        fold_markers: Option<FoldRepr>,
+        timing: bool,
+        color: Option<ColorPreset>,
+        max_depth: Option<usize>,
+        enter_char: char,
+        exit_char: char,
+        labels: Option<(String, String)>,
+        max_name_width: Option<usize>,
+        record_tree: bool,
+        collect_stats: bool,
+        thread_local_depth: bool,
+        name_column: Option<usize>,
+        location: bool,
+        top_level_separator: bool,
+        summary: bool,
+        depth_warning: Option<usize>,
+        compact_leaves: bool,
+        leaf_char: char,
+        timestamps: TimestampMode,
+        collapse_repeats: bool,
+        auto_color: bool,
+        indent_str: Option<String>,
+        tree_connectors: bool,
+        enter_lines: bool,
+        exit_lines: bool,
+        newline: String,
+        indent_offset: usize,
+        line_prefix: Option<String>,
+        target_levels: BTreeMap<String, Level>,
+    }
+
+    impl Default for ConfigRepr {
+        fn default() -> Self {
+            Self::from_config(&Config::default()).unwrap_or_else(|_| unreachable!())
+        }
+    }
+
+    impl ConfigRepr {
+        /// Converts a config into its serialized form, failing if it holds custom functions.
+        fn from_config(cfg: &Config) -> Result<Self, &'static str> {
+            let Some(depthmap) = DepthmapPreset::ALL
+                .into_iter()
+                .find(|preset| core::ptr::fn_addr_eq(cfg.depthmap, preset.function()))
+            else {
+                return Err("a custom depthmap can not be serialized");
+            };
+            let color = match cfg.color {
+                None => None,
+                Some(color) if core::ptr::fn_addr_eq(color, rainbow_color as fn(usize) -> u8) => {
+                    Some(ColorPreset::Rainbow)
+                }
+                Some(_) => return Err("a custom color function can not be serialized"),
+            };
+            if cfg.depthmap_str.is_some() {
+                return Err("a custom depthmap can not be serialized");
+            }
+            if cfg.name_filter.is_some() {
+                return Err("a name filter can not be serialized");
+            }
+            if cfg.color_map.is_some() {
+                return Err("a custom color map can not be serialized");
+            }
+            Ok(Self {
+                tabwidth: cfg.tabwidth,
+                skip: cfg.skip,
+                depthmap,
+                level: cfg.level,
+                time_window: cfg.time_window,
+                glyph_column: cfg.glyph_column,
+                max_rate: cfg.max_rate,
+                sampling: cfg.sampling,
+                early_return_char: cfg.early_return_char,
+                format: cfg.format,
+                thread_labels: cfg.thread_labels,
+                sanitize_names: cfg.sanitize_names,
+                overview: cfg.overview,
+                record_events: cfg.record_events,
+                fold_markers: cfg.fold_markers.map(|style| match style {
+                    FoldStyle::Vim => FoldRepr::Vim,
+                    FoldStyle::Custom { open, close } => FoldRepr::Custom {
+                        open: open.to_owned(),
+                        close: close.to_owned(),
+                    },
+                }),
+                timing: cfg.timing,
+                color,
+                max_depth: cfg.max_depth,
+                enter_char: cfg.enter_char,
+                exit_char: cfg.exit_char,
+                labels: cfg
+                    .labels
+                    .map(|(enter, exit)| (enter.to_owned(), exit.to_owned())),
+                max_name_width: cfg.max_name_width,
+                record_tree: cfg.record_tree,
+                collect_stats: cfg.collect_stats,
+                thread_local_depth: cfg.thread_local_depth,
+                name_column: cfg.name_column,
+                location: cfg.location,
+                top_level_separator: cfg.top_level_separator,
+                summary: cfg.summary,
+                depth_warning: cfg.depth_warning,
+                compact_leaves: cfg.compact_leaves,
+                leaf_char: cfg.leaf_char,
+                timestamps: cfg.timestamps,
+                collapse_repeats: cfg.collapse_repeats,
+                auto_color: cfg.auto_color,
+                indent_str: cfg.indent_str.map(ToOwned::to_owned),
+                tree_connectors: cfg.tree_connectors,
+                enter_lines: cfg.enter_lines,
+                exit_lines: cfg.exit_lines,
+                newline: cfg.newline.to_owned(),
+                indent_offset: cfg.indent_offset,
+                line_prefix: cfg.line_prefix.map(ToOwned::to_owned),
+                target_levels: cfg.target_levels.clone(),
+            })
+        }
+
+        /// Converts the serialized form back into a config, leaking the strings it holds as
+        /// `&'static str`s.
+        fn into_config(self) -> Config {
+            let leak = |s: String| -> &'static str { Box::leak(s.into_boxed_str()) };
+            Config {
+                tabwidth: self.tabwidth,
+                skip: self.skip,
+                depthmap: self.depthmap.function(),
+                level: self.level,
+                time_window: self.time_window,
+                glyph_column: self.glyph_column,
+                max_rate: self.max_rate,
+                sampling: self.sampling,
+                early_return_char: self.early_return_char,
+                format: self.format,
+                thread_labels: self.thread_labels,
+                sanitize_names: self.sanitize_names,
+                overview: self.overview,
+                record_events: self.record_events,
+                fold_markers: self.fold_markers.map(|style| match style {
+                    FoldRepr::Vim => FoldStyle::Vim,
+                    FoldRepr::Custom { open, close } => FoldStyle::Custom {
+                        open: leak(open),
+                        close: leak(close),
+                    },
+                }),
+                timing: self.timing,
+                color: self.color.map(|color| match color {
+                    ColorPreset::Rainbow => rainbow_color as fn(usize) -> u8,
+                }),
+                max_depth: self.max_depth,
+                enter_char: self.enter_char,
+                exit_char: self.exit_char,
+                labels: self.labels.map(|(enter, exit)| (leak(enter), leak(exit))),
+                max_name_width: self.max_name_width,
+                record_tree: self.record_tree,
+                collect_stats: self.collect_stats,
+                thread_local_depth: self.thread_local_depth,
+                name_column: self.name_column,
+                location: self.location,
+                top_level_separator: self.top_level_separator,
+                summary: self.summary,
+                depth_warning: self.depth_warning,
+                compact_leaves: self.compact_leaves,
+                leaf_char: self.leaf_char,
+                timestamps: self.timestamps,
+                collapse_repeats: self.collapse_repeats,
+                auto_color: self.auto_color,
+                indent_str: self.indent_str.map(leak),
+                tree_connectors: self.tree_connectors,
+                enter_lines: self.enter_lines,
+                exit_lines: self.exit_lines,
+                newline: leak(self.newline),
+                indent_offset: self.indent_offset,
+                line_prefix: self.line_prefix.map(leak),
+                target_levels: self.target_levels,
+                name_filter: None,
+                color_map: None,
+                depthmap_str: None,
+            }
+        }
+    }
+
+    impl Serialize for Config {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            ConfigRepr::from_config(self)
+                .map_err(ser::Error::custom)?
+                .serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Config {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            ConfigRepr::deserialize(deserializer).map(ConfigRepr::into_config)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn from_toml() {
+            let cfg: Config = toml::from_str(
+                r#"
+                skip = 3
+                level = "debug"
+                format = "json_lines"
+                color = "rainbow"
+                labels = ["in", "out"]
+                fold_markers = { custom = { open = "[[", close = "]]" } }
+                target_levels = { parser = "trace", lexer = "warn" }
+                "#,
+            )
+            .unwrap();
+            let expected = Config::rainbow()
+                .with_skip(3)
+                .with_level(Level::Debug)
+                .with_format(Format::JsonLines)
+                .with_labels(Some(("in", "out")))
+                .with_fold_markers(Some(FoldStyle::Custom {
+                    open: "[[",
+                    close: "]]",
+                }));
+            assert_eq!(cfg.skip, expected.skip);
+            assert_eq!(cfg.level, expected.level);
+            assert_eq!(cfg.format, expected.format);
+            assert_eq!(cfg.labels, expected.labels);
+            assert_eq!(cfg.fold_markers, expected.fold_markers);
+            assert!(cfg.color.is_some());
+            assert_eq!(cfg.target_level("parser"), Some(Level::Trace));
+            assert_eq!(cfg.target_level("lexer"), Some(Level::Warn));
+            assert_eq!(cfg.tabwidth, Config::default().tabwidth);
+
+            assert!(toml::from_str::<Config>("skipp = 3").is_err());
+            assert!(toml::from_str::<Config>("level = \"loud\"").is_err());
+        }
+
+        #[test]
+        fn round_trip() {
+            let cfg = Config::rainbow()
+                .with_theme(Theme::Heavy)
+                .with_time_window(Some(Duration::from_millis(1500)))
+                .with_max_depth(Some(8))
+                .with_fold_markers(Some(FoldStyle::Vim));
+            let serialized = toml::to_string(&cfg).unwrap();
+            let deserialized: Config = toml::from_str(&serialized).unwrap();
+            assert_eq!(toml::to_string(&deserialized).unwrap(), serialized);
+            assert_eq!(deserialized.time_window, cfg.time_window);
+            assert_eq!(deserialized.max_depth, cfg.max_depth);
+            assert_eq!((deserialized.depthmap)(3), '┃');
+
+            let custom = Config::new().with_color(Some(|_| 1));
+            assert!(toml::to_string(&custom).is_err());
+            let filtered = Config::new().with_name_filter(|name| name.contains("fib"));
+            assert!(toml::to_string(&filtered).is_err());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate() {
+        assert_eq!(Config::new().validate(), Ok(()));
+        assert_eq!(Config::new().with_tabwidth(MAX_TABWIDTH).validate(), Ok(()));
+        assert_eq!(
+            Config::new().with_tabwidth(MAX_TABWIDTH + 1).validate(),
+            Err(ConfigError::TabwidthTooLarge {
+                tabwidth: MAX_TABWIDTH + 1
+            })
+        );
+        assert_eq!(
+            Config::new()
+                .with_glyph_column(Some(4))
+                .with_name_column(Some(4))
+                .validate(),
+            Err(ConfigError::NameColumnBeforeGlyph {
+                name_column: 4,
+                glyph_column: 4
+            })
+        );
+        assert_eq!(
+            Config::new().with_max_rate(Some(0)).validate(),
+            Err(ConfigError::ZeroMaxRate)
+        );
+        assert_eq!(
+            Config::new().with_sampling(Some(0)).validate(),
+            Err(ConfigError::ZeroSampling)
+        );
+        assert_eq!(Config::new().with_tabwidth(0).validate(), Ok(()));
+        assert_eq!(Config::new().with_skip(MAX_SKIP).validate(), Ok(()));
+        assert_eq!(
+            Config::new().with_skip(MAX_SKIP + 1).validate(),
+            Err(ConfigError::SkipTooLarge { skip: MAX_SKIP + 1 })
+        );
+        assert_eq!(
+            Config::new().with_max_rate(Some(0)).validated(),
+            Err(ConfigError::ZeroMaxRate)
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn level_from_env_values() {
+        assert_eq!(level_from_env(Some("trace")), Level::Trace);
+        assert_eq!(level_from_env(Some("  Error ")), Level::Error);
+        assert_eq!(level_from_env(Some("loud")), Level::Info);
+        assert_eq!(level_from_env(Some("")), Level::Info);
+        assert_eq!(level_from_env(None), Level::Info);
+    }
 }
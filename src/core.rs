@@ -3,33 +3,110 @@
 //! This module provides functionality for generating spans and keeping track of the span depth.
 //! It includes the `Spanner` struct for managing span creation and the `Span` struct for representing individual spans.
 
-use std::fmt::Arguments;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Mutex;
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Arguments, Write as _};
+use std::io::IsTerminal;
+#[cfg(feature = "disabled")]
+use std::marker::PhantomData;
+use std::panic::Location;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use crate::config::Config;
+use crate::config::{Config, ConfigError, Format, TimestampMode};
 use crate::level::Level;
+use crate::render::{
+    csv_escape, json_escape, render_message, translate_newlines, with_message, Message, RenderState,
+};
+use crate::writer::{FmtWriter, SpanOutput};
+#[cfg(feature = "log")]
+use crate::writer::{LineLevel, LogWriter};
 
 /// A structure that generates spans and keeps track of the span depth.
 ///
 /// Note that `Spanner` is thread-safe, not because it produces coherent traces,
 /// but to make using it as a global mutable variable less cumbersome.
 /// If you want to use `Spanner` in multithreaded environments, you should consider
-/// creating a separate instance for each thread
+/// creating a separate instance for each thread. When a `Spanner` is shared anyway, enabling
+/// `Config::thread_labels` tags every line with the emitting thread so that the output can be
+/// filtered per thread, and `Config::thread_local_depth` indents every line by the depth of the
+/// emitting thread.
+///
+/// Every line is written with a single `write_all` call while the writer is locked, so lines are
+/// never torn apart, even when several spanners share a writer that locks on every write, such as
+/// `std::io::Stdout`.
+///
+/// `Spanner` implements `Drop` to write the summary of `Config::summary`, so a spanner created
+/// from a borrowed writer, e.g. `Spanner::from_writer(&mut writer)`, borrows the writer until the
+/// spanner is dropped. Use `Spanner::into_inner` to get the writer back.
 
 #[derive(Debug)]
 pub struct Spanner<T>
 where
-    T: std::io::Write,
+    T: SpanOutput,
 {
-    writer: Mutex<T>,
+    /// The writer, which is only `None` once it was taken by `Spanner::into_inner`.
+    writer: Mutex<Option<T>>,
+    id: usize,
     depth: AtomicUsize,
     config: Config,
+    window: Mutex<VecDeque<(Instant, String)>>,
+    deferred: Mutex<Vec<String>>,
+    /// The number of buffers in `deferred`, so that lines only lock it while a deferred span is
+    /// open.
+    deferred_open: AtomicUsize,
+    rate: Mutex<(Instant, u32)>,
+    overview: Mutex<Vec<(usize, usize)>>,
+    sequence: Mutex<Option<(Vec<String>, Vec<String>)>>,
+    /// Whether `Spanner::expect_sequence` is recording, so that spans only lock `sequence` then.
+    sequence_armed: AtomicBool,
+    events: Mutex<Vec<Event>>,
+    truncation_marked: AtomicBool,
+    /// The number of entered spans, for `Config::summary`, and the maximum depth they reached.
+    span_count: AtomicUsize,
+    max_depth: AtomicUsize,
+    /// Whether the summary of `Config::summary` has been written.
+    summarized: AtomicBool,
+    /// Whether the warning of `Config::depth_warning` has been written for the current crossing.
+    depth_warned: AtomicBool,
+    /// The enter message of the last entered span and its depth, held back by
+    /// `Config::compact_leaves` until it is known whether the span has children.
+    pending: Mutex<Option<(usize, String)>>,
+    /// The base of `TimestampMode::Relative`.
+    created: Instant,
+    /// The exit message of the last dropped span, held back by `Config::collapse_repeats`.
+    repeat: Mutex<Option<Repeat>>,
+    /// The depth of the repeated span that is being collapsed, if any.
+    collapsed: Mutex<Option<usize>>,
+    /// Whether the writer is a terminal, for `Config::auto_color`.
+    terminal: bool,
+    /// The open spans of `Config::tree_connectors`, from the top-level span to the innermost.
+    connectors: Mutex<Vec<SpanNode>>,
+    /// The configs of the spans entered with `Spanner::enter_with_config`, from the outermost to
+    /// the innermost, along with the state that they render with.
+    overrides: Mutex<Vec<(Config, RenderState)>>,
+    /// The number of configs in `overrides`, so that lines only lock it while there are any.
+    override_count: AtomicUsize,
+    /// The config of `Spanner::from_writer_shared_config`, which is read on every enter.
+    shared_config: Option<Arc<RwLock<Config>>>,
+    /// Whether a span was entered at depth zero, for `Config::top_level_separator`.
+    top_level_entered: AtomicBool,
+    /// The number of top-level spans seen by `Config::sampling`, and the number of open spans of
+    /// the top-level span that was sampled out, if any.
+    sampled: AtomicUsize,
+    sampled_out: AtomicUsize,
+    level: AtomicU8,
+    error: Mutex<Option<std::io::ErrorKind>>,
+    render: Mutex<RenderState>,
+    line: Mutex<String>,
+    tree: Mutex<Vec<SpanNode>>,
+    stats: Mutex<HashMap<String, SpanStats>>,
 }
 
 impl<T> Spanner<T>
 where
-    T: std::io::Write,
+    T: SpanOutput,
 {
     /// Creates a new `Spanner` instance from a writer.
     ///
@@ -44,12 +121,104 @@ where
     /// ```
     pub fn from_writer(writer: T) -> Self {
         Self {
-            writer: Mutex::new(writer),
+            writer: Mutex::new(Some(writer)),
+            id: next_spanner_id(),
             depth: AtomicUsize::new(0),
             config: Config::default(),
+            window: Mutex::new(VecDeque::new()),
+            deferred: Mutex::new(Vec::new()),
+            deferred_open: AtomicUsize::new(0),
+            rate: Mutex::new((Instant::now(), 0)),
+            overview: Mutex::new(Vec::new()),
+            sequence: Mutex::new(None),
+            sequence_armed: AtomicBool::new(false),
+            events: Mutex::new(Vec::new()),
+            truncation_marked: AtomicBool::new(false),
+            span_count: AtomicUsize::new(0),
+            max_depth: AtomicUsize::new(0),
+            summarized: AtomicBool::new(false),
+            depth_warned: AtomicBool::new(false),
+            pending: Mutex::new(None),
+            created: Instant::now(),
+            repeat: Mutex::new(None),
+            collapsed: Mutex::new(None),
+            terminal: false,
+            connectors: Mutex::new(Vec::new()),
+            overrides: Mutex::new(Vec::new()),
+            override_count: AtomicUsize::new(0),
+            shared_config: None,
+            top_level_entered: AtomicBool::new(false),
+            sampled: AtomicUsize::new(0),
+            sampled_out: AtomicUsize::new(0),
+            level: AtomicU8::new(Config::default().level as u8),
+            error: Mutex::new(None),
+            render: Mutex::new(RenderState::default()),
+            line: Mutex::new(String::new()),
+            tree: Mutex::new(vec![SpanNode::default()]),
+            stats: Mutex::new(HashMap::new()),
         }
     }
 
+    /// Creates a new `Spanner` instance from a writer and a configuration, validating the
+    /// configuration first.
+    ///
+    /// # Parameters
+    /// - `writer`: The writer to which the spans will be written.
+    /// - `cfg`: The configuration to use.
+    ///
+    /// # Errors
+    /// Returns the error of `Config::validate` if the configuration is invalid.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::{config::Config, core::Spanner};
+    ///
+    /// let spanner = Spanner::with_writer_and_config(Vec::new(), Config::new().with_skip(3))?;
+    /// # Ok::<(), spannify::config::ConfigError>(())
+    /// ```
+    pub fn with_writer_and_config(writer: T, cfg: Config) -> Result<Self, ConfigError> {
+        cfg.validate()?;
+        Ok(Self::from_writer(writer).with_config(cfg))
+    }
+
+    /// Creates a new `Spanner` instance from a writer and a config that can be changed while the
+    /// spanner is in use, e.g. to reload the config of a running server.
+    ///
+    /// The config is read through the lock every time a span is entered. The span keeps a
+    /// snapshot of it, so that its enter and exit messages are always rendered with the same
+    /// config, and changes only apply to the spans entered afterwards.
+    ///
+    /// The level, the name filter and the options that `render::Formatter` renders lines with,
+    /// e.g. the tab width, the skip and the glyphs, are read live. The format and the other
+    /// options that the spanner handles itself, such as timing or compact leaves, are read once
+    /// when the spanner is created.
+    ///
+    /// # Parameters
+    /// - `writer`: The writer to which the spans will be written.
+    /// - `config`: The shared configuration to use.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::{config::Config, core::Spanner};
+    /// use std::sync::{Arc, RwLock};
+    ///
+    /// let config = Arc::new(RwLock::new(Config::new()));
+    /// let spanner = Spanner::from_writer_shared_config(Vec::new(), Arc::clone(&config));
+    ///
+    /// drop(spanner.enter_span("before"));
+    /// config.write().unwrap().skip = 4;
+    /// drop(spanner.enter_span("after"));
+    /// ```
+    pub fn from_writer_shared_config(writer: T, config: Arc<RwLock<Config>>) -> Self {
+        let cfg = config
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        let mut spanner = Self::from_writer(writer).with_config(cfg);
+        spanner.shared_config = Some(config);
+        spanner
+    }
+
     /// Enters a span with `Level::Info`, increasing the depth and writing the span's enter message.
     ///
     /// # Parameters
@@ -62,7 +231,8 @@ where
     /// let spanner = Spanner::from_writer(Vec::new());
     /// let span = spanner.enter_span("test");
     /// ```
-    pub fn enter_span(&self, name: &str) -> Span<T> {
+    #[track_caller]
+    pub fn enter_span(&self, name: &str) -> Span<'_, T> {
         self.enter_with_level(Level::Info, name)
     }
 
@@ -90,10 +260,12 @@ where
     ///     let _span = spanner.enter_with_level(Level::Debug, "foo");
     ///     // Span is dropped here
     /// }
+    /// drop(spanner);
     ///
     /// assert_eq!(writer.get_ref(), &[]);
     /// ```
-    pub fn enter_with_level(&self, level: Level, name: &str) -> Span<T> {
+    #[track_caller]
+    pub fn enter_with_level(&self, level: Level, name: &str) -> Span<'_, T> {
         Span::enter(self, level, name)
     }
 
@@ -101,505 +273,5296 @@ where
     /// as a name to avoid allocations where possible in spf! macro.
     ///
     /// It is not
-    pub fn enter_args(&self, level: Level, args: Arguments) -> Span<T> {
+    #[track_caller]
+    pub fn enter_args(&self, level: Level, args: Arguments) -> Span<'_, T> {
         Span::enter_args(self, level, args)
     }
 
-    /// Sets a custom configuration for the spanner.
+    /// Enters a span with `Level::Info` whose lines are rendered with `cfg` instead of the config
+    /// of the spanner, e.g. to render a single subtree with a different `Config::skip`.
+    ///
+    /// The override is inherited: until the span is dropped, every line of the spanner, i.e. the
+    /// lines of the span and of its descendants, is rendered with `cfg`, including the indentation
+    /// of the enclosing depths. Nested overrides replace each other, and the enclosing override
+    /// applies again once the inner span is dropped. As the override belongs to the spanner, spans
+    /// entered on other threads meanwhile are rendered with it as well.
+    ///
+    /// Only the options that `render::Formatter` renders lines with are overridden, e.g. the tab
+    /// width, the skip, the glyphs and the colors. The format and the options that the spanner
+    /// handles itself, such as the level, timing or compact leaves, are always taken from the
+    /// config of the spanner.
     ///
     /// # Parameters
-    /// - `cfg`: The new configuration to use.
+    /// - `name`: The name of the span. It is displayed is span's enter and exit message
+    /// - `cfg`: The config to render the span and its descendants with
     ///
     /// # Examples
     /// ```
-    /// use spannify::{config::Config, core::Spanner};
+    /// use spannify::{config::Config, core::VecSpanner};
     ///
-    /// let spanner = Spanner::from_writer(Vec::new()).with_config(Config::new().with_skip(3));
+    /// let spanner = VecSpanner::new();
+    /// {
+    ///     let _outer = spanner.enter_span("outer");
+    ///     let _inner = spanner.enter_with_config("inner", Config::new().with_tabwidth(4));
+    /// }
+    ///
+    /// # #[cfg(not(feature = "disabled"))]
+    /// assert_eq!(
+    ///     spanner.into_string().unwrap(),
+    ///     "┌outer\n|    inner\n|    inner\n└outer\n"
+    /// );
     /// ```
-    #[must_use]
-    pub fn with_config(self, cfg: Config) -> Self {
-        Self {
-            config: cfg,
-            ..self
-        }
-    }
-}
-
-/// A Spanner that writes to the Vec of bytes.
-pub type VecSpanner = Spanner<Vec<u8>>;
-
-impl VecSpanner {
-    /// Creates a `VecSpanner` instance with default values.
-    #[must_use]
-    pub fn new() -> Self {
-        Self::default()
+    #[track_caller]
+    pub fn enter_with_config(&self, name: &str, cfg: Config) -> Span<'_, T> {
+        Span::enter_overridden(self, name, cfg)
     }
 
-    /// Creates a new `VecSpanner` from an existing vector.
+    /// Does the same thing as `enter_with_level`, but builds the name with `name` only if the
+    /// span passes the level filter, so that no formatting is done for filtered spans.
     ///
     /// # Parameters
-    /// - `vec`: The vector to use as the writer.
+    /// - `level`: The level of the span. It determines if the span would be outputted or not
+    /// - `name`: The function that builds the name of the span
     ///
     /// # Examples
     /// ```
-    /// use spannify::core::VecSpanner;
+    /// use spannify::{core::VecSpanner, level::Level};
     ///
-    /// let spanner = VecSpanner::from_vec(Vec::new());
+    /// let spanner = VecSpanner::new();
+    /// let _span = spanner.enter_lazy(Level::Trace, || unreachable!("Trace spans are filtered"));
     /// ```
-    #[must_use]
-    pub fn from_vec(vec: Vec<u8>) -> Self {
-        Self {
-            writer: Mutex::new(vec),
-            depth: AtomicUsize::new(0),
-            config: Config::default(),
+    #[track_caller]
+    pub fn enter_lazy(&self, level: Level, name: impl FnOnce() -> String) -> Span<'_, T> {
+        if !self.is_enabled(level) {
+            return Span::enter_args(self, level, format_args!(""));
         }
+        let name = name();
+        Span::enter_args(self, level, format_args!("{name}"))
     }
-}
 
-impl Default for VecSpanner {
-    fn default() -> Self {
-        Self {
-            writer: Mutex::new(Vec::new()),
-            depth: AtomicUsize::new(0),
-            config: Config::default(),
-        }
+    /// Does the same thing as `enter_with_level`, but accepts any `Display` value as a name, e.g.
+    /// a `String`, an integer or an enum, without formatting it into an intermediate `String`.
+    ///
+    /// # Parameters
+    /// - `level`: The level of the span. It determines if the span would be outputted or not
+    /// - `name`: The name of the span. It is displayed is span's enter and exit message
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::{core::Spanner, level::Level};
+    /// use std::io::Cursor;
+    ///
+    /// let mut writer = Cursor::new(Vec::new());
+    /// let spanner = Spanner::from_writer(&mut writer);
+    ///
+    /// {
+    ///     let _span = spanner.enter_display(Level::Info, 42);
+    /// }
+    /// drop(spanner);
+    ///
+    /// # #[cfg(not(feature = "disabled"))]
+    /// assert_eq!(writer.get_ref(), "┌42\n└42\n".as_bytes());
+    /// ```
+    #[track_caller]
+    pub fn enter_display<N: std::fmt::Display>(&self, level: Level, name: N) -> Span<'_, T> {
+        Span::enter_args(self, level, format_args!("{name}"))
     }
-}
-
-/// A Spanner that writes to a File
-pub type FileSpanner = Spanner<std::fs::File>;
 
-impl FileSpanner {
-    /// Creates a new `FileSpanner` from an existing file.
+    /// Does the same thing as `enter_with_level`, but the span belongs to `target`, e.g. the
+    /// subsystem it comes from, so that its level is compared against the override of the target
+    /// in `Config::target_levels` instead of the level of the spanner, if there is one.
     ///
     /// # Parameters
-    /// - `file`: The file to use as the writer.
+    /// - `target`: The target of the span. It determines which minimum level applies
+    /// - `level`: The level of the span. It determines if the span would be outputted or not
+    /// - `name`: The name of the span. It is displayed is span's enter and exit message
     ///
     /// # Examples
     /// ```
-    /// use spannify::core::FileSpanner;
+    /// use spannify::{config::Config, core::VecSpanner, level::Level};
     ///
-    /// let file = std::fs::File::create("/tmp/output.txt").unwrap();
-    /// let spanner = FileSpanner::new(file);
+    /// let spanner = VecSpanner::new().with_config(
+    ///     Config::new()
+    ///         .with_target_level("parser", Level::Debug)
+    ///         .with_target_level("lexer", Level::Warn),
+    /// );
+    /// drop(spanner.enter_target("parser", Level::Debug, "parse"));
+    /// drop(spanner.enter_target("lexer", Level::Info, "lex"));
+    /// # #[cfg(not(feature = "disabled"))]
+    /// assert_eq!(spanner.into_string().unwrap(), "┌parse\n└parse\n");
     /// ```
-    #[must_use]
-    pub fn new(file: std::fs::File) -> Self {
-        Self {
-            writer: Mutex::new(file),
-            depth: AtomicUsize::new(0),
-            config: Config::default(),
-        }
+    #[track_caller]
+    pub fn enter_target(&self, target: &str, level: Level, name: &str) -> Span<'_, T> {
+        let enabled = !cfg!(feature = "disabled")
+            && self
+                .config
+                .target_level(target)
+                .unwrap_or_else(|| self.level())
+                <= level;
+        Span::enter_inner(self, level, enabled, format_args!("{name}"), false)
     }
-}
-
-/// A Spanner that writes to the standard out.
-pub type StdoutSpanner = Spanner<std::io::Stdout>;
 
-impl StdoutSpanner {
-    /// Creates a `StdoutSpanner` instance with default values.
-    #[must_use]
-    pub fn new() -> Self {
-        Self::default()
+    /// Runs `f` inside a span with `Level::Info` and returns its value.
+    ///
+    /// The span is exited right after `f` returns. It is also exited if `f` panics, since the
+    /// span guard is dropped while unwinding.
+    ///
+    /// # Parameters
+    /// - `name`: The name of the span. It is displayed is span's enter and exit message
+    /// - `f`: The closure to run inside the span.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::core::Spanner;
+    /// use std::io::Cursor;
+    ///
+    /// let mut writer = Cursor::new(Vec::new());
+    /// let spanner = Spanner::from_writer(&mut writer);
+    ///
+    /// let n = spanner.in_span("compute", || 2 + 2);
+    ///
+    /// assert_eq!(n, 4);
+    /// drop(spanner);
+    /// # #[cfg(not(feature = "disabled"))]
+    /// assert_eq!(writer.get_ref(), "┌compute\n└compute\n".as_bytes());
+    /// ```
+    #[track_caller]
+    pub fn in_span<R>(&self, name: &str, f: impl FnOnce() -> R) -> R {
+        self.in_span_with_level(Level::Info, name, f)
     }
-}
 
-impl Default for StdoutSpanner {
-    fn default() -> Self {
-        Self {
-            writer: Mutex::new(std::io::stdout()),
-            depth: AtomicUsize::new(0),
-            config: Config::default(),
-        }
+    /// Does the same thing as `in_span`, but enters the span with `level`.
+    ///
+    /// # Parameters
+    /// - `level`: The level of the span. It determines if the span would be outputted or not
+    /// - `name`: The name of the span. It is displayed is span's enter and exit message
+    /// - `f`: The closure to run inside the span.
+    #[track_caller]
+    pub fn in_span_with_level<R>(&self, level: Level, name: &str, f: impl FnOnce() -> R) -> R {
+        let _span = self.enter_with_level(level, name);
+        f()
     }
-}
-
-/// A `Span` represents a hierarchical structure for tracking and displaying the entry and
-/// exit of various sections of code.
-///
-/// It uses a provided writer to output messages on entering
-/// and dropping spans, facilitating visual traceability of the execution flow.
-///
-/// The `Span` structure works in tandem with a `Spanner` instance, which maintains the
-/// configuration and depth state. Each span generates formatted messages based on the
-/// current depth and configuration, which are written to the provided writer.
-#[derive(Clone, Debug)]
-pub struct Span<'a, T>
-where
-    T: std::io::Write,
-{
-    parent: &'a Spanner<T>,
-    drop_message: String,
-    level: Level,
-}
 
-impl<'a, T> Span<'a, T>
-where
-    T: std::io::Write,
-{
-    /// Creates a new `Span` for a given `parent` `Spanner` and a span `name`. This method
-    /// increases the depth of the parent spanner, generates entry and drop messages, writes
-    /// the entry message to the writer, and returns the new `Span` instance.
+    /// Enters a span whose output, including the output of all of its descendants, is deferred
+    /// into a local buffer and written to the writer in one piece when the span is dropped.
+    ///
+    /// Deferred spans may be nested: an inner deferred span splices its buffer into the
+    /// enclosing one, so the final output is identical to the output of regular spans.
     ///
     /// # Parameters
+    /// - `level`: The level of the span. It determines if the span would be outputted or not
+    /// - `name`: The name of the span. It is displayed is span's enter and exit message
     ///
-    /// - `parent`: A reference to the `Spanner` instance managing the configuration and depth.
-    /// - `level`: The level of the span.
-    /// - `name`: The name of the span, which will be included in the messages.
-    fn enter(parent: &'a Spanner<T>, level: Level, name: &str) -> Self {
-        Self::enter_args(parent, level, format_args!("{name}"))
+    /// # Examples
+    /// ```
+    /// use spannify::{core::Spanner, level::Level};
+    /// use std::io::Cursor;
+    ///
+    /// let mut writer = Cursor::new(Vec::new());
+    /// let spanner = Spanner::from_writer(&mut writer);
+    ///
+    /// {
+    ///     let _span = spanner.enter_deferred(Level::Info, "batch");
+    ///     let _child = spanner.enter_span("item");
+    ///     // Nothing has been written yet
+    /// }
+    /// drop(spanner);
+    ///
+    /// # #[cfg(not(feature = "disabled"))]
+    /// assert_eq!(writer.get_ref(), "┌batch\n|  item\n|  item\n└batch\n".as_bytes());
+    /// ```
+    #[track_caller]
+    pub fn enter_deferred(&self, level: Level, name: &str) -> Span<'_, T> {
+        Span::enter_deferred(self, level, format_args!("{name}"))
     }
 
-    fn enter_args(parent: &'a Spanner<T>, level: Level, args: Arguments) -> Self {
-        let mut drop_message = String::new();
-        if parent.config.level <= level {
-            let prev_depth = parent.depth.fetch_add(1, Ordering::Relaxed);
-            let (enter_message, drop_msg) =
-                Self::generate_messages(args, prev_depth, &parent.config);
-            drop_message = drop_msg;
-
-            if let Ok(mut writer) = parent.writer.lock() {
-                let _ = writer.write(enter_message.as_ref());
-            }
-        }
-        Self {
-            parent,
-            drop_message,
-            level,
+    /// Creates a `SpanBuilder` that collects key/value fields before entering a span named
+    /// `name` with `Level::Info`.
+    ///
+    /// # Parameters
+    /// - `name`: The name of the span. It is displayed is span's enter and exit message
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::core::Spanner;
+    /// use std::io::Cursor;
+    ///
+    /// let mut writer = Cursor::new(Vec::new());
+    /// let spanner = Spanner::from_writer(&mut writer);
+    ///
+    /// {
+    ///     let _span = spanner.span_builder("query").with_field("rows", 42).enter();
+    /// }
+    /// drop(spanner);
+    ///
+    /// # #[cfg(not(feature = "disabled"))]
+    /// assert_eq!(writer.get_ref(), "┌query {rows=42}\n└query {rows=42}\n".as_bytes());
+    /// ```
+    pub const fn span_builder<'n>(&self, name: &'n str) -> SpanBuilder<'_, 'n, T> {
+        SpanBuilder {
+            parent: self,
+            name,
+            level: Level::Info,
+            fields: String::new(),
         }
     }
-    /// Generates the entry and drop messages for a span based on its name, depth, and configuration.
+
+    /// Sets a custom configuration for the spanner.
     ///
     /// # Parameters
+    /// - `cfg`: The new configuration to use.
     ///
-    /// - `name`: The name of the span.
-    /// - `depth`: The current depth of the span.
-    /// - `cfg`: The configuration for formatting the messages.
-    ///
-    fn generate_messages(name: Arguments, depth: usize, cfg: &Config) -> (String, String) {
-        let spaces: String = (0..depth).enumerate().fold(
-            String::with_capacity(depth * cfg.tabwidth),
-            |mut acc, (i, _)| {
-                let is_displayed = match cfg.skip {
-                    0 => false,
-                    _ => i % cfg.skip == 0,
-                };
-                if is_displayed {
-                    acc.push((cfg.depthmap)(i));
-                } else {
-                    acc.push(' ');
-                }
-                for _ in 0..cfg.tabwidth.saturating_sub(1) {
-                    acc.push(' ');
-                }
-                acc
-            },
-        );
-        let is_displayed = match cfg.skip {
-            0 => false,
-            _ => depth % cfg.skip == 0,
-        };
+    /// # Examples
+    /// ```
+    /// use spannify::{config::Config, core::Spanner};
+    ///
+    /// let spanner = Spanner::from_writer(Vec::new()).with_config(Config::new().with_skip(3));
+    /// ```
+    #[must_use]
+    pub fn with_config(mut self, cfg: Config) -> Self {
+        // `Spanner` implements `Drop`, so the fields are replaced instead of moved into a new one
+        self.level = AtomicU8::new(cfg.level as u8);
+        self.render = Mutex::new(RenderState::default());
+        self.config = cfg.resolve_auto_color(self.terminal);
+        self
+    }
 
-        let enter_message = format!(
-            "{}{}{}\n",
-            spaces,
-            if is_displayed { '┌' } else { ' ' },
-            name
-        );
-        let drop_message = format!(
-            "{}{}{}\n",
-            spaces,
-            if is_displayed { '└' } else { ' ' },
-            name
-        );
-        (enter_message, drop_message)
+    /// Wraps the spanner in an `Arc` so that it can be shared between threads.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::{core::VecSpanner, spf};
+    ///
+    /// let spanner = VecSpanner::new().shared();
+    /// let handle = {
+    ///     let spanner = spanner.clone();
+    ///     std::thread::spawn(move || {
+    ///         let _span = spf!(spanner, "worker");
+    ///     })
+    /// };
+    /// handle.join().unwrap();
+    /// ```
+    #[must_use]
+    pub fn shared(self) -> SharedSpanner<T> {
+        std::sync::Arc::new(self)
     }
-}
 
-/// Implements the `Drop` trait for the `Span` struct, ensuring that the drop message is
-/// written to the writer and the parent's depth is decremented when the span goes out of scope.
-impl<T> Drop for Span<'_, T>
-where
-    T: std::io::Write,
-{
-    /// Writes the drop message to the writer and decrements the parent's depth.
-    fn drop(&mut self) {
-        if self.parent.config.level < self.level {
-            return;
-        }
-        let _ = self.parent.depth.fetch_sub(1, Ordering::Relaxed);
+    /// Returns the current minimum level of the spans.
+    #[must_use]
+    pub fn level(&self) -> Level {
+        self.shared_config.as_ref().map_or_else(
+            || Level::from_u8(self.level.load(Ordering::Relaxed)),
+            |config| config.read().unwrap_or_else(PoisonError::into_inner).level,
+        )
+    }
 
-        if let Ok(mut writer) = self.parent.writer.lock() {
-            let _ = writer.write(self.drop_message.as_ref());
+    /// Changes the minimum level of the spans at runtime, overriding `Config::level`. The level
+    /// of a config shared with `Spanner::from_writer_shared_config` is changed in place.
+    ///
+    /// Spans that are already entered are not affected: whether a span writes its exit message
+    /// is decided by the level that was current when it was entered.
+    ///
+    /// # Parameters
+    /// - `level`: The new minimum level for the spans.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::{core::Spanner, level::Level};
+    /// use std::io::Cursor;
+    ///
+    /// let mut writer = Cursor::new(Vec::new());
+    /// let spanner = Spanner::from_writer(&mut writer);
+    ///
+    /// spanner.set_level(Level::Warn);
+    /// drop(spanner.enter_with_level(Level::Info, "ignored"));
+    /// assert_eq!(spanner.level(), Level::Warn);
+    /// drop(spanner);
+    ///
+    /// assert_eq!(writer.get_ref(), &[]);
+    /// ```
+    pub fn set_level(&self, level: Level) {
+        match &self.shared_config {
+            Some(config) => config.write().unwrap_or_else(PoisonError::into_inner).level = level,
+            None => self.level.store(level as u8, Ordering::Relaxed),
         }
     }
-}
 
-/// Creates a new span with a given spanner.
-///
-/// This macro is used to create and enter a new span.
-/// You can specify the level of span if needed.
-///
-/// # Examples
+    /// Returns the current depth, i.e. the number of spans that are currently entered, or the
+    /// number of spans that are currently entered on the calling thread if
+    /// `Config::thread_local_depth` is enabled.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::core::VecSpanner;
+    ///
+    /// let spanner = VecSpanner::new();
+    /// assert_eq!(spanner.depth(), 0);
+    /// {
+    ///     let _span = spanner.enter_span("work");
+    ///     # #[cfg(not(feature = "disabled"))]
+    ///     assert_eq!(spanner.depth(), 1);
+    /// }
+    /// assert_eq!(spanner.depth(), 0);
+    /// ```
+    #[must_use]
+    pub fn depth(&self) -> usize {
+        if self.config.thread_local_depth {
+            return THREAD_DEPTHS.with_borrow(|depths| depths.get(&self.id).copied().unwrap_or(0));
+        }
+        self.depth.load(Ordering::Relaxed)
+    }
+
+    /// Returns the maximum depth reached so far, i.e. the largest number of spans that were
+    /// entered at the same time, e.g. to detect runaway recursion. With
+    /// `Config::thread_local_depth` it is the maximum over all threads. Spans that are filtered
+    /// out by their level, name or rate do not count, spans truncated by `Config::max_depth` do.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::{core::VecSpanner, spf};
+    ///
+    /// fn fib(spanner: &VecSpanner, n: usize) -> usize {
+    ///     let _span = spf!(spanner, "fib({n})");
+    ///     match n {
+    ///         0 | 1 => n,
+    ///         _ => fib(spanner, n - 1) + fib(spanner, n - 2),
+    ///     }
+    /// }
+    ///
+    /// let spanner = VecSpanner::new();
+    /// assert_eq!(spanner.max_depth(), 0);
+    /// fib(&spanner, 5);
+    /// // fib(5) -> fib(4) -> fib(3) -> fib(2) -> fib(1)
+    /// # #[cfg(not(feature = "disabled"))]
+    /// assert_eq!(spanner.max_depth(), 5);
+    /// assert_eq!(spanner.depth(), 0);
+    /// ```
+    #[must_use]
+    pub fn max_depth(&self) -> usize {
+        self.max_depth.load(Ordering::Relaxed)
+    }
+
+    /// Resets the spanner for a new, independent trace: the depth is set to zero and the state of
+    /// rendering, such as the open stacks of `Format::FoldedStacks` or whether the array of
+    /// `Format::ChromeTrace` was opened, is forgotten. Lines that are still held back, by
+    /// `Config::compact_leaves`, `Config::collapse_repeats`, deferred spans or
+    /// `Config::tree_connectors`, are discarded, as are the overrides of
+    /// `Spanner::enter_with_config`. Recorded data, such as the statistics, the tree of spans, in
+    /// which the open spans are closed, or the overview, and the output itself are kept.
+    ///
+    /// This is only valid when no span of the spanner is alive, e.g. after a trace was abandoned
+    /// by a panic. Dropping a span that was entered before the reset corrupts the depth. With
+    /// `Config::thread_local_depth`, only the depth of the calling thread is reset.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::core::VecSpanner;
+    ///
+    /// let spanner = VecSpanner::new();
+    /// std::mem::forget(spanner.enter_span("leaked"));
+    /// # #[cfg(not(feature = "disabled"))]
+    /// assert_eq!(spanner.depth(), 1);
+    ///
+    /// spanner.reset();
+    /// assert_eq!(spanner.depth(), 0);
+    /// ```
+    pub fn reset(&self) {
+        if self.config.thread_local_depth {
+            THREAD_DEPTHS.with_borrow_mut(|depths| depths.remove(&self.id));
+        }
+        self.depth.store(0, Ordering::Relaxed);
+        self.truncation_marked.store(false, Ordering::Relaxed);
+        self.depth_warned.store(false, Ordering::Relaxed);
+        self.top_level_entered.store(false, Ordering::Relaxed);
+        self.sampled.store(0, Ordering::Relaxed);
+        self.sampled_out.store(0, Ordering::Relaxed);
+        if let Ok(mut render) = self.render.lock() {
+            *render = RenderState::default();
+        }
+        if let Ok(mut pending) = self.pending.lock() {
+            *pending = None;
+        }
+        if let Ok(mut repeat) = self.repeat.lock() {
+            *repeat = None;
+        }
+        if let Ok(mut collapsed) = self.collapsed.lock() {
+            *collapsed = None;
+        }
+        if let Ok(mut deferred) = self.deferred.lock() {
+            deferred.clear();
+            self.deferred_open.store(0, Ordering::Relaxed);
+        }
+        if let Ok(mut connectors) = self.connectors.lock() {
+            connectors.clear();
+        }
+        if let Ok(mut overrides) = self.overrides.lock() {
+            overrides.clear();
+            self.override_count.store(0, Ordering::Relaxed);
+        }
+        if let Ok(mut tree) = self.tree.lock() {
+            while tree.len() > 1 {
+                if let (Some(node), Some(parent)) = (tree.pop(), tree.last_mut()) {
+                    parent.children.push(node);
+                }
+            }
+        }
+    }
+
+    /// Increases the depth, returning the depth before the increase.
+    fn increment_depth(&self) -> usize {
+        if self.config.thread_local_depth {
+            return THREAD_DEPTHS.with_borrow_mut(|depths| {
+                let depth = depths.entry(self.id).or_default();
+                *depth += 1;
+                *depth - 1
+            });
+        }
+        self.depth.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Decreases the depth, forgetting the depth of the calling thread once it drops to zero.
+    fn decrement_depth(&self) {
+        if !self.config.thread_local_depth {
+            let _ = self.depth.fetch_sub(1, Ordering::Relaxed);
+            return;
+        }
+        THREAD_DEPTHS.with_borrow_mut(|depths| {
+            if let Some(depth) = depths.get_mut(&self.id) {
+                *depth -= 1;
+                if *depth == 0 {
+                    depths.remove(&self.id);
+                }
+            }
+        });
+    }
+
+    /// Returns the kind of the first error that occurred while writing span lines, if any.
+    ///
+    /// Entering and dropping spans never fails, so check this to find out whether some of the
+    /// trace output was lost, e.g. because the disk is full.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::core::VecSpanner;
+    ///
+    /// let spanner = VecSpanner::new();
+    /// drop(spanner.enter_span("work"));
+    /// assert_eq!(spanner.last_error(), None);
+    /// ```
+    #[must_use]
+    pub fn last_error(&self) -> Option<std::io::ErrorKind> {
+        self.error.lock().ok().and_then(|error| *error)
+    }
+
+    /// Flushes the underlying writer.
+    ///
+    /// Entering and dropping spans never flushes implicitly, so call this to make sure the span
+    /// lines written so far reach buffered writers' destination, e.g. in long-running processes.
+    /// Lines held by open deferred spans are not written until those spans are dropped. The writer
+    /// is flushed even if a thread panicked while writing to it.
+    ///
+    /// # Errors
+    /// Returns the error of the underlying writer's `flush`.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::core::Spanner;
+    /// use std::io::BufWriter;
+    ///
+    /// let spanner = Spanner::from_writer(BufWriter::new(Vec::new()));
+    /// drop(spanner.enter_span("work"));
+    /// spanner.flush().unwrap();
+    /// ```
+    pub fn flush(&self) -> std::io::Result<()> {
+        self.writer
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_mut()
+            .map_or(Ok(()), SpanOutput::flush_output)
+    }
+
+    /// Consumes the spanner and returns its writer, e.g. to get a file or a buffered writer back
+    /// once tracing is done.
+    ///
+    /// The lines held back by `Config::compact_leaves` and `Config::collapse_repeats` and the
+    /// summary of `Config::summary` are written first, as they would be when the spanner is
+    /// dropped.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::core::Spanner;
+    /// use std::io::BufWriter;
+    ///
+    /// let spanner = Spanner::from_writer(BufWriter::new(Vec::new()));
+    /// drop(spanner.enter_span("work"));
+    ///
+    /// let output = spanner.into_inner().into_inner().unwrap();
+    /// # #[cfg(not(feature = "disabled"))]
+    /// assert_eq!(output, "┌work\n└work\n".as_bytes());
+    /// ```
+    pub fn into_inner(mut self) -> T {
+        if !cfg!(feature = "disabled") {
+            self.flush_held();
+            self.write_summary();
+        }
+        self.writer
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+            .unwrap_or_else(|| {
+                unreachable!("the writer is only taken when the spanner is consumed")
+            })
+    }
+
+    /// Writes the span lines emitted within the configured time window to `writer`.
+    ///
+    /// Acts as a flight recorder: lines older than `Config::time_window` are pruned, so
+    /// only what happened most recently is dumped. Nothing is written if no time window is
+    /// configured.
+    ///
+    /// # Parameters
+    /// - `writer`: The writer to which the retained lines will be written.
+    ///
+    /// # Errors
+    /// Returns an error if writing to `writer` fails.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::{config::Config, core::VecSpanner};
+    /// use std::time::Duration;
+    ///
+    /// let spanner = VecSpanner::new()
+    ///     .with_config(Config::new().with_time_window(Some(Duration::from_secs(5))));
+    /// {
+    ///     let _span = spanner.enter_span("recent");
+    /// }
+    ///
+    /// let mut dump = Vec::new();
+    /// spanner.dump_window(&mut dump).unwrap();
+    /// # #[cfg(not(feature = "disabled"))]
+    /// assert_eq!(dump, "┌recent\n└recent\n".as_bytes());
+    /// ```
+    pub fn dump_window<W: std::io::Write>(&self, mut writer: W) -> std::io::Result<()> {
+        let Some(window) = self.config.time_window else {
+            return Ok(());
+        };
+        let Ok(mut events) = self.window.lock() else {
+            return Ok(());
+        };
+        prune_window(&mut events, Instant::now(), window);
+        for (_, line) in events.iter() {
+            writer.write_all(line.as_ref())?;
+        }
+        Ok(())
+    }
+
+    /// Returns a compact overview of the shape of the trace, recorded when `Config::overview` is
+    /// enabled.
+    ///
+    /// The overview is the sequence of depths at which spans were entered, separated by spaces.
+    /// Runs of consecutive spans entered at the same depth are encoded as `depth×count`.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::{config::Config, core::VecSpanner};
+    ///
+    /// let spanner = VecSpanner::new().with_config(Config::new().with_overview(true));
+    /// {
+    ///     let _root = spanner.enter_span("root");
+    ///     for _ in 0..3 {
+    ///         let _child = spanner.enter_span("child");
+    ///     }
+    /// }
+    ///
+    /// # #[cfg(not(feature = "disabled"))]
+    /// assert_eq!(spanner.overview(), "0 1×3");
+    /// ```
+    #[must_use]
+    pub fn overview(&self) -> String {
+        let Ok(overview) = self.overview.lock() else {
+            return String::new();
+        };
+        let mut rendered = String::new();
+        for (i, (depth, count)) in overview.iter().enumerate() {
+            if i > 0 {
+                rendered.push(' ');
+            }
+            let _ = write!(rendered, "{depth}");
+            if *count > 1 {
+                let _ = write!(rendered, "×{count}");
+            }
+        }
+        rendered
+    }
+
+    /// Writes the overview returned by `Spanner::overview` as an `overview: ...` line to the
+    /// writer, typically at the end of a run.
+    pub fn write_overview(&self) {
+        if cfg!(feature = "disabled") {
+            return;
+        }
+        let line = format!("overview: {}\n", self.overview());
+        self.emit(&line);
+    }
+
+    /// Starts recording the names of entered spans to compare them against `names` in
+    /// `Spanner::finalize`.
+    ///
+    /// Calling it again discards the previously recorded names.
+    ///
+    /// # Parameters
+    /// - `names`: The expected names of the entered spans, in order.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::core::VecSpanner;
+    ///
+    /// let spanner = VecSpanner::new();
+    /// spanner.expect_sequence(&["parse", "term", "term"]);
+    /// {
+    ///     let _parse = spanner.enter_span("parse");
+    ///     let _term = spanner.enter_span("term");
+    ///     let _factor = spanner.enter_span("factor");
+    /// }
+    ///
+    /// let mismatch = spanner.finalize().unwrap_err();
+    /// # #[cfg(not(feature = "disabled"))]
+    /// assert_eq!(mismatch.index, 2);
+    /// # #[cfg(not(feature = "disabled"))]
+    /// assert_eq!(
+    ///     mismatch.to_string(),
+    ///     "span sequence diverged at index 2: expected `term`, found `factor`"
+    /// );
+    /// ```
+    pub fn expect_sequence(&self, names: &[&str]) {
+        if let Ok(mut sequence) = self.sequence.lock() {
+            let expected = names.iter().map(|&name| name.to_owned()).collect();
+            *sequence = Some((expected, Vec::new()));
+            self.sequence_armed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Compares the names of the spans entered since `Spanner::expect_sequence` against the
+    /// expected ones and stops recording.
+    ///
+    /// Succeeds if no sequence is expected.
+    ///
+    /// # Errors
+    /// Returns a `SequenceMismatch` describing the first position where the entered spans
+    /// diverge from the expected sequence.
+    pub fn finalize(&self) -> Result<(), SequenceMismatch> {
+        if !self.sequence_armed.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let Some((expected, actual)) = self.sequence.lock().ok().and_then(|mut s| s.take()) else {
+            return Ok(());
+        };
+        let len = expected.len().max(actual.len());
+        for index in 0..len {
+            let (expected, actual) = (expected.get(index), actual.get(index));
+            if expected != actual {
+                return Err(SequenceMismatch {
+                    index,
+                    expected: expected.cloned(),
+                    actual: actual.cloned(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Consumes the spanner and returns the tree of spans recorded when `Config::record_tree` is
+    /// enabled.
+    ///
+    /// The returned node is a nameless root whose children are the top-level spans. Spans are
+    /// nested by the order they are entered and dropped in, which assumes that they are properly
+    /// nested, i.e. that the spanner is used from a single thread. When a spanner is shared
+    /// between threads, spans of other threads are attached to whichever span happens to be open
+    /// at the time. Spans that are still open are closed without a duration.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::{config::Config, core::VecSpanner};
+    ///
+    /// let spanner = VecSpanner::new().with_config(Config::new().with_record_tree(true));
+    /// {
+    ///     let _root = spanner.enter_span("root");
+    ///     let _child = spanner.enter_span("child");
+    /// }
+    ///
+    /// let tree = spanner.into_tree();
+    /// # #[cfg(not(feature = "disabled"))]
+    /// assert_eq!(tree.children[0].name, "root");
+    /// # #[cfg(not(feature = "disabled"))]
+    /// assert_eq!(tree.children[0].children[0].name, "child");
+    /// ```
+    #[must_use]
+    pub fn into_tree(mut self) -> SpanNode {
+        let mut stack = self.tree.get_mut().map(std::mem::take).unwrap_or_default();
+        while let Some(node) = stack.pop() {
+            match stack.last_mut() {
+                Some(parent) => parent.children.push(node),
+                None => return node,
+            }
+        }
+        SpanNode::default()
+    }
+
+    /// Returns the statistics collected for every span name when `Config::collect_stats` is
+    /// enabled.
+    ///
+    /// Spans are keyed by their full name, so spans entered with `spf!(spanner, "fib({n})")` are
+    /// aggregated per argument. Spans that are filtered out by their level or rate are not
+    /// counted, while spans deeper than `Config::max_depth` are. A span is counted once it is
+    /// dropped, so spans that are still open are missing.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::{config::Config, core::VecSpanner};
+    ///
+    /// let spanner = VecSpanner::new().with_config(Config::new().with_collect_stats(true));
+    /// for _ in 0..3 {
+    ///     let _span = spanner.enter_span("work");
+    /// }
+    ///
+    /// let stats = spanner.stats();
+    /// # #[cfg(not(feature = "disabled"))]
+    /// assert_eq!(stats["work"].count, 3);
+    /// # #[cfg(not(feature = "disabled"))]
+    /// assert!(stats["work"].max <= stats["work"].total);
+    /// ```
+    pub fn stats(&self) -> HashMap<String, SpanStats> {
+        self.stats
+            .lock()
+            .map(|stats| stats.clone())
+            .unwrap_or_default()
+    }
+
+    /// Adds a dropped span to the statistics of its name.
+    fn record_stats(&self, name: &str, duration: Duration) {
+        let Ok(mut stats) = self.stats.lock() else {
+            return;
+        };
+        // Avoids allocating the key for names that were seen before
+        if !stats.contains_key(name) {
+            stats.insert(name.to_owned(), SpanStats::default());
+        }
+        let Some(entry) = stats.get_mut(name) else {
+            return;
+        };
+        entry.count += 1;
+        entry.total += duration;
+        entry.max = entry.max.max(duration);
+    }
+
+    /// Checks whether spans are rendered as a tree with connectors.
+    fn draws_connectors(&self) -> bool {
+        self.config.tree_connectors && self.config.format == Format::Tree
+    }
+
+    /// Opens a new node of the tree of `Config::tree_connectors`.
+    fn open_connector(&self, name: String) {
+        if let Ok(mut connectors) = self.connectors.lock() {
+            connectors.push(SpanNode {
+                name,
+                children: Vec::new(),
+                duration: None,
+            });
+        }
+    }
+
+    /// Closes the innermost node of the tree of `Config::tree_connectors`, attaching it to its
+    /// parent, or writing the whole tree if it is a top-level span.
+    fn close_connector(&self) {
+        let Some(node) = self.connectors.lock().ok().and_then(|mut connectors| {
+            let node = connectors.pop()?;
+            match connectors.last_mut() {
+                Some(parent) => {
+                    parent.children.push(node);
+                    None
+                }
+                None => Some(node),
+            }
+        }) else {
+            return;
+        };
+        self.emit(&format!("{}\n", node.name));
+        let mut rules = String::new();
+        self.emit_connected(&node.children, &mut rules);
+    }
+
+    /// Writes the lines of `children` and their descendants, below the `rules` of their
+    /// ancestors.
+    fn emit_connected(&self, children: &[SpanNode], rules: &mut String) {
+        for (i, child) in children.iter().enumerate() {
+            let is_last = i + 1 == children.len();
+            let connector = if is_last { "└─ " } else { "├─ " };
+            self.emit(&format!("{rules}{connector}{}\n", child.name));
+            let len = rules.len();
+            rules.push_str(if is_last { "   " } else { "│  " });
+            self.emit_connected(&child.children, rules);
+            rules.truncate(len);
+        }
+    }
+
+    /// Opens a new node of the tree of spans if `Config::record_tree` is enabled.
+    fn open_node(&self, name: Arguments) {
+        if !self.config.record_tree {
+            return;
+        }
+        if let Ok(mut tree) = self.tree.lock() {
+            tree.push(SpanNode {
+                name: name.to_string(),
+                children: Vec::new(),
+                duration: None,
+            });
+        }
+    }
+
+    /// Closes the innermost node of the tree of spans, attaching it to its parent.
+    fn close_node(&self, duration: Option<Duration>) {
+        if !self.config.record_tree {
+            return;
+        }
+        let Ok(mut tree) = self.tree.lock() else {
+            return;
+        };
+        if tree.len() < 2 {
+            return;
+        }
+        if let Some(mut node) = tree.pop() {
+            node.duration = duration;
+            if let Some(parent) = tree.last_mut() {
+                parent.children.push(node);
+            }
+        }
+    }
+
+    /// Checks whether a span with `level` passes the level filter.
+    fn is_enabled(&self, level: Level) -> bool {
+        !cfg!(feature = "disabled") && self.level() <= level
+    }
+
+    /// Writes the footer of the configured format, which is `]` closing the array of events of
+    /// `Format::ChromeTrace` and the closing tags of the `Format::Html` document. Other formats
+    /// have no footer.
+    ///
+    /// Call it once after the last span is dropped, e.g. at the end of `main`.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::{config::{Config, Format}, core::Spanner};
+    /// use std::io::Cursor;
+    ///
+    /// let mut writer = Cursor::new(Vec::new());
+    /// let spanner = Spanner::from_writer(&mut writer)
+    ///     .with_config(Config::new().with_format(Format::ChromeTrace));
+    ///
+    /// drop(spanner.enter_span("main"));
+    /// spanner.write_footer();
+    /// drop(spanner);
+    ///
+    /// let trace = String::from_utf8(writer.into_inner()).unwrap();
+    /// # #[cfg(not(feature = "disabled"))]
+    /// assert!(trace.starts_with("[\n{\"name\":\"main\",\"ph\":\"B\""));
+    /// # #[cfg(not(feature = "disabled"))]
+    /// assert!(trace.ends_with("}\n]\n"));
+    /// ```
+    pub fn write_footer(&self) {
+        if !matches!(self.config.format, Format::ChromeTrace | Format::Html) {
+            return;
+        }
+        let footer = self
+            .render
+            .lock()
+            .map(|mut state| state.footer(self.config.format));
+        if let Ok(footer) = footer {
+            self.emit(&footer);
+        }
+    }
+
+    /// Records the name of an entered span if a sequence is expected.
+    fn record_sequence(&self, name: Arguments) {
+        if !self.sequence_armed.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Ok(mut sequence) = self.sequence.lock() {
+            if let Some((_, actual)) = sequence.as_mut() {
+                actual.push(name.to_string());
+            }
+        }
+    }
+
+    /// Records the entry depth of a span if `Config::overview` is enabled.
+    fn record_overview(&self, depth: usize) {
+        if !self.config.overview {
+            return;
+        }
+        if let Ok(mut overview) = self.overview.lock() {
+            match overview.last_mut() {
+                Some((last, count)) if *last == depth => *count += 1,
+                _ => overview.push((depth, 1)),
+            }
+        }
+    }
+
+    /// Writes a rendered line to the writer, or to the innermost deferred buffer if one is open,
+    /// retaining it if a time window is configured.
+    fn emit(&self, line: &str) {
+        // Enter messages of `Format::FoldedStacks` and exit messages of `Format::Markdown` are empty
+        if line.is_empty() {
+            return;
+        }
+        self.flush_held();
+        let line = translate_newlines(line, &self.config);
+        let line = line.as_ref();
+        if self.config.record_events {
+            if let Ok(mut events) = self.events.lock() {
+                events.push(Event {
+                    timestamp: Instant::now(),
+                    thread: thread_label(),
+                    line: line.to_owned(),
+                });
+            }
+        }
+        let line = self.decorate(line);
+        let line = line.as_ref();
+        if let Some(window) = self.config.time_window {
+            if let Ok(mut events) = self.window.lock() {
+                let now = Instant::now();
+                prune_window(&mut events, now, window);
+                events.push_back((now, line.to_owned()));
+            }
+        }
+        self.output(line);
+    }
+
+    /// Appends `output` to the innermost deferred buffer if there is one, or writes it otherwise.
+    fn output(&self, output: &str) {
+        if self.deferred_open.load(Ordering::Relaxed) > 0 {
+            if let Ok(mut deferred) = self.deferred.lock() {
+                if let Some(buffer) = deferred.last_mut() {
+                    buffer.push_str(output);
+                    return;
+                }
+            }
+        }
+        self.write(output);
+    }
+
+    /// Prepends the line prefix, the indent offset and the configured per-line labels to every
+    /// line of a rendered chunk.
+    fn decorate<'l>(&self, chunk: &'l str) -> Cow<'l, str> {
+        if self.config.line_prefix.is_none()
+            && self.config.indent_offset == 0
+            && !self.config.thread_labels
+            && self.config.timestamps == TimestampMode::None
+        {
+            return Cow::Borrowed(chunk);
+        }
+        let label = self.config.thread_labels.then(thread_label);
+        let mut decorated = String::new();
+        for line in chunk.split_inclusive('\n') {
+            decorated.push_str(self.config.line_prefix.unwrap_or_default());
+            let _ = write!(decorated, "{:1$}", "", self.config.indent_offset);
+            self.push_timestamp(&mut decorated);
+            if let Some(label) = &label {
+                decorated.push_str(label);
+            }
+            decorated.push_str(line);
+        }
+        Cow::Owned(decorated)
+    }
+
+    /// Pushes the timestamp of `Config::timestamps` to `buf`.
+    fn push_timestamp(&self, buf: &mut String) {
+        match self.config.timestamps {
+            TimestampMode::None => {}
+            TimestampMode::Relative => {
+                let elapsed = self.created.elapsed();
+                let _ = write!(
+                    buf,
+                    "[{}.{:03}ms] ",
+                    elapsed.as_millis(),
+                    elapsed.subsec_micros() % 1000
+                );
+            }
+            TimestampMode::Wall => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default();
+                let seconds = now.as_secs() % 86_400;
+                let _ = write!(
+                    buf,
+                    "[{:02}:{:02}:{:02}.{:03}] ",
+                    seconds / 3600,
+                    seconds / 60 % 60,
+                    seconds % 60,
+                    now.subsec_millis()
+                );
+            }
+        }
+    }
+
+    /// Writes a chunk of rendered output, which consists of whole lines, to the writer with a
+    /// single `write_all`, recording the first error that occurs.
+    fn write(&self, output: &str) {
+        let result = match self.writer.lock() {
+            Ok(mut writer) => match writer.as_mut() {
+                Some(writer) => writer.write_output(output),
+                None => return,
+            },
+            Err(_) => return,
+        };
+        if let Err(err) = result {
+            if let Ok(mut error) = self.error.lock() {
+                error.get_or_insert(err.kind());
+            }
+        }
+    }
+
+    /// Checks whether a span passes `Config::sampling`, counting it as open within a top-level
+    /// span that was sampled out, if it is not.
+    fn acquire_sample(&self) -> bool {
+        let Some(sampling) = self.config.sampling else {
+            return true;
+        };
+        if self.sampled_out.load(Ordering::Relaxed) > 0 {
+            self.sampled_out.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        if self.depth() > 0 {
+            return true;
+        }
+        let index = self.sampled.fetch_add(1, Ordering::Relaxed);
+        if index.is_multiple_of(sampling.max(1) as usize) {
+            return true;
+        }
+        self.sampled_out.store(1, Ordering::Relaxed);
+        false
+    }
+
+    /// Checks the configured rate limit, counting the span if it is allowed to be entered within
+    /// the current one-second window.
+    fn acquire_rate(&self) -> bool {
+        let Some(max_rate) = self.config.max_rate else {
+            return true;
+        };
+        let Ok(mut rate) = self.rate.lock() else {
+            return true;
+        };
+        let now = Instant::now();
+        if now.duration_since(rate.0) >= Duration::from_secs(1) {
+            *rate = (now, 0);
+        }
+        if rate.1 >= max_rate {
+            return false;
+        }
+        rate.1 += 1;
+        true
+    }
+
+    /// Writes the `… (truncated)` marker at `depth`, unless it has already been written for the
+    /// current run of truncated spans.
+    fn mark_truncation(&self, depth: usize) {
+        // A marker would be an unbalanced begin event in a Chrome trace, an unclosed stack frame
+        // or an unclosed element
+        if matches!(
+            self.config.format,
+            Format::ChromeTrace | Format::FoldedStacks | Format::Html
+        ) || self.truncation_marked.swap(true, Ordering::Relaxed)
+        {
+            return;
+        }
+        let (mut marker, offset) = self.message(format_args!("… (truncated)"), depth, false, None);
+        if self.config.format == Format::Tree {
+            replace_glyph(&mut marker, offset, ' ');
+        }
+        self.emit(&marker);
+    }
+
+    /// Writes the summary line of `Config::summary`, unless it has already been written.
+    fn write_summary(&self) {
+        if !self.config.summary
+            || !self.config.format.supports_suffixes()
+            || cfg!(feature = "disabled")
+            || self.summarized.swap(true, Ordering::Relaxed)
+        {
+            return;
+        }
+        let spans = self.span_count.load(Ordering::Relaxed);
+        let line = format!(
+            "— {spans} span{}, max depth {} —\n",
+            if spans == 1 { "" } else { "s" },
+            self.max_depth.load(Ordering::Relaxed)
+        );
+        self.emit(&line);
+    }
+
+    /// Checks whether spans without children are rendered on a single line.
+    fn compacts_leaves(&self) -> bool {
+        self.config.compact_leaves && self.config.format == Format::Tree
+    }
+
+    /// Holds back the enter message of a span at `depth` until it is known whether the span has
+    /// children, writing the held back messages first.
+    fn hold_pending(&self, depth: usize, enter_message: String) {
+        self.flush_held();
+        if let Ok(mut pending) = self.pending.lock() {
+            *pending = Some((depth, enter_message));
+        }
+    }
+
+    /// Writes the messages held back by `Config::collapse_repeats` and `Config::compact_leaves`,
+    /// in the order they were held back in.
+    fn flush_held(&self) {
+        self.flush_repeat();
+        self.flush_pending();
+    }
+
+    /// Checks whether consecutive sibling spans with the same name are collapsed.
+    const fn collapses_repeats(&self) -> bool {
+        self.config.collapse_repeats && self.config.format.supports_suffixes()
+    }
+
+    /// Decides whether a span named `name` entered at `depth` is collapsed, either because it
+    /// repeats the last dropped sibling, which is counted, or because it is a child of such a
+    /// span.
+    fn collapse(&self, depth: usize, name: &str) -> bool {
+        let (Ok(mut collapsed), Ok(mut repeat)) = (self.collapsed.lock(), self.repeat.lock())
+        else {
+            return false;
+        };
+        if collapsed.is_some_and(|collapsed| depth > collapsed) {
+            return true;
+        }
+        match repeat.as_mut() {
+            Some(repeat) if repeat.depth == depth && repeat.name == name => {
+                repeat.count += 1;
+                *collapsed = Some(depth);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Ends the collapse of a repeated span at `depth` when it is dropped.
+    fn end_collapse(&self, depth: usize) {
+        if let Ok(mut collapsed) = self.collapsed.lock() {
+            if *collapsed == Some(depth) {
+                *collapsed = None;
+            }
+        }
+    }
+
+    /// Holds back the exit message of a span until it is known whether identical siblings
+    /// follow it, writing the held back messages first.
+    fn hold_repeat(&self, depth: usize, name: &str, exit_message: String) {
+        self.flush_held();
+        if let Ok(mut repeat) = self.repeat.lock() {
+            *repeat = Some(Repeat {
+                depth,
+                name: name.to_owned(),
+                exit_message,
+                count: 1,
+            });
+        }
+    }
+
+    /// Writes the held back exit message, annotated with the number of spans it stands for.
+    fn flush_repeat(&self) {
+        if !self.collapses_repeats() {
+            return;
+        }
+        let repeat = self.repeat.lock().ok().and_then(|mut repeat| repeat.take());
+        if let Some(Repeat {
+            mut exit_message,
+            count,
+            ..
+        }) = repeat
+        {
+            if count > 1 {
+                append_suffix(&mut exit_message, format_args!(" ×{count}"));
+            }
+            self.emit(&exit_message);
+        }
+    }
+
+    /// Writes the held back enter message, as the span it belongs to turned out to have
+    /// children or other output follows it.
+    fn flush_pending(&self) {
+        if !self.compacts_leaves() {
+            return;
+        }
+        let pending = self
+            .pending
+            .lock()
+            .ok()
+            .and_then(|mut pending| pending.take());
+        if let Some((_, enter_message)) = pending {
+            self.emit(&enter_message);
+        }
+    }
+
+    /// Discards the held back enter message of a span at `depth`, returning whether there was
+    /// one, which means that the span has no children.
+    fn take_pending(&self, depth: usize) -> bool {
+        let Ok(mut pending) = self.pending.lock() else {
+            return false;
+        };
+        if pending.as_ref().is_some_and(|&(held, _)| held == depth) {
+            *pending = None;
+            return true;
+        }
+        false
+    }
+
+    /// Writes the warning of `Config::depth_warning` if `depth` crosses the threshold, unless it
+    /// has already been written for the current crossing.
+    fn warn_depth(&self, depth: usize) {
+        let Some(threshold) = self.config.depth_warning else {
+            return;
+        };
+        if depth <= threshold
+            || !self.config.format.supports_suffixes()
+            || self.depth_warned.swap(true, Ordering::Relaxed)
+        {
+            return;
+        }
+        self.emit(&format!("⚠ depth {threshold} exceeded\n"));
+    }
+
+    /// Writes the blank line of `Config::top_level_separator` before a span entered at `depth`,
+    /// unless it is not a top-level span or the first one.
+    fn separate_top_level(&self, depth: usize) {
+        if !self.config.top_level_separator
+            || depth != 0
+            || !self.config.format.supports_suffixes()
+            || !self.top_level_entered.swap(true, Ordering::Relaxed)
+        {
+            return;
+        }
+        self.flush_held();
+        self.output(self.config.newline);
+    }
+
+    /// Checks whether the enter or exit messages of spans are written, as set by
+    /// `Config::enter_lines` and `Config::exit_lines`. Formats other than `Format::Tree` and
+    /// `Format::Flat` always write both.
+    const fn writes_lines(&self, exit: bool) -> bool {
+        let enabled = if exit {
+            self.config.exit_lines
+        } else {
+            self.config.enter_lines
+        };
+        enabled || !matches!(self.config.format, Format::Tree | Format::Flat)
+    }
+
+    /// Generates a message of a span at `depth`, rendered with `snapshot` if it is given.
+    fn message(
+        &self,
+        name: Arguments,
+        depth: usize,
+        exit: bool,
+        snapshot: Option<&Config>,
+    ) -> (String, usize) {
+        self.with_message(name, depth, exit, snapshot, render_message)
+    }
+
+    /// Writes a message of a span at `depth` to the writer, rendering it into a buffer that is
+    /// reused between messages instead of a new `String`. Only valid if `writes_directly` holds.
+    fn write_message(&self, name: Arguments, depth: usize, exit: bool, snapshot: Option<&Config>) {
+        let Ok(mut line) = self.line.lock() else {
+            return;
+        };
+        line.clear();
+        self.with_message(name, depth, exit, snapshot, |message| {
+            let _ = write!(line, "{message}");
+        });
+        self.write(&line);
+    }
+
+    /// Passes a message of a span at `depth` to `f`, rendered with the state of the spanner and
+    /// the innermost override of `enter_with_config` or the snapshot of the shared config of the
+    /// span, if any.
+    fn with_message<R>(
+        &self,
+        name: Arguments,
+        depth: usize,
+        exit: bool,
+        snapshot: Option<&Config>,
+        f: impl FnOnce(&Message<'_>) -> R,
+    ) -> R {
+        let mut fallback = RenderState::default();
+        let mut state = self.render.lock().ok();
+        let mut state = state.as_deref_mut().unwrap_or(&mut fallback);
+        // The indentation cached by the state of the spanner depends on its config, so an override
+        // renders with its own state and a snapshot from scratch
+        let mut uncached = RenderState::default();
+        let mut overrides = if self.override_count.load(Ordering::Relaxed) > 0 {
+            self.overrides.lock().ok()
+        } else {
+            None
+        };
+        let (cfg, own_state) = match overrides
+            .as_deref_mut()
+            .and_then(|overrides| overrides.last_mut())
+        {
+            Some((cfg, own_state)) => (&*cfg, Some(own_state)),
+            None => (
+                snapshot.unwrap_or(&self.config),
+                snapshot.map(|_| &mut uncached),
+            ),
+        };
+        if let Some(own_state) = own_state.filter(|_| cfg.format == Format::Tree) {
+            state = own_state;
+        }
+        with_message(name, depth, cfg, state, exit, f)
+    }
+
+    /// Checks whether a line can be written straight into the writer, i.e. whether it would reach
+    /// the writer unchanged when passed to `emit`.
+    fn writes_directly(&self) -> bool {
+        !self.config.record_events
+            && !self.compacts_leaves()
+            && !self.collapses_repeats()
+            && !self.config.thread_labels
+            && self.config.timestamps == TimestampMode::None
+            && self.config.indent_offset == 0
+            && self.config.line_prefix.is_none()
+            && self.config.time_window.is_none()
+            && self.config.newline == "\n"
+            && self.deferred_open.load(Ordering::Relaxed) == 0
+    }
+
+    /// Returns a snapshot of the config of `Spanner::from_writer_shared_config` for a span that is
+    /// being entered, keeping the format of the spanner.
+    fn snapshot_config(&self) -> Option<Config> {
+        self.shared_config.as_ref().map(|config| {
+            let cfg = config
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone();
+            Config {
+                format: self.config.format,
+                ..cfg.resolve_auto_color(self.terminal)
+            }
+        })
+    }
+
+    /// Starts rendering lines with `cfg`, keeping the format of the spanner.
+    fn push_override(&self, cfg: Config) {
+        let cfg = Config {
+            format: self.config.format,
+            ..cfg.resolve_auto_color(self.terminal)
+        };
+        if let Ok(mut overrides) = self.overrides.lock() {
+            overrides.push((cfg, RenderState::default()));
+            self.override_count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Stops rendering lines with the innermost override.
+    fn pop_override(&self) {
+        if let Ok(mut overrides) = self.overrides.lock() {
+            if overrides.pop().is_some() {
+                self.override_count.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Opens a new deferred buffer that captures all subsequently emitted lines.
+    fn open_deferred(&self) {
+        if let Ok(mut deferred) = self.deferred.lock() {
+            deferred.push(String::new());
+            self.deferred_open.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Closes the innermost deferred buffer, splicing it into the enclosing buffer or writing it
+    /// to the writer if there is none.
+    fn close_deferred(&self) {
+        let Ok(mut deferred) = self.deferred.lock() else {
+            return;
+        };
+        let Some(buffer) = deferred.pop() else {
+            return;
+        };
+        self.deferred_open.fetch_sub(1, Ordering::Relaxed);
+        if let Some(outer) = deferred.last_mut() {
+            outer.push_str(&buffer);
+            return;
+        }
+        drop(deferred);
+        self.write(&buffer);
+    }
+}
+
+/// Writes the summary line of `Config::summary` when the spanner is dropped.
+impl<T> Drop for Spanner<T>
+where
+    T: SpanOutput,
+{
+    fn drop(&mut self) {
+        let is_taken = self
+            .writer
+            .get_mut()
+            .map_or(true, |writer| writer.is_none());
+        if cfg!(feature = "disabled") || is_taken {
+            return;
+        }
+        self.flush_held();
+        self.write_summary();
+    }
+}
+
+/// A builder that collects key/value fields of a span before entering it, see
+/// `Spanner::span_builder`.
+///
+/// Fields are rendered after the name in the order they were added, e.g.
+/// `┌query {rows=42 db=main}`, and are part of both the enter and the exit message.
+#[derive(Debug)]
+#[must_use = "the span is only entered by `SpanBuilder::enter`"]
+pub struct SpanBuilder<'a, 'n, T>
+where
+    T: SpanOutput,
+{
+    parent: &'a Spanner<T>,
+    name: &'n str,
+    level: Level,
+    fields: String,
+}
+
+impl<'a, T> SpanBuilder<'a, '_, T>
+where
+    T: SpanOutput,
+{
+    /// Adds a field to the span.
+    ///
+    /// # Parameters
+    /// - `key`: The key of the field.
+    /// - `value`: The value of the field.
+    pub fn with_field(mut self, key: &str, value: impl std::fmt::Display) -> Self {
+        if cfg!(feature = "disabled") {
+            return self;
+        }
+        let separator = if self.fields.is_empty() { "" } else { " " };
+        let _ = write!(self.fields, "{separator}{key}={value}");
+        self
+    }
+
+    /// Replaces the level of the span, `Level::Info` by default.
+    ///
+    /// # Parameters
+    /// - `level`: The level of the span. It determines if the span would be outputted or not
+    pub fn with_level(self, level: Level) -> Self {
+        Self { level, ..self }
+    }
+
+    /// Enters the span with the collected fields.
+    #[must_use]
+    #[track_caller]
+    pub fn enter(self) -> Span<'a, T> {
+        if self.fields.is_empty() {
+            return self.parent.enter_with_level(self.level, self.name);
+        }
+        self.parent.enter_args(
+            self.level,
+            format_args!("{} {{{}}}", self.name, self.fields),
+        )
+    }
+}
+
+/// A span recorded in the tree of spans, see `Config::record_tree` and `Spanner::into_tree`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SpanNode {
+    /// The name of the span. Empty for the root of the tree.
+    pub name: String,
+    /// The spans entered while this span was open, in the order they were entered in.
+    pub children: Vec<SpanNode>,
+    /// The time the span was open for, if `Config::timing` is enabled.
+    pub duration: Option<Duration>,
+}
+
+impl SpanNode {
+    /// Renders the tree as a Graphviz DOT digraph, with a node per span labeled with its name and
+    /// its duration, if it has one, and an edge from every span to each of its children. The
+    /// output can be rendered with e.g. `dot -Tpng trace.dot -o trace.png`.
+    ///
+    /// Nodes are identified by their position in the tree, so spans with the same name get nodes
+    /// of their own. A nameless node, such as the root returned by `Spanner::into_tree`, is
+    /// omitted, so that its children become the roots of the graph.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::{config::Config, core::VecSpanner};
+    ///
+    /// let spanner = VecSpanner::new().with_config(Config::new().with_record_tree(true));
+    /// {
+    ///     let _root = spanner.enter_span("root");
+    ///     let _child = spanner.enter_span("child");
+    /// }
+    ///
+    /// let dot = spanner.into_tree().to_dot();
+    /// # #[cfg(not(feature = "disabled"))]
+    /// assert_eq!(
+    ///     dot,
+    ///     "digraph {\n  n0 [label=\"root\"];\n  n1 [label=\"child\"];\n  n0 -> n1;\n}\n"
+    /// );
+    /// ```
+    #[must_use]
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph {\n");
+        let mut next_id = 0;
+        if self.name.is_empty() {
+            for child in &self.children {
+                child.push_dot(&mut dot, &mut next_id);
+            }
+        } else {
+            self.push_dot(&mut dot, &mut next_id);
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Pushes the node of the span and the nodes and edges of its descendants to `dot`, returning
+    /// the id of the node.
+    fn push_dot(&self, dot: &mut String, next_id: &mut usize) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+        let label = self.duration.map_or_else(
+            || self.name.clone(),
+            |duration| format!("{}\n{}", self.name, DisplayDuration(duration)),
+        );
+        let _ = writeln!(dot, "  n{id} [label=\"{}\"];", json_escape(&label));
+        for child in &self.children {
+            let child_id = child.push_dot(dot, next_id);
+            let _ = writeln!(dot, "  n{id} -> n{child_id};");
+        }
+        id
+    }
+}
+
+/// The statistics of all spans with the same name, see `Config::collect_stats` and
+/// `Spanner::stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SpanStats {
+    /// The number of spans with the name that were dropped.
+    pub count: usize,
+    /// The total time the spans were open for.
+    pub total: Duration,
+    /// The longest time a single span was open for.
+    pub max: Duration,
+}
+
+/// A rendered span line recorded with the time and the thread it was emitted from, see
+/// `Config::record_events`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    /// The time the line was emitted at.
+    pub timestamp: Instant,
+    /// The `[name] ` label of the emitting thread.
+    pub thread: String,
+    /// The rendered line, including the trailing newline.
+    pub line: String,
+}
+
+/// Merges the events recorded by several spanners into one chronologically ordered trace and
+/// writes it to `writer`, labeling every line with the thread that emitted it.
+///
+/// This is the way to get a coherent trace out of a multithreaded program: give each thread its
+/// own spanner with `Config::record_events` enabled, then merge them once the threads are done.
+/// Each line keeps the indentation of its own spanner. Events with the same timestamp keep their
+/// relative order: events of spanners earlier in `spanners` come first, and events of the same
+/// spanner stay in the order they were emitted.
+///
+/// # Parameters
+/// - `spanners`: The spanners whose recorded events will be merged.
+/// - `writer`: The writer to which the merged trace will be written.
 ///
-/// ```rust
-/// use spannify::{spf, level::Level, core::StdoutSpanner};
+/// # Errors
+/// Returns an error if writing to `writer` fails.
 ///
-/// let my_spanner = StdoutSpanner::new();
+/// # Examples
+/// ```
+/// use spannify::{config::Config, core::{merge_timestamped, VecSpanner}};
 ///
-/// let timestamp = "12:21";
+/// let config = Config::new().with_record_events(true);
+/// let spanners = std::thread::scope(|scope| {
+///     let workers: Vec<_> = (0..2)
+///         .map(|i| {
+///             let config = config.clone();
+///             std::thread::Builder::new()
+///                 .name(format!("worker-{i}"))
+///                 .spawn_scoped(scope, move || {
+///                     let spanner = VecSpanner::new().with_config(config);
+///                     drop(spanner.enter_span("work"));
+///                     spanner
+///                 })
+///                 .unwrap()
+///         })
+///         .collect();
+///     workers.into_iter().map(|w| w.join().unwrap()).collect()
+/// });
 ///
-/// // Creates a span with a level and literal message
-/// spf!(my_spanner, Level::Info => "Starting process");
+/// let mut merged = Vec::new();
+/// merge_timestamped(spanners, &mut merged).unwrap();
+/// # #[cfg(not(feature = "disabled"))]
+/// assert_eq!(String::from_utf8(merged).unwrap().lines().count(), 4);
+/// ```
+pub fn merge_timestamped<T, W>(spanners: Vec<Spanner<T>>, mut writer: W) -> std::io::Result<()>
+where
+    T: SpanOutput,
+    W: std::io::Write,
+{
+    let mut events: Vec<Event> = spanners
+        .into_iter()
+        .filter_map(|mut spanner| spanner.events.get_mut().ok().map(std::mem::take))
+        .flatten()
+        .collect();
+    events.sort_by_key(|event| event.timestamp);
+    for event in events {
+        writer.write_all(event.thread.as_ref())?;
+        writer.write_all(event.line.as_ref())?;
+    }
+    Ok(())
+}
+
+/// The exit message of a span held back by `Config::collapse_repeats`, along with the number of
+/// consecutive sibling spans with the same name it stands for.
+#[derive(Debug)]
+struct Repeat {
+    depth: usize,
+    name: String,
+    exit_message: String,
+    count: usize,
+}
+
+/// The first divergence between the expected and the actual sequence of entered spans, returned
+/// by `Spanner::finalize`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SequenceMismatch {
+    /// The position of the first diverging span.
+    pub index: usize,
+    /// The expected span name, or `None` if more spans were entered than expected.
+    pub expected: Option<String>,
+    /// The actual span name, or `None` if fewer spans were entered than expected.
+    pub actual: Option<String>,
+}
+
+impl std::fmt::Display for SequenceMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "span sequence diverged at index {}: ", self.index)?;
+        match &self.expected {
+            Some(expected) => write!(f, "expected `{expected}`, ")?,
+            None => write!(f, "expected no more spans, ")?,
+        }
+        match &self.actual {
+            Some(actual) => write!(f, "found `{actual}`"),
+            None => write!(f, "found end of trace"),
+        }
+    }
+}
+
+impl std::error::Error for SequenceMismatch {}
+
+thread_local! {
+    /// The depths of the calling thread in the spanners with `Config::thread_local_depth`,
+    /// keyed by the ids of the spanners.
+    static THREAD_DEPTHS: std::cell::RefCell<HashMap<usize, usize>> =
+        std::cell::RefCell::new(HashMap::new());
+}
+
+/// Returns a unique id for a new spanner, used to tell apart the depths of different spanners in
+/// `THREAD_DEPTHS`.
+fn next_spanner_id() -> usize {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Returns the `[name] ` label of the current thread, falling back to its id if it is unnamed.
+#[allow(clippy::use_debug)]
+fn thread_label() -> String {
+    let thread = std::thread::current();
+    thread.name().map_or_else(
+        || format!("[{:?}] ", thread.id()),
+        |name| format!("[{name}] "),
+    )
+}
+
+/// Displays a `Duration` in microseconds below a millisecond, in milliseconds below a second
+/// and in seconds otherwise.
+struct DisplayDuration(Duration);
+
+impl std::fmt::Display for DisplayDuration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let duration = self.0;
+        if duration < Duration::from_millis(1) {
+            write!(f, "{}µs", duration.as_micros())
+        } else if duration < Duration::from_secs(1) {
+            write!(f, "{:.1}ms", duration.as_secs_f64() * 1000.0)
+        } else {
+            write!(f, "{:.2}s", duration.as_secs_f64())
+        }
+    }
+}
+
+/// Replaces the character at the byte `offset` of a rendered line with `glyph`.
+fn replace_glyph(message: &mut String, offset: usize, glyph: char) {
+    if let Some(old) = message[offset..].chars().next() {
+        let mut buf = [0; 4];
+        message.replace_range(offset..offset + old.len_utf8(), glyph.encode_utf8(&mut buf));
+    }
+}
+
+/// Appends `suffix` to a rendered line, keeping the trailing newline at the end.
+fn append_suffix(message: &mut String, suffix: Arguments) {
+    let has_newline = message.ends_with('\n');
+    if has_newline {
+        message.pop();
+    }
+    let _ = message.write_fmt(suffix);
+    if has_newline {
+        message.push('\n');
+    }
+}
+
+/// Removes the events that are older than `window` relative to `now`.
+fn prune_window(events: &mut VecDeque<(Instant, String)>, now: Instant, window: Duration) {
+    while let Some((timestamp, _)) = events.front() {
+        if now.duration_since(*timestamp) <= window {
+            break;
+        }
+        events.pop_front();
+    }
+}
+
+impl<W> Spanner<FmtWriter<W>>
+where
+    W: std::fmt::Write,
+{
+    /// Creates a new `Spanner` instance that writes into a `std::fmt::Write` sink, such as a
+    /// `String`.
+    ///
+    /// # Parameters
+    /// - `writer`: The sink to which the spans will be written.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::core::Spanner;
+    ///
+    /// let mut trace = String::new();
+    /// let spanner = Spanner::from_fmt_writer(&mut trace);
+    /// ```
+    pub fn from_fmt_writer(writer: W) -> Self {
+        Self::from_writer(FmtWriter::new(writer))
+    }
+}
+
+/// A Spanner that can be cloned and shared between threads.
+pub type SharedSpanner<T> = std::sync::Arc<Spanner<T>>;
+
+/// A Spanner that writes to the Vec of bytes.
+pub type VecSpanner = Spanner<Vec<u8>>;
+
+impl VecSpanner {
+    /// Creates a `VecSpanner` instance with default values.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new `VecSpanner` from an existing vector.
+    ///
+    /// # Parameters
+    /// - `vec`: The vector to use as the writer.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::core::VecSpanner;
+    ///
+    /// let spanner = VecSpanner::from_vec(Vec::new());
+    /// ```
+    #[must_use]
+    pub fn from_vec(vec: Vec<u8>) -> Self {
+        Self::from_writer(vec)
+    }
+
+    /// Consumes the spanner and returns everything written to it as a `String`.
+    ///
+    /// # Errors
+    /// Returns an error if the output is not valid UTF-8, which can only happen if the spanner was
+    /// created from a vector that already contained invalid UTF-8.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::core::VecSpanner;
+    ///
+    /// let spanner = VecSpanner::new();
+    /// {
+    ///     let _outer = spanner.enter_span("outer");
+    ///     let _inner = spanner.enter_span("inner");
+    /// }
+    ///
+    /// let output = spanner.into_string().unwrap();
+    /// # #[cfg(not(feature = "disabled"))]
+    /// assert_eq!(output, "┌outer\n|  inner\n|  inner\n└outer\n");
+    /// ```
+    pub fn into_string(self) -> Result<String, std::string::FromUtf8Error> {
+        String::from_utf8(self.into_inner())
+    }
+
+    /// Passes everything written to the spanner so far to `f` and returns its result.
+    ///
+    /// The writer is locked while `f` runs, so `f` must not enter or drop spans of this spanner.
+    ///
+    /// # Parameters
+    /// - `f`: The function that receives the written bytes.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::core::VecSpanner;
+    ///
+    /// let spanner = VecSpanner::new();
+    /// drop(spanner.enter_span("work"));
+    ///
+    /// # #[cfg(not(feature = "disabled"))]
+    /// assert!(spanner.with_contents(|output| output.ends_with("└work\n".as_bytes())));
+    /// ```
+    pub fn with_contents<R>(&self, f: impl FnOnce(&[u8]) -> R) -> R {
+        let writer = self
+            .writer
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        f(writer.as_deref().unwrap_or_default())
+    }
+
+    /// Removes everything written to the spanner so far, e.g. to run another trace through the
+    /// same spanner after a call to `Spanner::reset`.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::core::VecSpanner;
+    ///
+    /// let spanner = VecSpanner::new();
+    /// drop(spanner.enter_span("first"));
+    /// spanner.clear();
+    /// drop(spanner.enter_span("second"));
+    ///
+    /// # #[cfg(not(feature = "disabled"))]
+    /// assert_eq!(spanner.into_string().unwrap(), "┌second\n└second\n");
+    /// ```
+    pub fn clear(&self) {
+        let mut writer = self
+            .writer
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if let Some(vec) = writer.as_mut() {
+            vec.clear();
+        }
+        drop(writer);
+        self.top_level_entered.store(false, Ordering::Relaxed);
+    }
+}
+
+impl Default for VecSpanner {
+    fn default() -> Self {
+        Self::from_writer(Vec::new())
+    }
+}
+
+/// A Spanner that writes to a File
+pub type FileSpanner = Spanner<std::fs::File>;
+
+impl FileSpanner {
+    /// Creates a new `FileSpanner` from an existing file.
+    ///
+    /// # Parameters
+    /// - `file`: The file to use as the writer.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::core::FileSpanner;
+    ///
+    /// let file = std::fs::File::create("/tmp/output.txt").unwrap();
+    /// let spanner = FileSpanner::new(file);
+    /// ```
+    #[must_use]
+    pub fn new(file: std::fs::File) -> Self {
+        Self::from_writer(file)
+    }
+
+    /// Creates a new `BufferedFileSpanner` that wraps `file` in a `std::io::BufWriter`, so that
+    /// span lines are written in batches instead of issuing a syscall per line.
+    ///
+    /// Buffered lines only reach the file when the buffer fills up, when `Spanner::flush` is
+    /// called or when the spanner is dropped. Call `Spanner::flush` before reading the file while
+    /// the spanner is still alive, and note that errors that occur when the spanner is dropped
+    /// are ignored.
+    ///
+    /// # Parameters
+    /// - `file`: The file to use as the writer.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::core::FileSpanner;
+    ///
+    /// let file = std::fs::File::create("/tmp/output.txt").unwrap();
+    /// let spanner = FileSpanner::buffered(file);
+    /// drop(spanner.enter_span("work"));
+    /// spanner.flush().unwrap();
+    /// ```
+    #[must_use]
+    pub fn buffered(file: std::fs::File) -> BufferedFileSpanner {
+        Spanner::from_writer(std::io::BufWriter::new(file))
+    }
+}
+
+/// A Spanner that writes to a File through a `std::io::BufWriter`, see `FileSpanner::buffered`.
+pub type BufferedFileSpanner = Spanner<std::io::BufWriter<std::fs::File>>;
+
+/// A Spanner that forwards its lines to the `log` facade, see `LogWriter`.
+#[cfg(feature = "log")]
+pub type LogSpanner = Spanner<LogWriter>;
+
+#[cfg(feature = "log")]
+impl LogSpanner {
+    /// Creates a `LogSpanner` instance that logs with the `spannify` target.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(feature = "log")]
+impl Default for LogSpanner {
+    fn default() -> Self {
+        Self::from_writer(LogWriter::new())
+    }
+}
+
+/// A Spanner that writes to the standard out.
+pub type StdoutSpanner = Spanner<std::io::Stdout>;
+
+impl StdoutSpanner {
+    /// Creates a `StdoutSpanner` instance with default values.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Default for StdoutSpanner {
+    fn default() -> Self {
+        let stdout = std::io::stdout();
+        let terminal = stdout.is_terminal();
+        let mut spanner = Self::from_writer(stdout);
+        spanner.terminal = terminal;
+        spanner
+    }
+}
+
+/// A Spanner that writes to the standard error.
+pub type StderrSpanner = Spanner<std::io::Stderr>;
+
+impl StderrSpanner {
+    /// Creates a `StderrSpanner` instance with default values.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Default for StderrSpanner {
+    fn default() -> Self {
+        let stderr = std::io::stderr();
+        let terminal = stderr.is_terminal();
+        let mut spanner = Self::from_writer(stderr);
+        spanner.terminal = terminal;
+        spanner
+    }
+}
+
+/// A `Span` represents a hierarchical structure for tracking and displaying the entry and
+/// exit of various sections of code.
 ///
-/// // Creates a span with a literal message
-/// spf!(my_spanner => "Starting process");
+/// It uses a provided writer to output messages on entering
+/// and dropping spans, facilitating visual traceability of the execution flow.
 ///
-/// // Example of creating a span with a formatted message
-/// spf!(my_spanner, "Starting process at {}", timestamp);
-/// // Or
-/// spf!(my_spanner, "Starting process at {timestamp}");
+/// The `Span` structure works in tandem with a `Spanner` instance, which maintains the
+/// configuration and depth state. Each span generates formatted messages based on the
+/// current depth and configuration, which are written to the provided writer.
 ///
-/// // Example of creating a span with a level and formatted message
-/// spf!(my_spanner, Level::Info, "Starting process at {}", timestamp);
+/// Cloning a span returns an idle span at the same level, which does nothing when dropped, so
+/// that the span is only exited once.
+#[cfg(not(feature = "disabled"))]
+#[derive(Debug)]
+pub struct Span<'a, T>
+where
+    T: SpanOutput,
+{
+    parent: &'a Spanner<T>,
+    state: SpanState,
+}
+
+/// A `Span` represents a hierarchical structure for tracking and displaying the entry and
+/// exit of various sections of code.
 ///
-#[macro_export]
-macro_rules! spf {
-    ($spa:expr, $level:path => $arg:literal) => {{
-        let span = $spa.enter_with_level($level, $arg);
+/// With the `disabled` feature, the span only holds its level and does nothing on enter and
+/// drop.
+#[cfg(feature = "disabled")]
+#[derive(Clone, Debug)]
+pub struct Span<'a, T>
+where
+    T: SpanOutput,
+{
+    parent: PhantomData<&'a Spanner<T>>,
+    level: Level,
+}
+
+/// The part of a `Span` that does not depend on the writer of its spanner, so that the global
+/// spanner can hold the spans of any spanner without boxing them.
+#[cfg(not(feature = "disabled"))]
+#[derive(Debug)]
+// The flags record independent facts about how the span was entered and has to be dropped
+#[allow(clippy::struct_excessive_bools)]
+pub(crate) struct SpanState {
+    level: Level,
+    name: String,
+    depth: usize,
+    entered: bool,
+    /// Whether the span was suppressed by `Config::sampling`.
+    sampled_out: bool,
+    deferred: bool,
+    metrics: Vec<(String, f64)>,
+    early_return: bool,
+    disarmed: bool,
+    start: Option<Instant>,
+    stats_start: Option<Instant>,
+    truncated: bool,
+    collapsed: bool,
+    /// Whether the span was entered with `Spanner::enter_with_config`.
+    overridden: bool,
+    /// The snapshot of the config of `Spanner::from_writer_shared_config` taken at enter.
+    config: Option<Config>,
+}
+
+/// The part of a `Span` held by the global spanner, which is only the level with the `disabled`
+/// feature.
+#[cfg(feature = "disabled")]
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct SpanState(Level);
+
+#[cfg(not(feature = "disabled"))]
+impl SpanState {
+    /// Returns the state of a span with `level` that was not entered, which does nothing when
+    /// dropped.
+    const fn idle(level: Level) -> Self {
+        Self {
+            level,
+            name: String::new(),
+            depth: 0,
+            entered: false,
+            sampled_out: false,
+            deferred: false,
+            metrics: Vec::new(),
+            early_return: false,
+            disarmed: false,
+            start: None,
+            stats_start: None,
+            truncated: false,
+            collapsed: false,
+            overridden: false,
+            config: None,
+        }
+    }
+}
+
+#[cfg(not(feature = "disabled"))]
+impl<'a, T> Span<'a, T>
+where
+    T: SpanOutput,
+{
+    /// Creates a new `Span` for a given `parent` `Spanner` and a span `name`. This method
+    /// increases the depth of the parent spanner, generates entry and drop messages, writes
+    /// the entry message to the writer, and returns the new `Span` instance.
+    ///
+    /// # Parameters
+    ///
+    /// - `parent`: A reference to the `Spanner` instance managing the configuration and depth.
+    /// - `level`: The level of the span.
+    /// - `name`: The name of the span, which will be included in the messages.
+    #[track_caller]
+    fn enter(parent: &'a Spanner<T>, level: Level, name: &str) -> Self {
+        Self::enter_args(parent, level, format_args!("{name}"))
+    }
+
+    #[track_caller]
+    fn enter_args(parent: &'a Spanner<T>, level: Level, args: Arguments) -> Self {
+        Self::enter_inner(parent, level, parent.is_enabled(level), args, false)
+    }
+
+    /// Same as `enter_args`, but buffers the output of the span and its descendants until the
+    /// span is dropped.
+    #[track_caller]
+    fn enter_deferred(parent: &'a Spanner<T>, level: Level, args: Arguments) -> Self {
+        Self::enter_inner(parent, level, parent.is_enabled(level), args, true)
+    }
+
+    /// Same as `enter`, but renders the span and its descendants with `cfg`, see
+    /// `Spanner::enter_with_config`.
+    #[track_caller]
+    fn enter_overridden(parent: &'a Spanner<T>, name: &str, cfg: Config) -> Self {
+        parent.push_override(cfg);
+        let mut span = Self::enter(parent, Level::Info, name);
+        if span.state.entered {
+            span.state.overridden = true;
+        } else {
+            parent.pop_override();
+        }
         span
-    }};
+    }
+
+    /// Enters the span if it passed the level filter, as decided by `enabled`, the sampling and
+    /// the rate limit.
+    #[track_caller]
+    fn enter_inner(
+        parent: &'a Spanner<T>,
+        level: Level,
+        enabled: bool,
+        args: Arguments,
+        deferred: bool,
+    ) -> Self {
+        let config = parent.snapshot_config();
+        let is_passed = enabled
+            && config
+                .as_ref()
+                .unwrap_or(&parent.config)
+                .passes_name_filter(args);
+        let is_sampled = is_passed && parent.acquire_sample();
+        let is_entered = is_sampled && parent.acquire_rate();
+        let state = SpanState {
+            entered: is_entered,
+            sampled_out: is_passed && !is_sampled,
+            deferred: deferred && is_entered,
+            config,
+            ..SpanState::idle(level)
+        };
+        let mut span = Self { parent, state };
+        if is_entered {
+            span.open(args);
+        }
+        span
+    }
+
+    /// Takes the state out of the span without exiting it, so that it can be held by a
+    /// `GlobalSpan` and exited later with `Span::from_state`.
+    pub(crate) fn into_state(mut self) -> SpanState {
+        let idle = SpanState::idle(self.state.level);
+        core::mem::replace(&mut self.state, idle)
+    }
+
+    /// Rebuilds a span from the state taken by `Span::into_state`, which exits it when dropped.
+    pub(crate) const fn from_state(parent: &'a Spanner<T>, state: SpanState) -> Self {
+        Self { parent, state }
+    }
+
+    /// Increases the depth of the parent spanner, remembers the name and the depth of the span to
+    /// generate its drop message from and writes the enter message, unless the span is deeper
+    /// than `Config::max_depth`.
+    #[track_caller]
+    fn open(&mut self, args: Arguments) {
+        let parent = self.parent;
+        let config = self.state.config.clone();
+        #[cfg(feature = "log")]
+        let _line_level = LineLevel::enter(self.state.level);
+        if self.state.deferred {
+            parent.open_deferred();
+        }
+        let prev_depth = parent.increment_depth();
+        self.state.depth = prev_depth;
+        parent.span_count.fetch_add(1, Ordering::Relaxed);
+        parent
+            .max_depth
+            .fetch_max(prev_depth + 1, Ordering::Relaxed);
+        parent.warn_depth(prev_depth + 1);
+        parent.record_overview(prev_depth);
+        parent.record_sequence(args);
+        parent.open_node(args);
+        if parent.config.collect_stats {
+            // Truncated spans are counted as well, so the name is remembered before truncation
+            self.state.name = args.to_string();
+            self.state.stats_start = Some(Instant::now());
+        }
+        if let Some(max_depth) = parent.config.max_depth {
+            if prev_depth >= max_depth {
+                self.state.truncated = true;
+                if prev_depth == max_depth {
+                    parent.mark_truncation(prev_depth);
+                }
+                return;
+            }
+        }
+        parent.truncation_marked.store(false, Ordering::Relaxed);
+
+        let name = args.to_string();
+        if parent.draws_connectors() {
+            parent.separate_top_level(prev_depth);
+            parent.open_connector(name);
+            return;
+        }
+        if parent.collapses_repeats() && parent.collapse(prev_depth, &name) {
+            self.state.collapsed = true;
+            return;
+        }
+        parent.separate_top_level(prev_depth);
+        let supports_suffixes = parent.config.format.supports_suffixes();
+        let fold_markers = parent.config.fold_markers.filter(|_| supports_suffixes);
+        let location = (parent.config.location && supports_suffixes).then_some(Location::caller());
+        if !parent.writes_lines(false) {
+            // The name and the start time are still needed by the exit message
+        } else if fold_markers.is_none() && location.is_none() && parent.writes_directly() {
+            parent.write_message(format_args!("{name}"), prev_depth, false, config.as_ref());
+        } else {
+            let (mut enter_message, _) =
+                parent.message(format_args!("{name}"), prev_depth, false, config.as_ref());
+            if let Some(location) = location {
+                append_suffix(
+                    &mut enter_message,
+                    format_args!(" ({}:{})", location.file(), location.line()),
+                );
+            }
+            if let Some(fold_markers) = fold_markers {
+                append_suffix(
+                    &mut enter_message,
+                    format_args!(" {}", fold_markers.markers().0),
+                );
+            }
+            if parent.compacts_leaves() {
+                parent.hold_pending(prev_depth, enter_message);
+            } else {
+                parent.emit(&enter_message);
+            }
+        }
+        self.state.name = name;
+
+        if parent.config.timing || parent.config.format == Format::Csv {
+            self.state.start = Some(Instant::now());
+        }
+    }
+
+    /// Returns the level that the span was entered with.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::{core::VecSpanner, level::Level};
+    ///
+    /// let spanner = VecSpanner::new();
+    /// let span = spanner.enter_with_level(Level::Warn, "retry");
+    /// assert_eq!(span.level(), Level::Warn);
+    /// ```
+    #[must_use]
+    pub const fn level(&self) -> Level {
+        self.state.level
+    }
+
+    /// Attaches a numeric metric to the span, which is displayed on the span's exit message.
+    ///
+    /// Metrics accumulate in the order they were set, e.g. `└parse_file (1024 bytes, 3 rows)`.
+    /// Setting a metric with a label that is already present replaces its value.
+    ///
+    /// # Parameters
+    /// - `label`: The label of the metric, typically its unit.
+    /// - `value`: The value of the metric.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::core::Spanner;
+    /// use std::io::Cursor;
+    ///
+    /// let mut writer = Cursor::new(Vec::new());
+    /// let spanner = Spanner::from_writer(&mut writer);
+    ///
+    /// {
+    ///     let mut span = spanner.enter_span("parse_file");
+    ///     span.set_metric("bytes", 1024.0);
+    /// }
+    /// drop(spanner);
+    ///
+    /// assert_eq!(writer.get_ref(), "┌parse_file\n└parse_file (1024 bytes)\n".as_bytes());
+    /// ```
+    pub fn set_metric(&mut self, label: &str, value: f64) {
+        if let Some(metric) = self.state.metrics.iter_mut().find(|(l, _)| l == label) {
+            metric.1 = value;
+        } else {
+            self.state.metrics.push((label.to_owned(), value));
+        }
+    }
+
+    /// Writes a note under the span, e.g. to annotate progress without entering a child span. The
+    /// note is written immediately, one level deeper than the span, with `·` in place of the
+    /// glyph, e.g. `| · computed 42`.
+    ///
+    /// Notes are only written in `Format::Tree`, and not for spans that are filtered out,
+    /// truncated by `Config::max_depth` or collapsed by `Config::collapse_repeats`, nor with
+    /// `Config::tree_connectors`, which renders whole trees at once.
+    ///
+    /// # Parameters
+    /// - `msg`: The note to write.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::core::Spanner;
+    /// use std::io::Cursor;
+    ///
+    /// let mut writer = Cursor::new(Vec::new());
+    /// let spanner = Spanner::from_writer(&mut writer);
+    ///
+    /// {
+    ///     let span = spanner.enter_span("solve");
+    ///     span.record(format_args!("computed {}", 42));
+    /// }
+    /// drop(spanner);
+    ///
+    /// assert_eq!(writer.get_ref(), "┌solve\n| · computed 42\n└solve\n".as_bytes());
+    /// ```
+    pub fn record(&self, msg: impl std::fmt::Display) {
+        let parent = self.parent;
+        if !self.state.entered
+            || self.state.truncated
+            || self.state.collapsed
+            || parent.config.format != Format::Tree
+            || parent.draws_connectors()
+        {
+            return;
+        }
+        let (mut note, offset) = parent.message(
+            format_args!(" {msg}"),
+            self.state.depth + 1,
+            false,
+            self.state.config.as_ref(),
+        );
+        replace_glyph(&mut note, offset, '·');
+        parent.emit(&note);
+    }
+
+    /// Marks the span as early-returned, replacing its exit glyph with `Config::early_return_char`.
+    ///
+    /// Call it right before an early `return` to tell the span apart from a span that ran to
+    /// completion. The glyph is rendered even at depths where `skip` hides the regular glyph.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::core::Spanner;
+    /// use std::io::Cursor;
+    ///
+    /// let mut writer = Cursor::new(Vec::new());
+    /// let spanner = Spanner::from_writer(&mut writer);
+    ///
+    /// {
+    ///     let mut span = spanner.enter_span("parse");
+    ///     span.mark_early_return();
+    /// }
+    /// drop(spanner);
+    ///
+    /// assert_eq!(writer.get_ref(), "┌parse\n↵parse\n".as_bytes());
+    /// ```
+    pub const fn mark_early_return(&mut self) {
+        self.state.early_return = true;
+    }
+
+    /// Exits the span before the end of its scope, writing its exit message and decreasing the
+    /// depth right away.
+    ///
+    /// This is the same as dropping the span, but states the intent more clearly. As the span is
+    /// consumed, its exit message is written exactly once.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::core::Spanner;
+    /// use std::io::Cursor;
+    ///
+    /// let mut writer = Cursor::new(Vec::new());
+    /// let spanner = Spanner::from_writer(&mut writer);
+    ///
+    /// let span = spanner.enter_span("load");
+    /// span.exit();
+    /// let _span = spanner.enter_span("process");
+    /// drop(_span);
+    /// drop(spanner);
+    ///
+    /// assert_eq!(writer.get_ref(), "┌load\n└load\n┌process\n└process\n".as_bytes());
+    /// ```
+    pub fn exit(self) {
+        drop(self);
+    }
+
+    /// Disarms the span, so that no exit message is written when it is dropped, e.g. when control
+    /// flow is transferred elsewhere and the exit line would be misleading.
+    ///
+    /// Everything else still happens on drop: the depth is decreased, so that the following spans
+    /// are indented correctly, and the span is recorded in the tree and the statistics. The exit
+    /// message is written anyway in `Format::ChromeTrace`, `Format::FoldedStacks` and
+    /// `Format::Html`, whose output would be malformed without it. Fold markers of a disarmed span
+    /// are left unbalanced.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::core::Spanner;
+    /// use std::io::Cursor;
+    ///
+    /// let mut writer = Cursor::new(Vec::new());
+    /// let spanner = Spanner::from_writer(&mut writer);
+    ///
+    /// {
+    ///     let mut span = spanner.enter_span("handoff");
+    ///     span.disarm();
+    /// }
+    /// drop(spanner);
+    ///
+    /// assert_eq!(writer.get_ref(), "┌handoff\n".as_bytes());
+    /// ```
+    pub const fn disarm(&mut self) {
+        self.state.disarmed = true;
+    }
+
+    /// Replaces the exit glyph of the drop message if the span was marked as early-returned.
+    fn apply_early_return(&self, message: &mut String, glyph_offset: usize) {
+        if !self.state.early_return {
+            return;
+        }
+        replace_glyph(message, glyph_offset, self.parent.config.early_return_char);
+    }
+
+    /// Appends the elapsed time to the drop message if the span is timed.
+    fn append_timing(&self, message: &mut String) {
+        if let Some(start) = self.state.start {
+            append_suffix(
+                message,
+                format_args!(" ({})", DisplayDuration(start.elapsed())),
+            );
+        }
+    }
+
+    /// Appends the row of `Format::Csv` to the drop message, which only holds the header if it is
+    /// the first row.
+    fn append_csv_row(&self, message: &mut String) {
+        let created = self.parent.created;
+        let enter = self
+            .state
+            .start
+            .map_or(0, |start| start.duration_since(created).as_micros());
+        let exit = created.elapsed().as_micros();
+        let _ = writeln!(
+            message,
+            "{},{},{enter},{exit},{}",
+            csv_escape(&self.state.name),
+            self.state.depth,
+            exit.saturating_sub(enter)
+        );
+    }
+
+    /// Appends the metrics to the drop message, keeping the trailing newline.
+    fn append_metrics(&self, message: &mut String) {
+        if self.state.metrics.is_empty() {
+            return;
+        }
+        let mut rendered = String::new();
+        for (i, (label, value)) in self.state.metrics.iter().enumerate() {
+            if i > 0 {
+                rendered.push_str(", ");
+            }
+            let _ = write!(rendered, "{value} {label}");
+        }
+        append_suffix(message, format_args!(" ({rendered})"));
+    }
+
+    /// Adds the early return flag, the elapsed time and the metrics to a `Format::JsonLines` drop
+    /// message.
+    fn append_json_fields(&self, message: &mut String) {
+        let mut fields = String::new();
+        if self.state.early_return {
+            fields.push_str(",\"early_return\":true");
+        }
+        if let Some(start) = self.state.start {
+            let _ = write!(fields, ",\"duration_us\":{}", start.elapsed().as_micros());
+        }
+        if !self.state.metrics.is_empty() {
+            fields.push_str(",\"metrics\":{");
+            for (i, (label, value)) in self.state.metrics.iter().enumerate() {
+                if i > 0 {
+                    fields.push(',');
+                }
+                let _ = write!(fields, "\"{}\":", json_escape(label));
+                if value.is_finite() {
+                    let _ = write!(fields, "{value}");
+                } else {
+                    fields.push_str("null");
+                }
+            }
+            fields.push('}');
+        }
+        if fields.is_empty() {
+            return;
+        }
+        // Insert the fields before the closing brace and the newline
+        let end = message.len() - "}\n".len();
+        message.insert_str(end, &fields);
+    }
+
+    /// Writes the drop message, straight into the writer if it is not decorated in any way.
+    fn write_drop_message(&self) {
+        let parent = self.parent;
+        let name = format_args!("{}", self.state.name);
+        let is_plain = !self.state.early_return
+            && self.state.start.is_none()
+            && self.state.metrics.is_empty()
+            && parent.config.fold_markers.is_none();
+        if is_plain && parent.writes_directly() {
+            parent.write_message(name, self.state.depth, true, self.state.config.as_ref());
+            return;
+        }
+        let is_leaf = parent.compacts_leaves() && parent.take_pending(self.state.depth);
+        let (mut message, glyph_offset) =
+            parent.message(name, self.state.depth, true, self.state.config.as_ref());
+        if is_leaf && message[glyph_offset..].starts_with(parent.config.exit_char) {
+            replace_glyph(&mut message, glyph_offset, parent.config.leaf_char);
+        }
+        match parent.config.format {
+            Format::JsonLines => self.append_json_fields(&mut message),
+            Format::Csv => self.append_csv_row(&mut message),
+            Format::ChromeTrace | Format::FoldedStacks | Format::Markdown | Format::Html => {}
+            Format::Tree | Format::Flat => {
+                self.apply_early_return(&mut message, glyph_offset);
+                self.append_timing(&mut message);
+                self.append_metrics(&mut message);
+                self.append_fold_marker(&mut message);
+            }
+        }
+        if parent.collapses_repeats() {
+            parent.hold_repeat(self.state.depth, &self.state.name, message);
+        } else {
+            parent.emit(&message);
+        }
+    }
+
+    /// Appends the close fold marker to the drop message if fold markers are configured.
+    fn append_fold_marker(&self, message: &mut String) {
+        if let Some(fold_markers) = self.parent.config.fold_markers {
+            let close = fold_markers.markers().1;
+            append_suffix(message, format_args!(" {close}"));
+        }
+    }
+}
+
+#[cfg(feature = "disabled")]
+impl<'a, T> Span<'a, T>
+where
+    T: SpanOutput,
+{
+    /// Returns a span with `level` that does nothing.
+    const fn disabled(level: Level) -> Self {
+        Self {
+            parent: PhantomData,
+            level,
+        }
+    }
+
+    #[track_caller]
+    const fn enter(_parent: &'a Spanner<T>, level: Level, _name: &str) -> Self {
+        Self::disabled(level)
+    }
+
+    #[track_caller]
+    const fn enter_args(_parent: &'a Spanner<T>, level: Level, _args: Arguments) -> Self {
+        Self::disabled(level)
+    }
+
+    #[track_caller]
+    const fn enter_deferred(_parent: &'a Spanner<T>, level: Level, _args: Arguments) -> Self {
+        Self::disabled(level)
+    }
+
+    #[track_caller]
+    const fn enter_inner(
+        _parent: &'a Spanner<T>,
+        level: Level,
+        _enabled: bool,
+        _args: Arguments,
+        _deferred: bool,
+    ) -> Self {
+        Self::disabled(level)
+    }
+
+    #[track_caller]
+    fn enter_overridden(_parent: &'a Spanner<T>, _name: &str, _cfg: Config) -> Self {
+        Self::disabled(Level::Info)
+    }
+
+    pub(crate) const fn into_state(self) -> SpanState {
+        SpanState(self.level)
+    }
+
+    pub(crate) const fn from_state(_parent: &'a Spanner<T>, state: SpanState) -> Self {
+        Self::disabled(state.0)
+    }
+
+    /// Returns the level that the span was entered with.
+    #[must_use]
+    pub const fn level(&self) -> Level {
+        self.level
+    }
+
+    /// Does nothing, as the `disabled` feature is enabled.
+    pub const fn set_metric(&mut self, _label: &str, _value: f64) {}
+
+    /// Does nothing, as the `disabled` feature is enabled.
+    pub fn record(&self, _msg: impl std::fmt::Display) {}
+
+    /// Does nothing, as the `disabled` feature is enabled.
+    pub const fn mark_early_return(&mut self) {}
+
+    /// Does nothing, as the `disabled` feature is enabled.
+    pub const fn exit(self) {}
+
+    /// Does nothing, as the `disabled` feature is enabled.
+    pub const fn disarm(&mut self) {}
+}
+
+#[cfg(not(feature = "disabled"))]
+impl<T> Clone for Span<'_, T>
+where
+    T: SpanOutput,
+{
+    /// Returns an idle span at the level of this span, which neither exits this span nor
+    /// writes anything when dropped.
+    fn clone(&self) -> Self {
+        Self {
+            parent: self.parent,
+            state: SpanState::idle(self.state.level),
+        }
+    }
+}
+
+/// Implements the `Drop` trait for the `Span` struct, ensuring that the drop message is
+/// written to the writer and the parent's depth is decremented when the span goes out of scope.
+#[cfg(not(feature = "disabled"))]
+impl<T> Drop for Span<'_, T>
+where
+    T: SpanOutput,
+{
+    /// Regenerates the drop message, writes it to the writer and decrements the parent's depth.
+    fn drop(&mut self) {
+        if self.state.sampled_out {
+            self.parent.sampled_out.fetch_sub(1, Ordering::Relaxed);
+        }
+        if !self.state.entered {
+            return;
+        }
+        #[cfg(feature = "log")]
+        let _line_level = LineLevel::enter(self.state.level);
+        self.parent.decrement_depth();
+        if self
+            .parent
+            .config
+            .depth_warning
+            .is_some_and(|threshold| self.state.depth <= threshold)
+        {
+            self.parent.depth_warned.store(false, Ordering::Relaxed);
+        }
+        self.parent
+            .close_node(self.state.start.map(|start| start.elapsed()));
+        if let Some(start) = self.state.stats_start {
+            self.parent.record_stats(&self.state.name, start.elapsed());
+        }
+
+        if self.state.collapsed {
+            self.parent.end_collapse(self.state.depth);
+        } else if self.parent.draws_connectors() && !self.state.truncated {
+            self.parent.close_connector();
+        } else if !self.state.truncated {
+            self.parent
+                .truncation_marked
+                .store(false, Ordering::Relaxed);
+            let needs_exit = matches!(
+                self.parent.config.format,
+                Format::ChromeTrace | Format::FoldedStacks | Format::Html
+            );
+            if (!self.state.disarmed || needs_exit) && self.parent.writes_lines(true) {
+                self.write_drop_message();
+            }
+        }
+        if self.state.deferred {
+            self.parent.close_deferred();
+        }
+        if self.state.overridden {
+            self.parent.pop_override();
+        }
+    }
+}
+
+#[cfg(all(test, feature = "disabled"))]
+mod disabled_tests {
+    use super::*;
+    use crate::spf;
+
+    #[test]
+    fn test_disabled() {
+        let spanner = VecSpanner::new()
+            .with_config(Config::new().with_level(Level::Trace).with_summary(true));
+        {
+            let mut span = spf!(spanner, "outer {}", 1);
+            span.set_metric("rows", 1.0);
+            let _inner = spanner.span_builder("inner").with_field("rows", 1).enter();
+            assert_eq!(spanner.depth(), 0);
+            assert_eq!(spf!(spanner, "expression" => 2 + 2), 4);
+            let span = spanner.enter_with_config("config", Config::new().with_tabwidth(4));
+            span.record("note");
+            assert_eq!(span.level(), Level::Info);
+        }
+        spanner.write_overview();
+        assert!(spanner.into_string().unwrap().is_empty());
+        assert_eq!(size_of::<Span<'_, Vec<u8>>>(), size_of::<Level>());
+    }
+}
+
+#[cfg(all(test, not(feature = "disabled")))]
+#[allow(clippy::needless_return, clippy::semicolon_if_nothing_returned)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::config::{Color, FoldStyle, Theme, MAX_TABWIDTH};
+    use crate::render::{push_colored, truncate_name};
+    use crate::{spf, spf_debug, spf_error, spf_trace, spf_warn};
+
+    struct Helper<T>
+    where
+        T: std::io::Write,
+    {
+        spanner: Spanner<T>,
+    }
+    impl<T> Helper<T>
+    where
+        T: std::io::Write,
+    {
+        fn helper(&self, current_depth: usize, target_depth: usize) {
+            let _span = self
+                .spanner
+                .enter_span(format!("Span({})", current_depth).as_ref());
+
+            if current_depth >= target_depth {
+                return;
+            }
+            return self.helper(current_depth + 1, target_depth);
+        }
+
+        fn helper_early_return(&self, current_depth: usize, target_depth: usize) {
+            let mut span = self
+                .spanner
+                .enter_span(format!("Span({})", current_depth).as_ref());
+
+            if current_depth >= target_depth {
+                span.mark_early_return();
+                return;
+            }
+            self.helper_early_return(current_depth + 1, target_depth);
+        }
+
+        fn helper_macro(&self, current_depth: usize, target_depth: usize) {
+            let _span = spf!(self.spanner, "Span({current_depth})");
+
+            if current_depth >= target_depth {
+                return;
+            }
+            return self.helper_macro(current_depth + 1, target_depth);
+        }
+
+        fn helper_macro_2(&self, current_depth: usize, target_depth: usize) {
+            let _span = spf!(self.spanner, Level::Info, "Span({current_depth})");
+
+            if current_depth >= target_depth {
+                return;
+            }
+            return self.helper_macro_2(current_depth + 1, target_depth);
+        }
+
+        fn helper_macro_3(&self, current_depth: usize, target_depth: usize) {
+            let _span = spf!(self.spanner => "Span({current_depth})");
+
+            if current_depth >= target_depth {
+                return;
+            }
+            return self.helper_macro_3(current_depth + 1, target_depth);
+        }
+
+        fn helper_macro_4(&self, current_depth: usize, target_depth: usize) {
+            let _span = spf!(self.spanner, "Span({})", current_depth);
+
+            if current_depth >= target_depth {
+                return;
+            }
+            return self.helper_macro_2(current_depth + 1, target_depth);
+        }
+    }
+
+    fn fib(spanner: &VecSpanner, n: usize) -> usize {
+        let _span = spf!(spanner, "fib({n})");
+        match n {
+            0 | 1 => n,
+            _ => fib(spanner, n - 1) + fib(spanner, n - 2),
+        }
+    }
+
+    #[test]
+    fn it_works() {
+        let helper = Helper {
+            spanner: VecSpanner::new(),
+        };
+
+        let expected = r#"┌Span(0)
+|  Span(1)
+|   ┌Span(2)
+|   ┆  Span(3)
+|   ┆   ┌Span(4)
+|   ┆   |  Span(5)
+|   ┆   |  Span(5)
+|   ┆   └Span(4)
+|   ┆  Span(3)
+|   └Span(2)
+|  Span(1)
+└Span(0)
+"#;
+
+        helper.helper(0, 5);
+        let vec = helper.spanner.into_inner();
+        assert_eq!(expected.bytes().collect::<Vec<_>>(), vec);
+
+        let helper = Helper {
+            spanner: VecSpanner::new(),
+        };
+        helper.helper_macro(0, 5);
+        let vec = helper.spanner.into_inner();
+        assert_eq!(expected.bytes().collect::<Vec<_>>(), vec)
+    }
+
+    #[test]
+    fn test_config() {
+        let helper = Helper {
+            spanner: VecSpanner::new().with_config(Config::new().with_skip(3)),
+        };
+
+        let expected = r#"┌Span(0)
+|  Span(1)
+|    Span(2)
+|     ┌Span(3)
+|     ┊  Span(4)
+|     ┊    Span(5)
+|     ┊    Span(5)
+|     ┊  Span(4)
+|     └Span(3)
+|    Span(2)
+|  Span(1)
+└Span(0)
+"#;
+
+        helper.helper(0, 5);
+        let vec = helper.spanner.into_inner();
+        assert_eq!(expected.bytes().collect::<Vec<_>>(), vec)
+    }
+
+    #[test]
+    fn test_level() {
+        let mut writer = Cursor::new(Vec::new());
+        let spanner = Spanner::from_writer(&mut writer)
+            .with_config(Config::new().with_skip(3).with_level(Level::Info));
+
+        {
+            let _span = spanner.enter_with_level(Level::Debug, "foo");
+            // Span is dropped here
+        }
+        let writer = spanner.into_inner();
+
+        assert_eq!(writer.get_ref(), &[0_u8; 0]);
+    }
+
+    #[test]
+    fn test_time_window() {
+        let start = Instant::now();
+        let mut events: VecDeque<_> = [0, 100, 200, 300]
+            .into_iter()
+            .map(|ms| (start + Duration::from_millis(ms), format!("{ms}\n")))
+            .collect();
+
+        prune_window(
+            &mut events,
+            start + Duration::from_millis(300),
+            Duration::from_millis(200),
+        );
+        let lines: Vec<_> = events.iter().map(|(_, line)| line.as_str()).collect();
+        assert_eq!(lines, ["100\n", "200\n", "300\n"]);
+
+        prune_window(
+            &mut events,
+            start + Duration::from_secs(1),
+            Duration::from_millis(200),
+        );
+        assert!(events.is_empty());
+
+        let spanner = VecSpanner::new()
+            .with_config(Config::new().with_time_window(Some(Duration::from_secs(60))));
+        {
+            let _span = spanner.enter_span("recent");
+        }
+
+        let mut dump = Vec::new();
+        spanner.dump_window(&mut dump).unwrap();
+        assert_eq!(String::from_utf8(dump).unwrap(), "┌recent\n└recent\n");
+
+        let vec = spanner.into_inner();
+        assert_eq!(String::from_utf8(vec).unwrap(), "┌recent\n└recent\n");
+    }
+
+    #[test]
+    fn test_glyph_column() {
+        let helper = Helper {
+            spanner: VecSpanner::new().with_config(Config::new().with_glyph_column(Some(3))),
+        };
+
+        let expected = r#"   ┌Span(0)
+|   Span(1)
+|   ┌Span(2)
+|   ┆  Span(3)
+|   ┆  Span(3)
+|   └Span(2)
+|   Span(1)
+   └Span(0)
+"#;
+
+        helper.helper(0, 3);
+        let vec = helper.spanner.into_inner();
+        assert_eq!(expected, String::from_utf8(vec).unwrap());
+    }
+
+    #[test]
+    fn test_deferred() {
+        let spanner = VecSpanner::new();
+
+        {
+            let _outer = spanner.enter_deferred(Level::Info, "outer");
+            {
+                let _inner = spanner.enter_deferred(Level::Info, "inner");
+                let _leaf = spanner.enter_span("leaf");
+            }
+            let _sibling = spanner.enter_span("sibling");
+            assert!(spanner.with_contents(<[u8]>::is_empty));
+        }
+        {
+            let _span = spanner.enter_span("after");
+        }
+
+        let expected = r#"┌outer
+|  inner
+|   ┌leaf
+|   └leaf
+|  inner
+|  sibling
+|  sibling
+└outer
+┌after
+└after
+"#;
+        let vec = spanner.into_inner();
+        assert_eq!(expected, String::from_utf8(vec).unwrap());
+    }
+
+    #[test]
+    fn test_metrics() {
+        let spanner = VecSpanner::new();
+
+        {
+            let mut span = spanner.enter_span("parse");
+            span.set_metric("bytes", 1024.0);
+            span.set_metric("rows", 2.5);
+            span.set_metric("rows", 3.0);
+            let _child = spanner.enter_span("child");
+        }
+
+        let expected = r#"┌parse
+|  child
+|  child
+└parse (1024 bytes, 3 rows)
+"#;
+        let vec = spanner.into_inner();
+        assert_eq!(expected, String::from_utf8(vec).unwrap());
+    }
+
+    #[test]
+    fn test_sampling() {
+        let spanner = VecSpanner::new().with_config(Config::new().with_sampling(Some(3)));
+        for i in 0..10 {
+            let _span = spf!(spanner, "iteration({i})");
+            let _inner = spanner.enter_span("inner");
+            assert_eq!(spanner.depth(), if i % 3 == 0 { 2 } else { 0 });
+        }
+        assert_eq!(spanner.depth(), 0);
+        let expected = "┌iteration(0)
+|  inner
+|  inner
+└iteration(0)
+┌iteration(3)
+|  inner
+|  inner
+└iteration(3)
+┌iteration(6)
+|  inner
+|  inner
+└iteration(6)
+┌iteration(9)
+|  inner
+|  inner
+└iteration(9)
+";
+        assert_eq!(spanner.into_string().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_max_rate() {
+        let spanner = VecSpanner::new().with_config(Config::new().with_max_rate(Some(3)));
+
+        {
+            let _outer = spanner.enter_span("outer");
+            let _first = spanner.enter_span("first");
+            let _second = spanner.enter_span("second");
+            {
+                let _third = spanner.enter_span("third");
+            }
+        }
+        {
+            let _span = spanner.enter_span("fourth");
+        }
+
+        assert_eq!(spanner.depth.load(Ordering::Relaxed), 0);
+        let expected = r#"┌outer
+|  first
+|   ┌second
+|   └second
+|  first
+└outer
+"#;
+        let vec = spanner.into_inner();
+        assert_eq!(expected, String::from_utf8(vec).unwrap());
+    }
+
+    #[test]
+    fn test_early_return() {
+        let helper = Helper {
+            spanner: VecSpanner::new(),
+        };
+
+        let expected = r#"┌Span(0)
+|  Span(1)
+|   ┌Span(2)
+|   ↵Span(2)
+|  Span(1)
+└Span(0)
+"#;
+
+        helper.helper_early_return(0, 2);
+        let vec = helper.spanner.into_inner();
+        assert_eq!(expected, String::from_utf8(vec).unwrap());
+
+        let helper = Helper {
+            spanner: VecSpanner::new().with_config(Config::new().with_early_return_char('<')),
+        };
+        helper.helper_early_return(0, 1);
+        let vec = helper.spanner.into_inner();
+        assert_eq!(
+            "┌Span(0)\n|  Span(1)\n| <Span(1)\n└Span(0)\n",
+            String::from_utf8(vec).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_flat_format() {
+        let spanner = VecSpanner::new().with_config(Config::new().with_format(Format::Flat));
+
+        {
+            let _outer = spanner.enter_span("parse");
+            let _inner = spanner.enter_span("parse \"file\" a\\b");
+            let _empty = spanner.enter_span("");
+        }
+
+        let expected = r#"0 E parse
+1 E "parse \"file\" a\\b"
+2 E ""
+2 X ""
+1 X "parse \"file\" a\\b"
+0 X parse
+"#;
+        let vec = spanner.into_inner();
+        let output = String::from_utf8(vec).unwrap();
+        assert_eq!(expected, output);
+        for line in output.lines() {
+            let mut fields = line.split_whitespace();
+            assert!(fields.next().unwrap().parse::<usize>().is_ok());
+            assert!(matches!(fields.next(), Some("E" | "X")));
+        }
+    }
+
+    #[test]
+    fn test_thread_labels() {
+        let spanner = VecSpanner::new().with_config(Config::new().with_thread_labels(true));
+
+        std::thread::scope(|scope| {
+            std::thread::Builder::new()
+                .name("worker-1".to_owned())
+                .spawn_scoped(scope, || {
+                    let _span = spanner.enter_span("named");
+                })
+                .unwrap()
+                .join()
+                .unwrap();
+            scope
+                .spawn(|| {
+                    let _span = spanner.enter_span("unnamed");
+                })
+                .join()
+                .unwrap();
+        });
+
+        let vec = spanner.into_inner();
+        let output = String::from_utf8(vec).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines[..2], ["[worker-1] ┌named", "[worker-1] └named"]);
+        assert!(lines[2].starts_with("[ThreadId(") && lines[2].ends_with(")] ┌unnamed"));
+        assert!(lines[3].starts_with("[ThreadId(") && lines[3].ends_with(")] └unnamed"));
+    }
+
+    #[test]
+    fn test_sanitize_names() {
+        let name = "\x1b[31mred\x1b[0m\nnext";
+
+        let spanner = VecSpanner::new().with_config(Config::new().with_sanitize_names(true));
+        {
+            let _span = spanner.enter_span(name);
+        }
+        let vec = spanner.into_inner();
+        assert_eq!(
+            r"┌\x1b[31mred\x1b[0m\x0anext
+└\x1b[31mred\x1b[0m\x0anext
+",
+            String::from_utf8(vec).unwrap()
+        );
+
+        let spanner = VecSpanner::new();
+        {
+            let _span = spanner.enter_span(name);
+        }
+        let vec = spanner.into_inner();
+        assert_eq!(
+            format!("┌{name}\n└{name}\n"),
+            String::from_utf8(vec).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_overview() {
+        let helper = Helper {
+            spanner: VecSpanner::new().with_config(Config::new().with_overview(true)),
+        };
+
+        helper.helper(0, 2);
+        helper.helper(0, 0);
+        helper.helper(0, 0);
+        assert_eq!(helper.spanner.overview(), "0 1 2 0×2");
+
+        helper.spanner.write_overview();
+        let vec = helper.spanner.into_inner();
+        let output = String::from_utf8(vec).unwrap();
+        assert!(output.ends_with("└Span(0)\noverview: 0 1 2 0×2\n"));
+    }
+
+    #[test]
+    fn test_expect_sequence() {
+        let helper = Helper {
+            spanner: VecSpanner::new(),
+        };
+
+        helper
+            .spanner
+            .expect_sequence(&["Span(0)", "Span(1)", "Span(2)"]);
+        helper.helper(0, 2);
+        assert_eq!(helper.spanner.finalize(), Ok(()));
+
+        helper
+            .spanner
+            .expect_sequence(&["Span(0)", "Span(1)", "Span(2)"]);
+        helper.helper(0, 1);
+        let mismatch = helper.spanner.finalize().unwrap_err();
+        assert_eq!(mismatch.index, 2);
+        assert_eq!(
+            mismatch.to_string(),
+            "span sequence diverged at index 2: expected `Span(2)`, found end of trace"
+        );
+
+        helper.spanner.expect_sequence(&["Span(0)"]);
+        helper.helper(0, 1);
+        assert_eq!(
+            helper.spanner.finalize().unwrap_err().to_string(),
+            "span sequence diverged at index 1: expected no more spans, found `Span(1)`"
+        );
+
+        // Recording stops after finalizing
+        helper.helper(0, 1);
+        assert_eq!(helper.spanner.finalize(), Ok(()));
+    }
+
+    #[test]
+    fn test_merge_timestamped() {
+        let config = Config::new().with_record_events(true);
+        let first = VecSpanner::new().with_config(config.clone());
+        let second = VecSpanner::new().with_config(config);
+
+        {
+            let _a = first.enter_span("a");
+            std::thread::sleep(Duration::from_millis(5));
+            let _b = second.enter_span("b");
+            std::thread::sleep(Duration::from_millis(5));
+            let _c = first.enter_span("c");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        let mut merged = Vec::new();
+        merge_timestamped(vec![first, second], &mut merged).unwrap();
+        let label = thread_label();
+        let expected = ["┌a", "┌b", "|  c", "|  c", "└b", "└a"]
+            .iter()
+            .map(|line| format!("{label}{line}\n"))
+            .collect::<String>();
+        assert_eq!(expected, String::from_utf8(merged).unwrap());
+    }
+
+    #[test]
+    fn test_fold_markers() {
+        let helper = Helper {
+            spanner: VecSpanner::new()
+                .with_config(Config::new().with_fold_markers(Some(FoldStyle::Vim))),
+        };
+
+        let expected = r#"┌Span(0) {{{
+|  Span(1) {{{
+|   ┌Span(2) {{{
+|   └Span(2) }}}
+|  Span(1) }}}
+└Span(0) }}}
+"#;
+
+        helper.helper(0, 2);
+        let vec = helper.spanner.into_inner();
+        assert_eq!(expected, String::from_utf8(vec).unwrap());
+
+        let spanner = VecSpanner::new().with_config(Config::new().with_fold_markers(Some(
+            FoldStyle::Custom {
+                open: "#region",
+                close: "#endregion",
+            },
+        )));
+        {
+            let mut span = spanner.enter_span("parse");
+            span.set_metric("bytes", 3.0);
+        }
+        let vec = spanner.into_inner();
+        assert_eq!(
+            "┌parse #region\n└parse (3 bytes) #endregion\n",
+            String::from_utf8(vec).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_timing() {
+        let spanner = VecSpanner::new().with_config(Config::new().with_timing(true));
+
+        {
+            let _outer = spanner.enter_span("outer");
+            let filtered = spanner.enter_with_level(Level::Trace, "filtered");
+            assert!(filtered.state.start.is_none());
+            std::thread::sleep(Duration::from_millis(2));
+        }
+
+        let vec = spanner.into_inner();
+        let output = String::from_utf8(vec).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], "┌outer");
+        assert!(lines[1].starts_with("└outer ("));
+        assert!(lines[1].ends_with("ms)"));
+
+        let render = |duration| DisplayDuration(duration).to_string();
+        assert_eq!(render(Duration::from_micros(250)), "250µs");
+        assert_eq!(render(Duration::from_micros(1_250)), "1.2ms");
+        assert_eq!(render(Duration::from_millis(2_500)), "2.50s");
+    }
+
+    #[test]
+    fn test_color() {
+        let helper = Helper {
+            spanner: VecSpanner::new().with_config(
+                Config::new().with_color(Some(|depth| u8::try_from(depth).unwrap() + 1)),
+            ),
+        };
+
+        let expected = "\x1b[38;5;1m┌Span(0)\x1b[0m
+\x1b[38;5;1m|\x1b[0m \x1b[38;5;2m Span(1)\x1b[0m
+\x1b[38;5;1m|\x1b[0m \x1b[38;5;2m Span(1)\x1b[0m
+\x1b[38;5;1m└Span(0)\x1b[0m
+";
+
+        helper.helper(0, 1);
+        let vec = helper.spanner.into_inner();
+        assert_eq!(expected, String::from_utf8(vec).unwrap());
+
+        let helper = Helper {
+            spanner: VecSpanner::new(),
+        };
+        helper.helper(0, 5);
+        let vec = helper.spanner.into_inner();
+        assert!(!vec.contains(&0x1b));
+
+        let helper = Helper {
+            spanner: VecSpanner::new().with_config(Config::rainbow()),
+        };
+        helper.helper(0, 6);
+        let output = String::from_utf8(helper.spanner.into_inner()).unwrap();
+        assert!(output.starts_with("\x1b[38;5;196m┌Span(0)\x1b[0m\n"));
+        assert!(output.contains("\x1b[38;5;196m┌Span(6)\x1b[0m\n"));
+    }
+
+    #[test]
+    fn test_color_map() {
+        let spanner =
+            VecSpanner::new().with_config(Config::new().with_color(Some(|_| 1)).with_color_map(
+                Some(|depth| match depth {
+                    0 => Color::Red,
+                    _ => Color::Fixed(208),
+                }),
+            ));
+        {
+            let _outer = spanner.enter_span("outer");
+            let _inner = spanner.enter_span("inner");
+        }
+        let expected = "\x1b[31m┌outer\x1b[0m
+\x1b[38;5;208m|  inner\x1b[0m
+\x1b[38;5;208m|  inner\x1b[0m
+\x1b[31m└outer\x1b[0m
+";
+        assert_eq!(spanner.into_string().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_auto_color() {
+        fn render(terminal: bool) -> String {
+            let mut spanner = VecSpanner::new();
+            spanner.terminal = terminal;
+            let spanner = spanner.with_config(Config::new().with_auto_color());
+            drop(spanner.enter_span("work"));
+            spanner.into_string().unwrap()
+        }
+
+        let colored = "\x1b[38;5;196m┌work\x1b[0m\n\x1b[38;5;196m└work\x1b[0m\n";
+        std::env::remove_var("NO_COLOR");
+        assert_eq!(render(true), colored);
+        assert_eq!(render(false), "┌work\n└work\n");
+        std::env::set_var("NO_COLOR", "1");
+        assert_eq!(render(true), "┌work\n└work\n");
+        std::env::set_var("NO_COLOR", "");
+        assert_eq!(render(true), colored);
+        std::env::remove_var("NO_COLOR");
+
+        // Explicitly configured colors are used regardless of the output
+        let spanner = VecSpanner::new().with_config(Config::rainbow());
+        drop(spanner.enter_span("work"));
+        assert_eq!(spanner.into_string().unwrap(), colored);
+    }
+
+    #[test]
+    fn test_indent_str() {
+        let spanner =
+            VecSpanner::new().with_config(Config::new().with_skip(1).with_indent_str("│ "));
+        fib(&spanner, 3);
+        let expected = "┌fib(3)
+│ ┌fib(2)
+│ │ ┌fib(1)
+│ │ └fib(1)
+│ │ ┌fib(0)
+│ │ └fib(0)
+│ └fib(2)
+│ ┌fib(1)
+│ └fib(1)
+└fib(3)
+";
+        assert_eq!(spanner.into_string().unwrap(), expected);
+
+        // Depths that are not displayed are indented with spaces, and `tabwidth` is ignored
+        let spanner = VecSpanner::new().with_config(
+            Config::new()
+                .with_skip(2)
+                .with_tabwidth(8)
+                .with_indent_str("│ "),
+        );
+        fib(&spanner, 2);
+        let expected = "┌fib(2)
+│  fib(1)
+│  fib(1)
+│  fib(0)
+│  fib(0)
+└fib(2)
+";
+        assert_eq!(spanner.into_string().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_enter_and_exit_lines() {
+        let spanner = VecSpanner::new().with_config(
+            Config::new()
+                .with_skip(1)
+                .with_indent_str("│ ")
+                .with_exit_lines(false),
+        );
+        fib(&spanner, 3);
+        drop(spanner.enter_span("main"));
+        assert_eq!(spanner.depth(), 0);
+        let expected = "┌fib(3)
+│ ┌fib(2)
+│ │ ┌fib(1)
+│ │ ┌fib(0)
+│ ┌fib(1)
+┌main
+";
+        assert_eq!(spanner.into_string().unwrap(), expected);
+
+        let spanner = VecSpanner::new().with_config(
+            Config::new()
+                .with_skip(1)
+                .with_indent_str("│ ")
+                .with_enter_lines(false),
+        );
+        fib(&spanner, 3);
+        let expected = "│ │ └fib(1)
+│ │ └fib(0)
+│ └fib(2)
+│ └fib(1)
+└fib(3)
+";
+        assert_eq!(spanner.into_string().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_record() {
+        let spanner = VecSpanner::new().with_config(Config::new().with_skip(1));
+        {
+            let outer = spanner.enter_span("outer");
+            outer.record("started");
+            {
+                let inner = spanner.enter_span("inner");
+                inner.record(format_args!("computed {}", 42));
+                inner.record("done");
+            }
+            let filtered = spanner.enter_with_level(Level::Trace, "filtered");
+            filtered.record("hidden");
+        }
+        let expected = "┌outer
+| · started
+| ┌inner
+| ¦ · computed 42
+| ¦ · done
+| └inner
+└outer
+";
+        assert_eq!(spanner.into_string().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_tree_connectors() {
+        let spanner = VecSpanner::new().with_config(Config::new().with_tree_connectors(true));
+        fib(&spanner, 3);
+        {
+            let _span = spanner.enter_span("main");
+            let _filtered = spanner.enter_with_level(Level::Trace, "filtered");
+            fib(&spanner, 4);
+        }
+        let expected = "fib(3)
+├─ fib(2)
+│  ├─ fib(1)
+│  └─ fib(0)
+└─ fib(1)
+main
+└─ fib(4)
+   ├─ fib(3)
+   │  ├─ fib(2)
+   │  │  ├─ fib(1)
+   │  │  └─ fib(0)
+   │  └─ fib(1)
+   └─ fib(2)
+      ├─ fib(1)
+      └─ fib(0)
+";
+        assert_eq!(spanner.into_string().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_markdown() {
+        fn fib(spanner: &VecSpanner, n: usize) -> usize {
+            let mut span = spf!(spanner, "fib({n})");
+            span.set_metric("calls", 1.0);
+            match n {
+                0 | 1 => n,
+                _ => fib(spanner, n - 1) + fib(spanner, n - 2),
+            }
+        }
+
+        let spanner = VecSpanner::new().with_config(
+            Config::new()
+                .with_format(Format::Markdown)
+                .with_timing(true),
+        );
+        fib(&spanner, 3);
+        let expected = "- fib(3)
+  - fib(2)
+    - fib(1)
+    - fib(0)
+  - fib(1)
+";
+        assert_eq!(spanner.into_string().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let spanner = VecSpanner::new().with_config(Config::new().with_record_tree(true));
+        fib(&spanner, 3);
+        drop(spanner.enter_span("say \"hi\""));
+        let dot = spanner.into_tree().to_dot();
+        assert!(dot.starts_with("digraph {\n") && dot.ends_with("}\n"));
+        assert_eq!(dot.matches("[label=").count(), 6);
+        assert_eq!(dot.matches(" -> ").count(), 4);
+        assert!(dot.contains("  n0 [label=\"fib(3)\"];\n"));
+        assert!(dot.contains("  n0 -> n1;\n  n4 [label=\"fib(1)\"];\n  n0 -> n4;\n"));
+        assert!(dot.contains("  n5 [label=\"say \\\"hi\\\"\"];\n"));
+
+        let spanner =
+            VecSpanner::new().with_config(Config::new().with_record_tree(true).with_timing(true));
+        drop(spanner.enter_span("timed"));
+        let dot = spanner.into_tree().to_dot();
+        assert!(dot.contains("  n0 [label=\"timed\\n"));
+    }
+
+    #[test]
+    fn test_html() {
+        let spanner = VecSpanner::new().with_config(Config::new().with_format(Format::Html));
+        {
+            let _outer = spanner.enter_span("parse");
+            let _inner = spanner.enter_span("Vec<u8> & \"str\"");
+            let _innermost = spanner.enter_span("lex");
+        }
+        drop(spanner.enter_span("main"));
+        spanner.write_footer();
+        let html = spanner.into_string().unwrap();
+        let expected = "<!DOCTYPE html>
+<html>
+<head><meta charset=\"utf-8\"><title>spannify</title></head>
+<body>
+<details><summary>parse</summary>
+  <details><summary>Vec&lt;u8&gt; &amp; &quot;str&quot;</summary>
+    <details><summary>lex</summary>
+    </details>
+  </details>
+</details>
+<details><summary>main</summary>
+</details>
+</body>
+</html>
+";
+        assert_eq!(html, expected);
+
+        // The nesting of the elements follows the depth of the spans
+        let mut nesting = 0;
+        for line in html.lines().filter(|line| line.contains("details>")) {
+            let indentation = line.len() - line.trim_start().len();
+            if line.trim_start().starts_with("</details>") {
+                nesting -= 1;
+                assert_eq!(indentation, 2 * nesting);
+            } else {
+                assert_eq!(indentation, 2 * nesting);
+                nesting += 1;
+            }
+        }
+        assert_eq!(nesting, 0);
+
+        let spanner = VecSpanner::new().with_config(Config::new().with_format(Format::Html));
+        spanner.write_footer();
+        let html = spanner.into_string().unwrap();
+        assert!(
+            html.starts_with("<!DOCTYPE html>\n") && html.ends_with("<body>\n</body>\n</html>\n")
+        );
+    }
+
+    #[test]
+    fn test_csv() {
+        let spanner = VecSpanner::new().with_config(Config::new().with_format(Format::Csv));
+        {
+            let _outer = spanner.enter_span("parse, then \"lex\"");
+            let _inner = spanner.enter_span("lex");
+        }
+        let csv = spanner.into_string().unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some("name,depth,enter_ts_us,exit_ts_us,duration_us")
+        );
+        let rows: Vec<_> = lines.collect();
+        assert_eq!(rows.len(), 2);
+        let (inner, outer) = (rows[0], rows[1]);
+        assert!(inner.starts_with("lex,1,"));
+        let quoted = "\"parse, then \"\"lex\"\"\",0,";
+        assert!(outer.starts_with(quoted));
+
+        // Every row has the five columns, and the duration is the difference of the timestamps
+        for row in [&inner["lex,".len()..], &outer[quoted.len() - "0,".len()..]] {
+            let columns: Vec<u128> = row.split(',').map(|c| c.parse().unwrap()).collect();
+            let [_depth, enter, exit, duration] = columns[..] else {
+                panic!("malformed row `{row}`");
+            };
+            assert!(enter <= exit);
+            assert_eq!(duration, exit - enter);
+        }
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let spanner = VecSpanner::new().with_config(Config::new().with_format(Format::JsonLines));
+
+        {
+            let _outer = spanner.enter_span("say \"hi\"");
+            let mut inner = spanner.enter_span("a\\b\n");
+            inner.set_metric("rows", 3.0);
+            inner.mark_early_return();
+        }
+
+        let expected = r#"{"event":"enter","name":"say \"hi\"","depth":0}
+{"event":"enter","name":"a\\b\n","depth":1}
+{"event":"exit","name":"a\\b\n","depth":1,"early_return":true,"metrics":{"rows":3}}
+{"event":"exit","name":"say \"hi\"","depth":0}
+"#;
+        let vec = spanner.into_inner();
+        assert_eq!(expected, String::from_utf8(vec).unwrap());
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let helper = Helper {
+            spanner: VecSpanner::new().with_config(Config::new().with_max_depth(Some(3))),
+        };
+
+        let expected = r#"┌Span(0)
+|  Span(1)
+|   ┌Span(2)
+|   ┆  … (truncated)
+|   └Span(2)
+|  Span(1)
+└Span(0)
+┌Span(0)
+|  Span(1)
+|  Span(1)
+└Span(0)
+"#;
+
+        helper.helper(0, 10);
+        assert_eq!(helper.spanner.depth.load(Ordering::Relaxed), 0);
+        helper.helper(0, 1);
+        let vec = helper.spanner.into_inner();
+        assert_eq!(expected, String::from_utf8(vec).unwrap());
+
+        let spanner = VecSpanner::new().with_config(Config::new().with_max_depth(Some(1)));
+        {
+            let _parent = spanner.enter_span("parent");
+            for _ in 0..3 {
+                let _child = spanner.enter_span("child");
+            }
+        }
+        let vec = spanner.into_inner();
+        assert_eq!(
+            "┌parent\n|  … (truncated)\n└parent\n",
+            String::from_utf8(vec).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_in_span() {
+        let spanner = VecSpanner::new();
+
+        let value = spanner.in_span("outer", || {
+            spanner.in_span_with_level(Level::Info, "inner", || String::from("value"))
+        });
+        assert_eq!(value, "value");
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            spanner.in_span("panicking", || panic!("boom"));
+        }));
+        assert!(result.is_err());
+
+        let expected = r#"┌outer
+|  inner
+|  inner
+└outer
+┌panicking
+└panicking
+"#;
+        let vec = spanner.into_inner();
+        assert_eq!(expected, String::from_utf8(vec).unwrap());
+    }
+
+    #[test]
+    fn test_macro_expression() {
+        let spanner = VecSpanner::new();
+
+        let value = spf!(spanner, "outer" => {
+            let inner = spf!(spanner, "inner" => String::from("value"));
+            inner.len()
+        });
+        assert_eq!(value, 5);
+
+        let expected = r#"┌outer
+|  inner
+|  inner
+└outer
+"#;
+        let vec = spanner.into_inner();
+        assert_eq!(expected, String::from_utf8(vec).unwrap());
+    }
+
+    #[test]
+    fn test_flush() {
+        #[derive(Default)]
+        struct FlushCounter {
+            inner: Cursor<Vec<u8>>,
+            flushes: usize,
+        }
+        impl std::io::Write for FlushCounter {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.inner.write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                self.flushes += 1;
+                Ok(())
+            }
+        }
+
+        let spanner = Spanner::from_writer(FlushCounter::default());
+        {
+            let _span = spanner.enter_span("work");
+        }
+        assert_eq!(spanner.writer.lock().unwrap().as_ref().unwrap().flushes, 0);
+
+        spanner.flush().unwrap();
+        let writer = spanner.into_inner();
+        assert_eq!(writer.flushes, 1);
+        assert_eq!(writer.inner.get_ref(), "┌work\n└work\n".as_bytes());
+    }
+
+    #[test]
+    fn test_flush_poisoned() {
+        struct FailingFlush;
+        impl std::io::Write for FailingFlush {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::BrokenPipe,
+                    "pipe closed",
+                ))
+            }
+        }
+
+        let spanner = Spanner::from_writer(FailingFlush);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _writer = spanner.writer.lock().unwrap();
+            panic!("poisons the writer lock");
+        }));
+        assert!(result.is_err());
+        assert!(spanner.writer.is_poisoned());
+
+        let err = spanner.flush().unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn test_glyphs() {
+        let helper = Helper {
+            spanner: VecSpanner::new().with_config(
+                Config::new()
+                    .with_enter_char('+')
+                    .with_exit_char('\\')
+                    .with_depthmap(|_| '|'),
+            ),
+        };
+
+        let expected = r#"+Span(0)
+|  Span(1)
+|   +Span(2)
+|   |  Span(3)
+|   |  Span(3)
+|   \Span(2)
+|  Span(1)
+\Span(0)
+"#;
+
+        helper.helper(0, 3);
+        let vec = helper.spanner.into_inner();
+        assert_eq!(expected, String::from_utf8(vec).unwrap());
+    }
+
+    #[test]
+    fn test_labels() {
+        let helper = Helper {
+            spanner: VecSpanner::new()
+                .with_config(Config::new().with_labels(Some(("entered", "dropped")))),
+        };
+
+        let expected = r#"┌Span(0) entered
+|  Span(1) entered
+|   ┌Span(2) entered
+|   └Span(2) dropped
+|  Span(1) dropped
+└Span(0) dropped
+"#;
+
+        helper.helper(0, 2);
+        let vec = helper.spanner.into_inner();
+        assert_eq!(expected, String::from_utf8(vec).unwrap());
+    }
+
+    #[test]
+    fn test_set_level() {
+        let spanner = VecSpanner::new();
+
+        {
+            let _first = spanner.enter_span("first");
+            spanner.set_level(Level::Error);
+        }
+        assert_eq!(spanner.level(), Level::Error);
+        assert_eq!(spanner.depth(), 0);
+        {
+            let _ignored = spanner.enter_span("ignored");
+            spanner.set_level(Level::Info);
+        }
+        assert_eq!(spanner.depth(), 0);
+
+        let vec = spanner.into_inner();
+        assert_eq!("┌first\n└first\n", String::from_utf8(vec).unwrap());
+    }
+
+    #[test]
+    fn test_thread_labels_shared() {
+        let spanner = VecSpanner::new().with_config(Config::new().with_thread_labels(true));
+
+        std::thread::scope(|scope| {
+            for name in ["worker-1", "worker-2"] {
+                std::thread::Builder::new()
+                    .name(name.to_owned())
+                    .spawn_scoped(scope, || {
+                        for _ in 0..10 {
+                            let _span = spanner.enter_span("work");
+                        }
+                    })
+                    .unwrap();
+            }
+        });
+
+        let vec = spanner.into_inner();
+        let output = String::from_utf8(vec).unwrap();
+        assert_eq!(output.lines().count(), 40);
+        for name in ["worker-1", "worker-2"] {
+            let tag = format!("[{name}] ");
+            assert_eq!(output.lines().filter(|l| l.starts_with(&tag)).count(), 20);
+        }
+    }
+
+    #[test]
+    fn test_shared() {
+        let spanner = VecSpanner::new().shared();
+
+        let handle = {
+            let spanner = SharedSpanner::clone(&spanner);
+            std::thread::spawn(move || {
+                let _outer = spf!(spanner, "outer");
+                let _inner = spf!(spanner, Level::Info => "inner");
+            })
+        };
+        handle.join().unwrap();
+
+        let spanner = std::sync::Arc::into_inner(spanner).unwrap();
+        let vec = spanner.into_inner();
+        assert_eq!(
+            "┌outer\n|  inner\n|  inner\n└outer\n",
+            String::from_utf8(vec).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_last_error() {
+        struct FailingWriter;
+        impl std::io::Write for FailingWriter {
+            fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::StorageFull,
+                    "disk full",
+                ))
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let spanner = Spanner::from_writer(FailingWriter);
+        assert_eq!(spanner.last_error(), None);
+        {
+            let _span = spanner.enter_span("lost");
+        }
+        assert_eq!(spanner.last_error(), Some(std::io::ErrorKind::StorageFull));
+    }
+
+    #[test]
+    fn test_max_name_width() {
+        let spanner = VecSpanner::new().with_config(Config::new().with_max_name_width(Some(4)));
+
+        {
+            let _long = spanner.enter_span("привет мир");
+            let _short = spanner.enter_span("мир");
+            let _exact = spanner.enter_span("абвг");
+        }
+
+        let expected = r#"┌при…
+|  мир
+|   ┌абвг
+|   └абвг
+|  мир
+└при…
+"#;
+        let vec = spanner.into_inner();
+        assert_eq!(expected, String::from_utf8(vec).unwrap());
+
+        let mut name = String::from("name");
+        truncate_name(&mut name, 0);
+        assert_eq!(name, "");
+    }
+
+    #[test]
+    fn test_partial_writes() {
+        #[derive(Default)]
+        struct OneByteWriter(Vec<u8>);
+        impl std::io::Write for OneByteWriter {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.extend(buf.first());
+                Ok(buf.len().min(1))
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let spanner = Spanner::from_writer(OneByteWriter(Vec::new()));
+        {
+            let _outer = spanner.enter_span("outer");
+            let _inner = spanner.enter_span("inner");
+        }
+
+        let writer = spanner.into_inner();
+        assert_eq!(
+            "┌outer\n|  inner\n|  inner\n└outer\n",
+            String::from_utf8(writer.0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_filtered_depth() {
+        let spanner = VecSpanner::new().with_config(Config::new().with_level(Level::Warn));
+
+        {
+            let _filtered = spanner.enter_with_level(Level::Debug, "filtered");
+        }
+        assert_eq!(spanner.depth(), 0);
+        {
+            let _outer = spanner.enter_with_level(Level::Warn, "outer");
+            let _inner = spanner.enter_with_level(Level::Error, "inner");
+            let _filtered = spanner.enter_with_level(Level::Info, "filtered");
+        }
+        assert_eq!(spanner.depth(), 0);
+
+        let vec = spanner.into_inner();
+        assert_eq!(
+            "┌outer\n|  inner\n|  inner\n└outer\n",
+            String::from_utf8(vec).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_skip() {
+        let helper = Helper {
+            spanner: VecSpanner::new().with_config(Config::new().with_skip(0)),
+        };
+
+        let expected = r#" Span(0)
+   Span(1)
+     Span(2)
+       Span(3)
+         Span(4)
+           Span(5)
+           Span(5)
+         Span(4)
+       Span(3)
+     Span(2)
+   Span(1)
+ Span(0)
+"#;
+
+        helper.helper(0, 5);
+        let vec = helper.spanner.into_inner();
+        assert_eq!(expected.bytes().collect::<Vec<_>>(), vec)
+    }
+
+    #[test]
+    fn test_enter_with_config() {
+        fn recurse(spanner: &VecSpanner, n: usize) {
+            let _span = spf!(spanner, "recurse({n})");
+            if n > 0 {
+                recurse(spanner, n - 1);
+            }
+        }
+
+        let spanner = VecSpanner::new().with_config(Config::new().with_skip(1));
+        {
+            let _main = spanner.enter_span("main");
+            recurse(&spanner, 1);
+            {
+                let _subtree = spanner.enter_with_config("subtree", Config::new().with_skip(4));
+                recurse(&spanner, 1);
+            }
+            recurse(&spanner, 1);
+        }
+
+        // Only the lines of `subtree` and its descendants are rendered with the other skip
+        let expected = r#"┌main
+| ┌recurse(1)
+| ¦ ┌recurse(0)
+| ¦ └recurse(0)
+| └recurse(1)
+|  subtree
+|    recurse(1)
+|      recurse(0)
+|      recurse(0)
+|    recurse(1)
+|  subtree
+| ┌recurse(1)
+| ¦ ┌recurse(0)
+| ¦ └recurse(0)
+| └recurse(1)
+└main
+"#;
+        assert_eq!(spanner.into_string().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_shared_config() {
+        let config = Arc::new(RwLock::new(Config::new().with_skip(1)));
+        let spanner = Spanner::from_writer_shared_config(Vec::new(), Arc::clone(&config));
+        {
+            let _outer = spanner.enter_span("outer");
+            // The outer span keeps rendering with the skip it was entered with
+            config.write().unwrap().skip = 0;
+            let _inner = spanner.enter_span("inner");
+            config.write().unwrap().level = Level::Warn;
+            drop(spanner.enter_span("filtered"));
+            drop(spanner.enter_with_level(Level::Warn, "warn"));
+        }
+        spanner.set_level(Level::Error);
+        assert_eq!(config.read().unwrap().level, Level::Error);
+        drop(spanner.enter_with_level(Level::Warn, "filtered"));
+
+        let expected = r#"┌outer
+   inner
+     warn
+     warn
+   inner
+└outer
+"#;
+        assert_eq!(spanner.into_inner(), expected.as_bytes());
+    }
+
+    #[test]
+    fn it_works_macros() {
+        let expected = r#"┌Span(0)
+|  Span(1)
+|   ┌Span(2)
+|   ┆  Span(3)
+|   ┆   ┌Span(4)
+|   ┆   |  Span(5)
+|   ┆   |  Span(5)
+|   ┆   └Span(4)
+|   ┆  Span(3)
+|   └Span(2)
+|  Span(1)
+└Span(0)
+"#;
+        let expected_3 = r#"┌Span({current_depth})
+|  Span({current_depth})
+|   ┌Span({current_depth})
+|   ┆  Span({current_depth})
+|   ┆   ┌Span({current_depth})
+|   ┆   |  Span({current_depth})
+|   ┆   |  Span({current_depth})
+|   ┆   └Span({current_depth})
+|   ┆  Span({current_depth})
+|   └Span({current_depth})
+|  Span({current_depth})
+└Span({current_depth})
+"#;
+
+        let helper = Helper {
+            spanner: VecSpanner::new(),
+        };
+        helper.helper_macro(0, 5);
+        let vec = helper.spanner.into_inner();
+        assert_eq!(expected.bytes().collect::<Vec<_>>(), vec);
+
+        let helper = Helper {
+            spanner: VecSpanner::new(),
+        };
+        helper.helper_macro_2(0, 5);
+        let vec = helper.spanner.into_inner();
+        assert_eq!(expected.bytes().collect::<Vec<_>>(), vec);
+
+        let helper = Helper {
+            spanner: VecSpanner::new(),
+        };
+        helper.helper_macro_3(0, 5);
+        let vec = helper.spanner.into_inner();
+        assert_eq!(expected_3.bytes().collect::<Vec<_>>(), vec);
+
+        let helper = Helper {
+            spanner: VecSpanner::new(),
+        };
+        helper.helper_macro_4(0, 5);
+        let vec = helper.spanner.into_inner();
+        assert_eq!(expected.bytes().collect::<Vec<_>>(), vec);
+    }
+
+    #[test]
+    fn test_prefix_cache() {
+        // The indentation algorithm that renders every prefix from scratch
+        fn uncached_prefix(depth: usize, cfg: &Config) -> String {
+            (0..depth).fold(String::new(), |mut acc, i| {
+                if cfg.skip != 0 && i % cfg.skip == 0 {
+                    push_colored(
+                        &mut acc,
+                        cfg.color,
+                        i,
+                        format_args!("{}", (cfg.depthmap)(i)),
+                    );
+                } else {
+                    acc.push(' ');
+                }
+                acc.extend(std::iter::repeat_n(' ', cfg.tabwidth.saturating_sub(1)));
+                acc
+            })
+        }
+
+        let configs = [
+            Config::new(),
+            Config::new().with_skip(3),
+            Config::new().with_skip(0),
+            Config::rainbow(),
+        ];
+        for cfg in configs {
+            let mut cache = RenderState::default();
+            for depth in (0..=20).chain((0..=20).rev()).chain([7, 20, 0, 13]) {
+                assert_eq!(cache.prefix(depth, &cfg), uncached_prefix(depth, &cfg));
+            }
+
+            let helper = Helper {
+                spanner: VecSpanner::new().with_config(cfg.clone()),
+            };
+            helper.helper(0, 20);
+            let output = String::from_utf8(helper.spanner.into_inner()).unwrap();
+            let lines: Vec<_> = output.lines().collect();
+            assert_eq!(lines.len(), 42);
+            for depth in 0..=20 {
+                let prefix = uncached_prefix(depth, &cfg);
+                assert!(lines[depth].starts_with(&prefix));
+                assert!(lines[41 - depth].starts_with(&prefix));
+            }
+        }
+    }
+
+    #[test]
+    fn test_regenerated_drop_message() {
+        let spanner = VecSpanner::new().with_config(
+            Config::new()
+                .with_sanitize_names(true)
+                .with_max_name_width(Some(12))
+                .with_labels(Some(("in", "out"))),
+        );
+
+        {
+            let _outer = spf!(spanner, "outer\t{}", 1);
+            let mut inner = spf!(spanner, "a rather long name {}", 2);
+            inner.mark_early_return();
+        }
+
+        let expected =
+            "┌outer\\x091 in\n|  a rather lo… in\n| ↵a rather lo… out\n└outer\\x091 out\n";
+        let vec = spanner.into_inner();
+        assert_eq!(expected, String::from_utf8(vec).unwrap());
+    }
+
+    #[test]
+    fn test_direct_writes() {
+        let configs = [
+            Config::new(),
+            Config::rainbow().with_skip(2),
+            Config::new()
+                .with_glyph_column(Some(9))
+                .with_labels(Some(("in", "out"))),
+            Config::new()
+                .with_sanitize_names(true)
+                .with_max_name_width(Some(6)),
+            Config::new().with_format(Format::Flat),
+            Config::new().with_format(Format::JsonLines),
+        ];
+        for cfg in configs {
+            let direct = Helper {
+                spanner: VecSpanner::new().with_config(cfg.clone()),
+            };
+            // Recording events routes every line through `emit`, which renders it into a `String`
+            let buffered = Helper {
+                spanner: VecSpanner::new().with_config(cfg.with_record_events(true)),
+            };
+            direct.helper(0, 6);
+            buffered.helper(0, 6);
+            assert_eq!(direct.spanner.into_inner(), buffered.spanner.into_inner());
+        }
+    }
+
+    #[test]
+    fn test_into_tree() {
+        fn shape(node: &SpanNode) -> String {
+            let children: Vec<_> = node.children.iter().map(shape).collect();
+            if children.is_empty() {
+                return node.name.clone();
+            }
+            format!("{}[{}]", node.name, children.join(" "))
+        }
+
+        let spanner =
+            VecSpanner::new().with_config(Config::new().with_record_tree(true).with_timing(true));
+        assert_eq!(fib(&spanner, 4), 3);
+
+        let tree = spanner.into_tree();
+        assert_eq!(
+            shape(&tree),
+            "[fib(4)[fib(3)[fib(2)[fib(1) fib(0)] fib(1)] fib(2)[fib(1) fib(0)]]]"
+        );
+        assert!(tree.duration.is_none());
+        assert!(tree.children[0].duration.is_some());
+
+        let spanner = VecSpanner::new();
+        drop(spanner.enter_span("untracked"));
+        assert_eq!(spanner.into_tree(), SpanNode::default());
+    }
+
+    #[test]
+    fn test_buffered_file() {
+        fn recurse<T: std::io::Write>(spanner: &Spanner<T>, depth: usize) {
+            let _span = spf!(spanner, "Span({depth})");
+            if depth < 4 {
+                recurse(spanner, depth + 1);
+            }
+        }
+
+        let path = std::env::temp_dir().join(format!("spannify-buffered-{}", std::process::id()));
+        let spanner: BufferedFileSpanner =
+            FileSpanner::buffered(std::fs::File::create(&path).unwrap());
+        recurse(&spanner, 0);
+        assert_eq!(std::fs::read(&path).unwrap(), b"");
+        spanner.flush().unwrap();
+
+        let expected = VecSpanner::new();
+        recurse(&expected, 0);
+        assert_eq!(std::fs::read(&path).unwrap(), expected.into_inner());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_span_builder() {
+        let spanner = VecSpanner::new();
+        {
+            let _query = spanner
+                .span_builder("query")
+                .with_field("rows", 42)
+                .with_field("db", "main")
+                .enter();
+            let _plain = spanner.span_builder("plain").enter();
+            let _filtered = spanner
+                .span_builder("filtered")
+                .with_level(Level::Trace)
+                .with_field("rows", 0)
+                .enter();
+        }
+
+        let expected = r#"┌query {rows=42 db=main}
+|  plain
+|  plain
+└query {rows=42 db=main}
+"#;
+        let vec = spanner.into_inner();
+        assert_eq!(expected, String::from_utf8(vec).unwrap());
+    }
+
+    #[test]
+    fn test_level_macros() {
+        let spanner = VecSpanner::new().with_config(Config::new().with_level(Level::Debug));
+        let token = "fn";
+        {
+            let _trace = spf_trace!(spanner, "trace {token:?}");
+            let _debug = spf_debug!(spanner, "parse {token:?}");
+            let _warn = spf_warn!(spanner => "warn");
+            let _error = spf_error!(spanner, "error {}", 1);
+        }
+
+        let expected = r#"┌parse "fn"
+|  warn
+|   ┌error 1
+|   └error 1
+|  warn
+└parse "fn"
+"#;
+        let vec = spanner.into_inner();
+        assert_eq!(expected, String::from_utf8(vec).unwrap());
+    }
+
+    #[test]
+    fn test_enter_display() {
+        enum Phase {
+            Parse,
+        }
+
+        impl std::fmt::Display for Phase {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                match self {
+                    Self::Parse => f.write_str("parse"),
+                }
+            }
+        }
+
+        let spanner = VecSpanner::new();
+        {
+            let _phase = spanner.enter_display(Level::Info, Phase::Parse);
+            let _number = spanner.enter_display(Level::Info, 7_u64);
+            let _owned = spanner.enter_display(Level::Info, String::from("owned"));
+            let _filtered = spanner.enter_display(Level::Debug, 0);
+        }
+
+        let expected = r#"┌parse
+|  7
+|   ┌owned
+|   └owned
+|  7
+└parse
+"#;
+        let vec = spanner.into_inner();
+        assert_eq!(expected, String::from_utf8(vec).unwrap());
+    }
+
+    #[test]
+    fn test_enter_lazy() {
+        let spanner = VecSpanner::new().with_config(Config::new().with_level(Level::Debug));
+        let calls = std::cell::Cell::new(0);
+        let name = |i: usize| {
+            calls.set(calls.get() + 1);
+            format!("item {i}")
+        };
+        {
+            let _trace = spanner.enter_lazy(Level::Trace, || name(0));
+            let _debug = spanner.enter_lazy(Level::Debug, || name(1));
+            let _macro_trace = spf!(spanner, Level::Trace, || name(2));
+            let _macro_info = spf!(spanner, Level::Info, || name(3));
+        }
+        assert_eq!(calls.get(), 2);
+
+        let expected = r#"┌item 1
+|  item 3
+|  item 3
+└item 1
+"#;
+        let vec = spanner.into_inner();
+        assert_eq!(expected, String::from_utf8(vec).unwrap());
+    }
+
+    #[test]
+    fn test_tabwidth() {
+        let helper = Helper {
+            spanner: VecSpanner::new().with_config(Config::new().with_tabwidth(4)),
+        };
+        helper.helper(0, 2);
+        let expected = r#"┌Span(0)
+|    Span(1)
+|       ┌Span(2)
+|       └Span(2)
+|    Span(1)
+└Span(0)
+"#;
+        let vec = helper.spanner.into_inner();
+        assert_eq!(expected, String::from_utf8(vec).unwrap());
+
+        let helper = Helper {
+            spanner: VecSpanner::new()
+                .with_config(Config::new().with_tabwidth(0).with_glyph_column(Some(2))),
+        };
+        helper.helper(0, 2);
+        let expected = r#"  ┌Span(0)
+   Span(1)
+  ┌Span(2)
+  └Span(2)
+   Span(1)
+  └Span(0)
+"#;
+        let vec = helper.spanner.into_inner();
+        assert_eq!(expected, String::from_utf8(vec).unwrap());
+    }
+
+    #[test]
+    fn test_large_tabwidth() {
+        let helper = Helper {
+            spanner: VecSpanner::new().with_config(Config::new().with_tabwidth(usize::MAX)),
+        };
+        helper.helper(0, 2);
+        let pad = " ".repeat(MAX_TABWIDTH - 1);
+        let expected = format!(
+            "┌Span(0)\n|{pad} Span(1)\n|{pad} {pad}┌Span(2)\n|{pad} {pad}└Span(2)\n|{pad} Span(1)\n└Span(0)\n"
+        );
+        let vec = helper.spanner.into_inner();
+        assert_eq!(expected, String::from_utf8(vec).unwrap());
+    }
+
+    #[test]
+    fn test_chrome_trace() {
+        let helper = Helper {
+            spanner: VecSpanner::new().with_config(
+                Config::new()
+                    .with_format(Format::ChromeTrace)
+                    .with_max_depth(Some(3))
+                    .with_fold_markers(Some(FoldStyle::Vim)),
+            ),
+        };
+        helper.helper(0, 4);
+        {
+            let mut span = helper.spanner.enter_span("quoted \"name\"");
+            span.set_metric("rows", 1.0);
+            span.mark_early_return();
+        }
+        helper.spanner.write_footer();
+
+        let trace = helper.spanner.into_inner();
+        let events: Vec<serde_json::Value> = serde_json::from_slice(&trace).unwrap();
+        assert_eq!(events.len(), 8);
+        let mut open = Vec::new();
+        let mut last_ts = 0;
+        for event in &events {
+            let name = event["name"].as_str().unwrap();
+            let ts = event["ts"].as_u64().unwrap();
+            assert!(ts >= last_ts);
+            last_ts = ts;
+            assert_eq!(event["pid"], std::process::id());
+            assert_eq!(event["tid"], events[0]["tid"]);
+            match event["ph"].as_str().unwrap() {
+                "B" => open.push(name),
+                "E" => assert_eq!(open.pop(), Some(name)),
+                ph => panic!("unexpected phase {ph}"),
+            }
+        }
+        assert!(open.is_empty());
+        assert_eq!(events[6]["name"], "quoted \"name\"");
+
+        let spanner = VecSpanner::new().with_config(Config::new().with_format(Format::ChromeTrace));
+        spanner.write_footer();
+        let trace = spanner.into_inner();
+        assert!(serde_json::from_slice::<Vec<serde_json::Value>>(&trace)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_exit() {
+        let spanner = VecSpanner::new();
+        {
+            let _outer = spanner.enter_span("outer");
+            let first = spanner.enter_span("first");
+            first.exit();
+            assert_eq!(spanner.depth(), 1);
+            let _second = spanner.enter_span("second");
+        }
+        assert_eq!(spanner.depth(), 0);
+
+        let expected = "┌outer\n|  first\n|  first\n|  second\n|  second\n└outer\n";
+        let output = String::from_utf8(spanner.into_inner()).unwrap();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_disarm() {
+        let spanner = VecSpanner::new();
+        {
+            let _outer = spanner.enter_span("outer");
+            {
+                let mut span = spanner.enter_span("disarmed");
+                span.disarm();
+                let _inner = spanner.enter_span("inner");
+            }
+            let _sibling = spanner.enter_span("sibling");
+        }
+        assert_eq!(spanner.depth(), 0);
+
+        let expected =
+            "┌outer\n|  disarmed\n|   ┌inner\n|   └inner\n|  sibling\n|  sibling\n└outer\n";
+        let output = String::from_utf8(spanner.into_inner()).unwrap();
+        assert_eq!(output, expected);
+
+        let spanner =
+            VecSpanner::new().with_config(Config::new().with_format(Format::FoldedStacks));
+        {
+            let _outer = spanner.enter_span("outer");
+            let mut span = spanner.enter_span("disarmed");
+            span.disarm();
+        }
+        let output = String::from_utf8(spanner.into_inner()).unwrap();
+        assert!(output.starts_with("outer;disarmed "));
+        assert_eq!(output.lines().count(), 2);
+    }
+
+    #[test]
+    fn test_clone_span() {
+        let spanner = VecSpanner::new();
+        {
+            let outer = spanner.enter_span("outer");
+            {
+                let _clone = outer.clone();
+                let _inner = spanner.enter_span("inner");
+            }
+            let _sibling = spanner.enter_span("sibling");
+        }
+        assert_eq!(spanner.depth(), 0);
+
+        let expected = "┌outer\n|  inner\n|  inner\n|  sibling\n|  sibling\n└outer\n";
+        let output = String::from_utf8(spanner.into_inner()).unwrap();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_thread_local_depth() {
+        let spanner = VecSpanner::new().with_config(
+            Config::new()
+                .with_thread_local_depth(true)
+                .with_thread_labels(true),
+        );
+        let barrier = std::sync::Barrier::new(2);
+        std::thread::scope(|scope| {
+            for name in ["first", "second"] {
+                let (spanner, barrier) = (&spanner, &barrier);
+                std::thread::Builder::new()
+                    .name(name.to_owned())
+                    .spawn_scoped(scope, move || {
+                        let _outer = spanner.enter_span("outer");
+                        // Both threads are inside their outer span before either enters the
+                        // inner one
+                        barrier.wait();
+                        assert_eq!(spanner.depth(), 1);
+                        let _inner = spanner.enter_span("inner");
+                        barrier.wait();
+                    })
+                    .unwrap();
+            }
+        });
+        assert_eq!(spanner.depth(), 0);
+
+        let output = String::from_utf8(spanner.into_inner()).unwrap();
+        for name in ["first", "second"] {
+            let label = format!("[{name}] ");
+            let lines: Vec<_> = output
+                .lines()
+                .filter_map(|line| line.strip_prefix(&label))
+                .collect();
+            assert_eq!(lines, ["┌outer", "|  inner", "|  inner", "└outer"]);
+        }
+    }
+
+    #[test]
+    fn test_line_atomic_writes() {
+        /// A writer shared between spanners, which is only locked for a single write.
+        #[derive(Clone, Default)]
+        struct SharedWriter(std::sync::Arc<Mutex<Vec<u8>>>);
+        impl std::io::Write for SharedWriter {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let writer = SharedWriter::default();
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                let spanner = Spanner::from_writer(writer.clone())
+                    .with_config(Config::new().with_timing(true));
+                scope.spawn(move || {
+                    for i in 0..200 {
+                        let _outer = spf!(spanner, "outer({i})");
+                        let _inner = spf!(spanner, "inner({i})");
+                    }
+                });
+            }
+        });
+
+        let output = String::from_utf8(writer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(output.lines().count(), 4 * 200 * 4);
+        for line in output.lines() {
+            let valid = line.starts_with("┌outer(")
+                || line.starts_with("|  inner(")
+                || line.starts_with("└outer(");
+            assert!(valid, "garbled line {line:?}");
+            assert_eq!(line.matches("er(").count(), 1, "garbled line {line:?}");
+        }
+    }
+
+    #[test]
+    fn test_reset() {
+        let spanner = VecSpanner::new().with_config(Config::new().with_max_depth(Some(1)));
+        let mut traces = Vec::new();
+        for _ in 0..2 {
+            {
+                let _outer = spanner.enter_span("outer");
+                std::mem::forget(spanner.enter_span("leaked"));
+            }
+            assert_eq!(spanner.depth(), 1);
+            traces.push(spanner.with_contents(<[u8]>::to_vec));
+            spanner.reset();
+            spanner.clear();
+            assert_eq!(spanner.depth(), 0);
+        }
+        assert_eq!(traces[0], traces[1]);
+        assert_eq!(traces[0], "┌outer\n|  … (truncated)\n└outer\n".as_bytes());
+
+        // The held enter line of the abandoned trace is not flushed into the next one
+        let spanner = VecSpanner::new().with_config(
+            Config::new()
+                .with_compact_leaves(true)
+                .with_record_tree(true),
+        );
+        std::mem::forget(spanner.enter_span("leaked"));
+        spanner.reset();
+        drop(spanner.enter_span("fresh"));
+        let output = spanner.with_contents(|output| String::from_utf8(output.to_vec()).unwrap());
+        assert_eq!(output, "─fresh\n");
+        let tree = spanner.into_tree();
+        assert_eq!(tree.children.len(), 2);
+        assert!(tree.children[1].children.is_empty());
+    }
+
+    #[test]
+    fn test_themes() {
+        let render = |theme| {
+            let helper = Helper {
+                spanner: VecSpanner::new()
+                    .with_config(Config::new().with_skip(1).with_theme(theme)),
+            };
+            helper.helper(0, 2);
+            helper.spanner.into_string().unwrap()
+        };
+
+        let ascii = render(Theme::Ascii);
+        let rounded = render(Theme::Rounded);
+        assert_eq!(
+            ascii,
+            "+Span(0)\n| +Span(1)\n| | +Span(2)\n| | +Span(2)\n| +Span(1)\n+Span(0)\n"
+        );
+        assert_eq!(
+            rounded,
+            "╭Span(0)\n│ ╭Span(1)\n│ │ ╭Span(2)\n│ │ ╰Span(2)\n│ ╰Span(1)\n╰Span(0)\n"
+        );
+    }
+
+    #[test]
+    fn test_name_column() {
+        let spanner = VecSpanner::new().with_config(
+            Config::new()
+                .with_skip(1)
+                .with_timing(true)
+                .with_name_column(Some(12))
+                .with_glyph_column(Some(3)),
+        );
+        {
+            let _a = spanner.enter_span("a");
+            let _bb = spanner.enter_span("bb");
+            let _ccc = spanner.enter_span("ccc");
+            let _overflowing = spanner.enter_span("overflowing name");
+        }
+
+        let output = spanner.into_string().unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines[0], "   ┌a       ");
+        assert_eq!(lines[3], "| ¦ ┆ ┌overflowing name");
+        assert!(lines[4].starts_with("| ¦ ┆ └overflowing name ("));
+        for line in &lines[5..] {
+            let column = line.chars().position(|c| c == '(').unwrap();
+            assert_eq!(column, 13, "misaligned line {line:?}");
+        }
+    }
+
+    #[test]
+    fn test_location() {
+        let spanner = VecSpanner::new().with_config(Config::new().with_location(true));
+        let line = line!() + 2;
+        {
+            let _span = spf!(spanner, "macro");
+            let _method = spanner.enter_span("method");
+            spanner.in_span("closure", || {});
+        }
 
-    ($spa:expr => $arg:literal) => {{
-        let span = $spa.enter_span($arg);
-        span
-    }};
+        let output = spanner.into_string().unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines[0], format!("┌macro (src/core.rs:{line})"));
+        assert_eq!(lines[1], format!("|  method (src/core.rs:{})", line + 1));
+        assert_eq!(lines[2], format!("|   ┌closure (src/core.rs:{})", line + 2));
+        assert_eq!(lines[5], "└macro");
+    }
 
-    ($spa:expr, $level:path, $($arg:tt)*) => {{
-        let span = $spa.enter_args($level, format_args!($($arg)*));
-        span
-    }};
+    mod parser {
+        use crate::core::VecSpanner;
+        use crate::level::Level;
 
-    ($spa:expr, $($arg:tt)*) => {{
-        let span = $spa.enter_args($crate::level::Level::Info, format_args!($($arg)*));
-        span
-    }};
-}
+        pub fn parse(spanner: &VecSpanner, input: &str) {
+            let _span = crate::spf_located!(spanner, "parse({input})");
+            let _lex = crate::spf_located!(spanner, Level::Warn => "lex");
+        }
+    }
 
-#[cfg(test)]
-mod tests {
-    use std::io::Cursor;
+    #[test]
+    fn test_spf_located() {
+        let spanner = VecSpanner::new();
+        parser::parse(&spanner, "1 + 2");
+        drop(crate::spf_located!(spanner => "main"));
 
-    use super::*;
+        let output = spanner.into_string().unwrap();
+        let expected = "┌spannify::core::tests::parser::parse(1 + 2)
+|  spannify::core::tests::parser::lex
+|  spannify::core::tests::parser::lex
+└spannify::core::tests::parser::parse(1 + 2)
+┌spannify::core::tests::main
+└spannify::core::tests::main
+";
+        assert_eq!(output, expected);
+    }
 
-    struct Helper<T>
-    where
-        T: std::io::Write,
-    {
-        spanner: Spanner<T>,
+    #[test]
+    fn test_target_levels() {
+        let spanner = VecSpanner::new().with_config(
+            Config::new()
+                .with_target_level("parser", Level::Debug)
+                .with_target_level("lexer", Level::Warn),
+        );
+        {
+            let _parse = spanner.enter_target("parser", Level::Debug, "parse");
+            let _tokens = spanner.enter_target("lexer", Level::Info, "tokens");
+            let _lex = spanner.enter_target("lexer", Level::Warn, "lex");
+            let _trace = spanner.enter_target("parser", Level::Trace, "trace");
+            let _codegen = spanner.enter_target("codegen", Level::Debug, "codegen");
+            let _emit = spanner.enter_target("codegen", Level::Info, "emit");
+        }
+
+        let expected = "┌parse\n|  lex\n|   ┌emit\n|   └emit\n|  lex\n└parse\n";
+        assert_eq!(spanner.into_string().unwrap(), expected);
     }
-    impl<T> Helper<T>
-    where
-        T: std::io::Write,
-    {
-        fn helper(&self, current_depth: usize, target_depth: usize) {
-            let _span = self
-                .spanner
-                .enter_span(format!("Span({})", current_depth).as_ref());
 
-            if current_depth >= target_depth {
-                return;
+    #[test]
+    fn test_name_filter() {
+        fn fib(spanner: &VecSpanner, n: usize) -> usize {
+            let _span = spf!(spanner, "fib({n})");
+            let _add = spf!(spanner => "add");
+            match n {
+                0 | 1 => n,
+                _ => fib(spanner, n - 1) + fib(spanner, n - 2),
             }
-            return self.helper(current_depth + 1, target_depth);
         }
 
-        fn helper_macro(&self, current_depth: usize, target_depth: usize) {
-            let _span = spf!(self.spanner, "Span({current_depth})");
+        let focus = String::from("fib");
+        let spanner = VecSpanner::new()
+            .with_config(Config::new().with_name_filter(move |name| name.contains(focus.as_str())));
+        {
+            let _main = spanner.enter_span("main");
+            assert_eq!(spanner.depth(), 0);
+            assert_eq!(fib(&spanner, 2), 1);
+        }
 
-            if current_depth >= target_depth {
-                return;
-            }
-            return self.helper_macro(current_depth + 1, target_depth);
+        let expected = "┌fib(2)\n|  fib(1)\n|  fib(1)\n|  fib(0)\n|  fib(0)\n└fib(2)\n";
+        assert_eq!(spanner.into_string().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_depthmap_str() {
+        let spanner = VecSpanner::new().with_config(
+            Config::new()
+                .with_tabwidth(3)
+                .with_skip(1)
+                .with_name_column(Some(12))
+                .with_labels(Some(("in", "out")))
+                .with_depthmap_str(Some(|depth| if depth % 2 == 0 { "||" } else { "::" })),
+        );
+        {
+            let _outer = spanner.enter_span("outer");
+            let _inner = spanner.enter_span("inner");
+            let _leaf = spanner.enter_span("leaf");
         }
 
-        fn helper_macro_2(&self, current_depth: usize, target_depth: usize) {
-            let _span = spf!(self.spanner, Level::Info, "Span({current_depth})");
+        let expected = "┌outer       in
+|| ┌inner    in
+|| :: ┌leaf  in
+|| :: └leaf  out
+|| └inner    out
+└outer       out
+";
+        assert_eq!(spanner.into_string().unwrap(), expected);
 
-            if current_depth >= target_depth {
-                return;
-            }
-            return self.helper_macro_2(current_depth + 1, target_depth);
+        // Guides wider than the tab width are cut to it
+        let spanner = VecSpanner::new().with_config(
+            Config::new()
+                .with_tabwidth(1)
+                .with_skip(1)
+                .with_depthmap_str(Some(|_| "||")),
+        );
+        {
+            let _outer = spanner.enter_span("outer");
+            let _inner = spanner.enter_span("inner");
         }
+        assert_eq!(
+            spanner.into_string().unwrap(),
+            "┌outer\n|┌inner\n|└inner\n└outer\n"
+        );
+    }
 
-        fn helper_macro_3(&self, current_depth: usize, target_depth: usize) {
-            let _span = spf!(self.spanner => "Span({current_depth})");
+    #[cfg(feature = "unicode-width")]
+    #[test]
+    fn test_unicode_width() {
+        let spanner = VecSpanner::new().with_config(
+            Config::new()
+                .with_name_column(Some(10))
+                .with_max_name_width(Some(5))
+                .with_labels(Some(("in", "out"))),
+        );
+        {
+            let _outer = spanner.enter_span("解析");
+            let _inner = spanner.enter_span("parse");
+            let _truncated = spanner.enter_span("中文字符");
+        }
 
-            if current_depth >= target_depth {
-                return;
+        let expected = "┌解析      in
+|  parse   in
+|   ┌中文… in
+|   └中文… out
+|  parse   out
+└解析      out
+";
+        assert_eq!(spanner.into_string().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_with_writer_and_config() {
+        let spanner =
+            Spanner::with_writer_and_config(Vec::new(), Config::new().with_skip(3)).unwrap();
+        drop(spanner.enter_span("valid"));
+        assert_eq!(spanner.config.skip, 3);
+        assert_eq!(spanner.into_string().unwrap(), "┌valid\n└valid\n");
+
+        let config = Config::new()
+            .with_glyph_column(Some(8))
+            .with_name_column(Some(4));
+        let err = Spanner::with_writer_and_config(Vec::new(), config).unwrap_err();
+        assert_eq!(
+            err,
+            ConfigError::NameColumnBeforeGlyph {
+                name_column: 4,
+                glyph_column: 8
             }
-            return self.helper_macro_3(current_depth + 1, target_depth);
+        );
+        assert_eq!(
+            err.to_string(),
+            "name column 4 is not right of glyph column 8"
+        );
+    }
+
+    #[test]
+    fn test_top_level_separator() {
+        let spanner = VecSpanner::new().with_config(Config::new().with_top_level_separator(true));
+        for name in ["first", "second", "third"] {
+            let _span = spanner.enter_span(name);
+            let _child = spanner.enter_span("child");
         }
 
-        fn helper_macro_4(&self, current_depth: usize, target_depth: usize) {
-            let _span = spf!(self.spanner, "Span({})", current_depth);
+        let expected = "┌first\n|  child\n|  child\n└first\n\n┌second\n|  child\n|  child\n└second\n\n┌third\n|  child\n|  child\n└third\n";
+        let output = spanner.into_string().unwrap();
+        assert_eq!(output, expected);
+        assert_eq!(output.matches("\n\n").count(), 2);
+    }
 
-            if current_depth >= target_depth {
-                return;
-            }
-            return self.helper_macro_2(current_depth + 1, target_depth);
+    #[test]
+    fn test_summary() {
+        let mut trace = Cursor::new(Vec::new());
+        {
+            let spanner = Spanner::from_writer(&mut trace)
+                .with_config(Config::new().with_summary(true).with_level(Level::Info));
+            drop(spanner.enter_with_level(Level::Debug, "filtered"));
+            drop(spanner.enter_span("single"));
         }
+        assert_eq!(
+            String::from_utf8(trace.into_inner()).unwrap(),
+            "┌single\n└single\n— 1 span, max depth 1 —\n"
+        );
+
+        let spanner = VecSpanner::new().with_config(Config::new().with_summary(true));
+        fib(&spanner, 4);
+        let output = spanner.into_string().unwrap();
+        assert!(output.ends_with("└fib(4)\n— 9 spans, max depth 4 —\n"));
+        assert_eq!(output.matches('—').count(), 2);
     }
 
     #[test]
-    fn it_works() {
-        let helper = Helper {
-            spanner: VecSpanner::new(),
-        };
+    fn test_into_inner() {
+        let config = Config::new().with_collapse_repeats(true).with_summary(true);
+        let spanner =
+            Spanner::from_writer(std::io::BufWriter::new(Vec::new())).with_config(config.clone());
+        let expected = VecSpanner::new().with_config(config);
+        for _ in 0..3 {
+            drop(spanner.enter_span("repeat"));
+            drop(expected.enter_span("repeat"));
+        }
 
-        let expected = r#"┌Span(0)
-|  Span(1)
-|   ┌Span(2)
-|   ┆  Span(3)
-|   ┆   ┌Span(4)
-|   ┆   |  Span(5)
-|   ┆   |  Span(5)
-|   ┆   └Span(4)
-|   ┆  Span(3)
-|   └Span(2)
-|  Span(1)
-└Span(0)
-"#;
+        let vec = spanner.into_inner().into_inner().unwrap();
+        let output = String::from_utf8(vec).unwrap();
+        assert!(output.ends_with("— 3 spans, max depth 1 —\n"));
+        assert_eq!(output, expected.into_string().unwrap());
+    }
 
-        helper.helper(0, 5);
-        let vec = helper.spanner.writer.into_inner().unwrap();
-        assert_eq!(expected.bytes().collect::<Vec<_>>(), vec);
+    #[test]
+    fn test_newline() {
+        let spanner = VecSpanner::new().with_config(
+            Config::new()
+                .with_newline("\r\n")
+                .with_summary(true)
+                .with_overview(true)
+                .with_top_level_separator(true),
+        );
+        for name in ["first", "second"] {
+            let _span = spanner.enter_span(name);
+            let _child = spanner.enter_span("child");
+        }
+        spanner.write_overview();
+        let output = spanner.into_string().unwrap();
+        assert_eq!(
+            output,
+            "┌first\r\n|  child\r\n|  child\r\n└first\r\n\r\n┌second\r\n|  child\r\n|  child\r\n\
+             └second\r\noverview: 0 1 0 1\r\n— 4 spans, max depth 2 —\r\n"
+        );
+        assert_eq!(output.matches('\n').count(), output.matches("\r\n").count());
 
-        let helper = Helper {
-            spanner: VecSpanner::new(),
-        };
-        helper.helper_macro(0, 5);
-        let vec = helper.spanner.writer.into_inner().unwrap();
-        assert_eq!(expected.bytes().collect::<Vec<_>>(), vec)
+        let spanner = VecSpanner::new().with_config(
+            Config::new()
+                .with_newline("\r\n")
+                .with_format(Format::ChromeTrace),
+        );
+        drop(spanner.enter_span("main"));
+        spanner.write_footer();
+        let output = spanner.into_string().unwrap();
+        assert!(output.starts_with("[\r\n") && output.ends_with("}\r\n]\r\n"));
+        assert_eq!(output.matches('\n').count(), output.matches("\r\n").count());
     }
 
     #[test]
-    fn test_config() {
-        let helper = Helper {
-            spanner: VecSpanner::new().with_config(Config::new().with_skip(3)),
-        };
+    fn test_indent_offset() {
+        let spanner = VecSpanner::new().with_config(
+            Config::new()
+                .with_indent_offset(4)
+                .with_summary(true)
+                .with_depth_warning(Some(1)),
+        );
+        {
+            let _outer = spanner.enter_span("outer");
+            let _inner = spanner.enter_span("inner");
+        }
+        let output = spanner.into_string().unwrap();
+        assert_eq!(
+            output,
+            "    ┌outer\n    ⚠ depth 1 exceeded\n    |  inner\n    |  inner\n    └outer\n    \
+             — 2 spans, max depth 2 —\n"
+        );
+        assert!(output.lines().all(|line| line.starts_with("    ")));
 
-        let expected = r#"┌Span(0)
-|  Span(1)
-|    Span(2)
-|     ┌Span(3)
-|     ┊  Span(4)
-|     ┊    Span(5)
-|     ┊    Span(5)
-|     ┊  Span(4)
-|     └Span(3)
-|    Span(2)
-|  Span(1)
-└Span(0)
-"#;
+        let spanner = VecSpanner::new().with_config(
+            Config::new()
+                .with_indent_offset(4)
+                .with_format(Format::ChromeTrace),
+        );
+        drop(spanner.enter_span("main"));
+        spanner.write_footer();
+        let output = spanner.into_string().unwrap();
+        assert_eq!(output.lines().count(), 4);
+        assert!(output.lines().all(|line| line.starts_with("    ")));
+    }
 
-        helper.helper(0, 5);
-        let vec = helper.spanner.writer.into_inner().unwrap();
-        assert_eq!(expected.bytes().collect::<Vec<_>>(), vec)
+    #[test]
+    fn test_line_prefix() {
+        let spanner = VecSpanner::new().with_config(
+            Config::new()
+                .with_line_prefix("SPAN | ")
+                .with_indent_offset(2)
+                .with_summary(true),
+        );
+        {
+            let _outer = spanner.enter_span("outer");
+            let _inner = spanner.enter_span("inner");
+        }
+        let output = spanner.into_string().unwrap();
+        assert_eq!(
+            output,
+            "SPAN |   ┌outer\nSPAN |   |  inner\nSPAN |   |  inner\nSPAN |   └outer\n\
+             SPAN |   — 2 spans, max depth 2 —\n"
+        );
+        assert!(output.lines().all(|line| line.starts_with("SPAN | ")));
     }
 
     #[test]
-    fn test_level() {
-        let mut writer = Cursor::new(Vec::new());
-        let spanner = Spanner::from_writer(&mut writer)
-            .with_config(Config::new().with_skip(3).with_level(Level::Info));
+    fn test_max_depth_threads() {
+        let spanner = VecSpanner::new()
+            .with_config(Config::new().with_thread_local_depth(true))
+            .shared();
+        let handles: Vec<_> = (1..=4)
+            .map(|depth| {
+                let spanner = spanner.clone();
+                std::thread::spawn(move || {
+                    let spans: Vec<_> = (0..depth).map(|_| spanner.enter_span("span")).collect();
+                    drop(spans);
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(spanner.max_depth(), 4);
+    }
 
-        {
-            let _span = spanner.enter_with_level(Level::Debug, "foo");
-            // Span is dropped here
+    #[test]
+    fn test_depth_warning() {
+        fn recurse(spanner: &VecSpanner, n: usize) {
+            let _span = spf!(spanner, "recurse({n})");
+            if n > 0 {
+                recurse(spanner, n - 1);
+            }
         }
 
-        assert_eq!(writer.get_ref(), &[]);
+        let spanner = VecSpanner::new().with_config(
+            Config::new()
+                .with_depth_warning(Some(2))
+                .with_max_depth(Some(4)),
+        );
+        recurse(&spanner, 5);
+        let output = spanner.into_string().unwrap();
+        assert_eq!(output.matches("⚠ depth 2 exceeded").count(), 1);
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines[2], "⚠ depth 2 exceeded");
+        assert_eq!(lines[3], "|   ┌recurse(3)");
+
+        // The warning is written again after the depth dropped back to the threshold
+        let spanner = VecSpanner::new().with_config(Config::new().with_depth_warning(Some(2)));
+        recurse(&spanner, 3);
+        recurse(&spanner, 3);
+        let output = spanner.into_string().unwrap();
+        assert_eq!(output.matches("⚠ depth 2 exceeded").count(), 2);
     }
 
     #[test]
-    fn test_skip() {
-        let helper = Helper {
-            spanner: VecSpanner::new().with_config(Config::new().with_skip(0)),
-        };
+    fn test_compact_leaves() {
+        fn fib(spanner: &VecSpanner, n: usize) -> usize {
+            let _span = spf!(spanner, "fib({n})");
+            let _filtered = spanner.enter_with_level(Level::Trace, "filtered");
+            match n {
+                0 | 1 => n,
+                _ => fib(spanner, n - 1) + fib(spanner, n - 2),
+            }
+        }
 
-        let expected = r#" Span(0)
-   Span(1)
-     Span(2)
-       Span(3)
-         Span(4)
-           Span(5)
-           Span(5)
-         Span(4)
-       Span(3)
-     Span(2)
-   Span(1)
- Span(0)
-"#;
+        let normal = VecSpanner::new();
+        fib(&normal, 3);
+        let expected = "┌fib(3)
+|  fib(2)
+|   ┌fib(1)
+|   └fib(1)
+|   ┌fib(0)
+|   └fib(0)
+|  fib(2)
+|  fib(1)
+|  fib(1)
+└fib(3)
+";
+        assert_eq!(normal.into_string().unwrap(), expected);
 
-        helper.helper(0, 5);
-        let vec = helper.spanner.writer.into_inner().unwrap();
-        assert_eq!(expected.bytes().collect::<Vec<_>>(), vec)
+        let compact = VecSpanner::new().with_config(Config::new().with_compact_leaves(true));
+        fib(&compact, 3);
+        let expected = "┌fib(3)
+|  fib(2)
+|   ─fib(1)
+|   ─fib(0)
+|  fib(2)
+|  fib(1)
+└fib(3)
+";
+        assert_eq!(compact.into_string().unwrap(), expected);
     }
 
     #[test]
-    fn it_works_macros() {
-        let expected = r#"┌Span(0)
-|  Span(1)
-|   ┌Span(2)
-|   ┆  Span(3)
-|   ┆   ┌Span(4)
-|   ┆   |  Span(5)
-|   ┆   |  Span(5)
-|   ┆   └Span(4)
-|   ┆  Span(3)
-|   └Span(2)
-|  Span(1)
-└Span(0)
-"#;
-        let expected_3 = r#"┌Span({current_depth})
-|  Span({current_depth})
-|   ┌Span({current_depth})
-|   ┆  Span({current_depth})
-|   ┆   ┌Span({current_depth})
-|   ┆   |  Span({current_depth})
-|   ┆   |  Span({current_depth})
-|   ┆   └Span({current_depth})
-|   ┆  Span({current_depth})
-|   └Span({current_depth})
-|  Span({current_depth})
-└Span({current_depth})
-"#;
+    fn test_collapse_repeats() {
+        let spanner = VecSpanner::new().with_config(Config::new().with_collapse_repeats(true));
+        {
+            let _outer = spanner.enter_span("outer");
+            for _ in 0..4 {
+                let _repeat = spanner.enter_span("fib(2)");
+                let _inner = spanner.enter_span("fib(1)");
+            }
+            drop(spanner.enter_span("fib(3)"));
+            drop(spanner.enter_span("fib(2)"));
+        }
+        drop(spanner.enter_span("outer"));
+        let expected = "┌outer
+|  fib(2)
+|   ┌fib(1)
+|   └fib(1)
+|  fib(2) ×4
+|  fib(3)
+|  fib(3)
+|  fib(2)
+|  fib(2)
+└outer ×2
+";
+        assert_eq!(spanner.into_string().unwrap(), expected);
+    }
 
-        let helper = Helper {
-            spanner: VecSpanner::new(),
-        };
-        helper.helper_macro(0, 5);
-        let vec = helper.spanner.writer.into_inner().unwrap();
-        assert_eq!(expected.bytes().collect::<Vec<_>>(), vec);
+    #[test]
+    fn test_timestamps() {
+        let spanner = VecSpanner::new().with_config(
+            Config::new()
+                .with_timestamps(TimestampMode::Relative)
+                .with_thread_labels(true),
+        );
+        {
+            let _outer = spanner.enter_span("outer");
+            std::thread::sleep(Duration::from_millis(2));
+            let _inner = spanner.enter_span("inner");
+        }
 
-        let helper = Helper {
-            spanner: VecSpanner::new(),
-        };
-        helper.helper_macro_2(0, 5);
-        let vec = helper.spanner.writer.into_inner().unwrap();
-        assert_eq!(expected.bytes().collect::<Vec<_>>(), vec);
+        let output = spanner.into_string().unwrap();
+        let mut last = 0.0;
+        for line in output.lines() {
+            let (timestamp, rest) = line.split_once("ms] [").unwrap();
+            let timestamp: f64 = timestamp.strip_prefix('[').unwrap().parse().unwrap();
+            assert!(timestamp >= last, "{timestamp} < {last} in {output:?}");
+            last = timestamp;
+            // The thread label follows the timestamp
+            assert!(rest.contains("] "), "missing thread label in {line:?}");
+        }
+        assert!(last >= 2.0);
 
-        let helper = Helper {
-            spanner: VecSpanner::new(),
-        };
-        helper.helper_macro_3(0, 5);
-        let vec = helper.spanner.writer.into_inner().unwrap();
-        assert_eq!(expected_3.bytes().collect::<Vec<_>>(), vec);
+        let spanner =
+            VecSpanner::new().with_config(Config::new().with_timestamps(TimestampMode::Wall));
+        drop(spanner.enter_span("wall"));
+        let output = spanner.into_string().unwrap();
+        let (timestamp, rest) = output.lines().next().unwrap().split_once("] ").unwrap();
+        assert_eq!(rest, "┌wall");
+        let bytes = timestamp.as_bytes();
+        assert_eq!(bytes.len(), "[13:37:02.123".len());
+        assert_eq!(
+            (bytes[0], bytes[3], bytes[6], bytes[9]),
+            (b'[', b':', b':', b'.')
+        );
+    }
 
-        let helper = Helper {
-            spanner: VecSpanner::new(),
-        };
-        helper.helper_macro_4(0, 5);
-        let vec = helper.spanner.writer.into_inner().unwrap();
-        assert_eq!(expected.bytes().collect::<Vec<_>>(), vec);
+    #[test]
+    fn test_stats() {
+        let spanner = VecSpanner::new().with_config(
+            Config::new()
+                .with_collect_stats(true)
+                .with_max_depth(Some(2)),
+        );
+        assert_eq!(fib(&spanner, 10), 55);
+        {
+            let _filtered = spanner.enter_with_level(Level::Trace, "filtered");
+        }
+
+        let stats = spanner.stats();
+        assert_eq!(stats.len(), 11);
+        // fib(k) is called fib(11 - k) times while computing fib(10), and fib(0) as often as fib(2)
+        let expected = [34, 55, 34, 21, 13, 8, 5, 3, 2, 1, 1];
+        for (k, count) in expected.into_iter().enumerate() {
+            let stat = stats[&format!("fib({k})")];
+            assert_eq!(stat.count, count, "fib({k})");
+            assert!(stat.max <= stat.total);
+        }
+        assert!(stats["fib(10)"].total >= stats["fib(9)"].total);
+    }
+
+    #[test]
+    fn test_folded_stacks() {
+        let spanner = VecSpanner::new().with_config(
+            Config::new()
+                .with_format(Format::FoldedStacks)
+                .with_max_depth(Some(3))
+                .with_thread_labels(true),
+        );
+        {
+            let _main = spanner.enter_span("main");
+            {
+                let _parse = spanner.enter_span("parse;lex");
+                let _hidden = spanner.enter_span("hidden");
+                let _truncated = spanner.enter_span("truncated");
+            }
+            let _filtered = spanner.enter_with_level(Level::Trace, "filtered");
+            let _run = spanner.enter_span("run");
+        }
+
+        let output = String::from_utf8(spanner.into_inner()).unwrap();
+        let mut stacks = Vec::new();
+        for line in output.lines() {
+            let (label, line) = line.split_once("] ").unwrap();
+            assert!(label.starts_with('['));
+            let (stack, micros) = line.rsplit_once(' ').unwrap();
+            micros.parse::<u128>().unwrap();
+            stacks.push(stack);
+        }
+        assert_eq!(
+            stacks,
+            [
+                "main;parse:lex;hidden",
+                "main;parse:lex",
+                "main;run",
+                "main"
+            ]
+        );
     }
 }
@@ -0,0 +1,251 @@
+//! The global spanner
+//!
+//! This module provides a process-wide slot for a spanner, so that spans can be entered without
+//! passing a spanner around or declaring a static for it. The spanner is installed once with
+//! `set_global` and is used by `gspf!` and by the forms of `spf!` that omit the spanner argument.
+
+use core::any::Any;
+use core::fmt::Arguments;
+use std::sync::OnceLock;
+
+use crate::config::Config;
+use crate::core::{Span, SpanState, Spanner, StdoutSpanner};
+use crate::level::Level;
+use crate::writer::SpanOutput;
+
+static GLOBAL: OnceLock<Box<dyn GlobalSpanner>> = OnceLock::new();
+
+/// A spanner that can be installed as the global spanner.
+///
+/// `Spanner<T>` is generic over its writer, so the global slot stores the spanner as a trait
+/// object. This trait is implemented for every `Spanner` whose writer can be sent between
+/// threads.
+pub trait GlobalSpanner: Send + Sync {
+    /// Enters a span with `level` and the name `args`, like `Spanner::enter_args`.
+    fn enter_global(&'static self, level: Level, args: Arguments) -> GlobalSpan;
+
+    /// Exits `span`, which was entered with `GlobalSpanner::enter_global`. It is called when the
+    /// span is dropped.
+    fn exit_global(&'static self, span: &mut GlobalSpan);
+
+    /// Returns the spanner as `Any`, so that it can be downcast to its concrete type, e.g. to
+    /// read the output of a `VecSpanner`.
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T> GlobalSpanner for Spanner<T>
+where
+    T: SpanOutput + Send + 'static,
+{
+    fn enter_global(&'static self, level: Level, args: Arguments) -> GlobalSpan {
+        GlobalSpan {
+            inner: Some((self, self.enter_args(level, args).into_state())),
+        }
+    }
+
+    fn exit_global(&'static self, span: &mut GlobalSpan) {
+        if let Some((_, state)) = span.inner.take() {
+            Span::from_state(self, state).exit();
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl dyn GlobalSpanner {
+    /// Enters a span with `Level::Info`, like `Spanner::enter_span`.
+    ///
+    /// # Parameters
+    /// - `name`: The name of the span. It is displayed is span's enter and exit message
+    pub fn enter_span(&'static self, name: &str) -> GlobalSpan {
+        self.enter_global(Level::Info, format_args!("{name}"))
+    }
+
+    /// Enters a span with `level`, like `Spanner::enter_with_level`.
+    ///
+    /// # Parameters
+    /// - `level`: The level of the span. It determines if the span would be outputted or not
+    /// - `name`: The name of the span. It is displayed is span's enter and exit message
+    pub fn enter_with_level(&'static self, level: Level, name: &str) -> GlobalSpan {
+        self.enter_global(level, format_args!("{name}"))
+    }
+
+    /// Does the same thing as `enter_with_level`, but uses `std::fmt::Arguments` instead of `&str`
+    /// as a name to avoid allocations in spf! macro.
+    pub fn enter_args(&'static self, level: Level, args: Arguments) -> GlobalSpan {
+        self.enter_global(level, args)
+    }
+}
+
+impl core::fmt::Debug for dyn GlobalSpanner {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("GlobalSpanner").finish_non_exhaustive()
+    }
+}
+
+/// A span entered through the global spanner. It exits the span when dropped.
+///
+/// The span is a no-op if no global spanner was set when it was entered.
+#[derive(Debug)]
+#[must_use]
+pub struct GlobalSpan {
+    inner: Option<(&'static dyn GlobalSpanner, SpanState)>,
+}
+
+impl GlobalSpan {
+    /// Returns whether the span was entered through a global spanner, i.e. whether a global
+    /// spanner was set when it was entered. A span filtered out by the spanner counts as entered.
+    #[must_use]
+    pub const fn is_global(&self) -> bool {
+        self.inner.is_some()
+    }
+}
+
+impl Drop for GlobalSpan {
+    fn drop(&mut self) {
+        if let Some((spanner, _)) = self.inner {
+            spanner.exit_global(self);
+        }
+    }
+}
+
+/// The error returned by `set_global` when a global spanner is already set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SetGlobalError;
+
+impl core::fmt::Display for SetGlobalError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("a global spanner is already set")
+    }
+}
+
+impl std::error::Error for SetGlobalError {}
+
+/// Installs `spanner` as the global spanner, used by the forms of `spf!` that omit the spanner.
+///
+/// The global spanner can only be set once and lives until the end of the process. It is never
+/// dropped, so the summary of `Config::summary` is not written for it.
+///
+/// # Parameters
+/// - `spanner`: The spanner to install.
+///
+/// # Errors
+/// Returns `SetGlobalError` and drops `spanner` if a global spanner is already set.
+///
+/// # Examples
+/// ```
+/// use spannify::{core::StdoutSpanner, spf};
+///
+/// spannify::set_global(StdoutSpanner::new()).unwrap();
+///
+/// let _span = spf!("main");
+/// ```
+pub fn set_global<T>(spanner: Spanner<T>) -> Result<(), SetGlobalError>
+where
+    T: SpanOutput + Send + 'static,
+{
+    let mut set = false;
+    GLOBAL.get_or_init(|| {
+        set = true;
+        Box::new(spanner)
+    });
+    if set {
+        Ok(())
+    } else {
+        Err(SetGlobalError)
+    }
+}
+
+/// Installs a `StdoutSpanner` configured with `Config::from_env` as the global spanner, like
+/// `env_logger::init`, and returns it.
+///
+/// The level of the spanner is read from the `SPANNIFY_LEVEL` environment variable, so a single
+/// call at the top of `main` is enough to trace the program with `gspf!`.
+///
+/// # Errors
+/// Returns `SetGlobalError` if a global spanner is already set.
+///
+/// # Examples
+/// ```
+/// use spannify::gspf;
+///
+/// // SPANNIFY_LEVEL=debug cargo run
+/// let spanner = spannify::init().unwrap();
+///
+/// let _span = gspf!("main");
+/// # #[cfg(not(feature = "disabled"))]
+/// assert_eq!(spanner.depth(), 1);
+/// ```
+pub fn init() -> Result<&'static StdoutSpanner, SetGlobalError> {
+    set_global(StdoutSpanner::new().with_config(Config::from_env()))?;
+    global()
+        .and_then(|spanner| spanner.as_any().downcast_ref())
+        .ok_or(SetGlobalError)
+}
+
+/// Returns the global spanner, or `None` if `set_global` has not been called yet.
+///
+/// # Examples
+/// ```
+/// use spannify::{core::StdoutSpanner, spf};
+///
+/// assert!(spannify::global().is_none());
+/// spannify::set_global(StdoutSpanner::new()).unwrap();
+///
+/// let spanner = spannify::global().unwrap();
+/// let _span = spf!(spanner, "main");
+/// ```
+#[must_use]
+pub fn global() -> Option<&'static dyn GlobalSpanner> {
+    GLOBAL.get().map(|spanner| &**spanner)
+}
+
+/// Enters a span through the global spanner, or returns a no-op span if none is set. Used by
+/// `spf!`.
+#[doc(hidden)]
+pub fn enter_args(level: Level, args: Arguments) -> GlobalSpan {
+    global().map_or(GlobalSpan { inner: None }, |spanner| {
+        spanner.enter_global(level, args)
+    })
+}
+
+#[cfg(all(test, not(feature = "disabled")))]
+mod tests {
+    use super::*;
+    use crate::core::VecSpanner;
+    use crate::{gspf, spf};
+
+    // This is the only test of the library that sets the global spanner, as it can only be set
+    // once per process
+    #[test]
+    fn global_spanner() {
+        set_global(VecSpanner::new()).unwrap();
+        assert_eq!(set_global(VecSpanner::new()), Err(SetGlobalError));
+        {
+            let _outer = spf!("outer");
+            let n = 1;
+            let _inner = spf!("inner({})", n);
+            let _last = spf!("last({n})");
+            let _warn = gspf!(Level::Warn, "warn({})", n);
+            let filtered = gspf!(Level::Debug, "filtered");
+            assert!(filtered.is_global());
+        }
+        let spanner = global().unwrap().as_any();
+        let output = spanner
+            .downcast_ref::<VecSpanner>()
+            .unwrap()
+            .with_contents(|output| String::from_utf8(output.to_vec()).unwrap());
+        let expected = r#"┌outer
+|  inner(1)
+|   ┌last(1)
+|   ┆  warn(1)
+|   ┆  warn(1)
+|   └last(1)
+|  inner(1)
+└outer
+"#;
+        assert_eq!(output, expected);
+    }
+}
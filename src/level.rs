@@ -1,8 +1,18 @@
 //! Span levels
 
+use alloc::borrow::ToOwned;
+use alloc::string::String;
+use core::str::FromStr;
+
 /// Repesent a level of the span. The level determines if the span should be ouputted or not. If
 /// `Span` level is less than parent `Spanner` level, the span is ignored.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+#[repr(u8)]
 pub enum Level {
     Trace,
     Debug,
@@ -11,12 +21,160 @@ pub enum Level {
     Error,
 }
 
+impl Level {
+    /// Converts the `u8` representation of a level back into the level. Out of range values
+    /// saturate to `Level::Error`.
+    #[cfg(feature = "std")]
+    pub(crate) const fn from_u8(value: u8) -> Self {
+        match value {
+            0 => Self::Trace,
+            1 => Self::Debug,
+            2 => Self::Info,
+            3 => Self::Warn,
+            _ => Self::Error,
+        }
+    }
+
+    /// Returns the lowercase name of the level, e.g. `"info"`.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::level::Level;
+    ///
+    /// assert_eq!(Level::Warn.as_str(), "warn");
+    /// ```
+    #[must_use]
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Trace => "trace",
+            Self::Debug => "debug",
+            Self::Info => "info",
+            Self::Warn => "warn",
+            Self::Error => "error",
+        }
+    }
+}
+
+#[cfg(feature = "log")]
+impl From<Level> for log::Level {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::Trace => Self::Trace,
+            Level::Debug => Self::Debug,
+            Level::Info => Self::Info,
+            Level::Warn => Self::Warn,
+            Level::Error => Self::Error,
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl From<Level> for tracing::Level {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::Trace => Self::TRACE,
+            Level::Debug => Self::DEBUG,
+            Level::Info => Self::INFO,
+            Level::Warn => Self::WARN,
+            Level::Error => Self::ERROR,
+        }
+    }
+}
+
+impl core::fmt::Display for Level {
+    /// Writes the lowercase name of the level, which can be parsed back with `FromStr`.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Level {
+    type Err = ParseLevelError;
+
+    /// Parses `trace`, `debug`, `info`, `warn` or `error`, ignoring case and surrounding
+    /// whitespace.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let level = s.trim();
+        [
+            Self::Trace,
+            Self::Debug,
+            Self::Info,
+            Self::Warn,
+            Self::Error,
+        ]
+        .into_iter()
+        .find(|l| level.eq_ignore_ascii_case(l.as_str()))
+        .ok_or_else(|| ParseLevelError {
+            input: s.to_owned(),
+        })
+    }
+}
+
+/// The error returned when a string can not be parsed into a `Level`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseLevelError {
+    input: String,
+}
+
+impl core::fmt::Display for ParseLevelError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "invalid level `{}`, expected one of `trace`, `debug`, `info`, `warn` or `error`",
+            self.input
+        )
+    }
+}
+
+impl core::error::Error for ParseLevelError {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use alloc::string::ToString;
 
     #[test]
     fn sanity_check() {
         assert!(Level::Trace < Level::Warn);
     }
+
+    #[test]
+    fn parse() {
+        assert_eq!(" Debug\n".parse(), Ok(Level::Debug));
+        assert_eq!("WARN".parse(), Ok(Level::Warn));
+        assert!("verbose".parse::<Level>().is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn u8_round_trip() {
+        for level in [
+            Level::Trace,
+            Level::Debug,
+            Level::Info,
+            Level::Warn,
+            Level::Error,
+        ] {
+            assert_eq!(Level::from_u8(level as u8), level);
+        }
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for level in [
+            Level::Trace,
+            Level::Debug,
+            Level::Info,
+            Level::Warn,
+            Level::Error,
+        ] {
+            assert_eq!(level.to_string(), level.as_str());
+            assert_eq!(level.to_string().parse(), Ok(level));
+        }
+        let err = "verbose".parse::<Level>().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid level `verbose`, expected one of `trace`, `debug`, `info`, `warn` or `error`"
+        );
+    }
 }
@@ -16,12 +16,40 @@
 //! - [`config`]: Contains the configuration structures and options for customizing the appearance
 //!   and behavior of the callstack visualization.
 //! - [`core`]: Contains the core functionality for managing spans, generating messages, and
-//!   handling the callstack visualization logic.
+//!   handling the callstack visualization logic. Requires the `std` feature.
+//! - [`global`](mod@global): Contains the global spanner, used by `gspf!` and by `spf!` when the
+//!   spanner is omitted. Requires the `std` feature.
 //! - [`level`]: Contains the levels of spans, which determines if the span should be outputted or not
+//! - [`local`]: Contains a lock-free spanner for single-threaded code.
+//! - [`render`]: Contains the formatter that renders the lines of spans, for custom tools.
+//! - `tracing`: Contains a spanner that opens `tracing` spans. Requires the `tracing` feature.
+//! - [`writer`]: Contains writer adapters to use as the output of a spanner.
+//!
+//! ## Features
+//!
+//! - `std` (enabled by default): Enables the thread-safe `Spanner`, its writers backed by
+//!   `std::io::Write` and everything else that needs the standard library. Without it, the crate
+//!   is `no_std` and only needs `alloc`: spans are traced with a `LocalSpanner` that writes into
+//!   any `SpanOutput`, e.g. a `core::fmt::Write` sink wrapped in a `FmtWriter`, and there is no
+//!   clock, so timings and timestamps are zero.
+//! - `serde`: Implements `Serialize` and `Deserialize` for `Config`, so that it can be loaded from
+//!   a configuration file.
+//! - `unicode-width`: Measures span names and guides by their display width instead of their
+//!   number of characters when aligning and truncating them, so that wide characters, such as CJK
+//!   characters and emoji, which take two columns in a terminal, stay aligned.
+//! - `log`: Enables `LogSpanner`, whose `LogWriter` forwards every line to the `log` facade at
+//!   the level of its span, so that spans flow into whatever `log` backend the application uses.
+//! - `tracing`: Enables `TracingSpanner`, which opens a `tracing` span on enter and closes it on
+//!   drop instead of writing text, so that spans are handled by the `tracing` subscriber in use.
+//! - `disabled`: Turns every span into a no-op that never locks, formats or writes anything, and
+//!   shrinks `core::Span` down to its level, so that tracing can be compiled out of release builds
+//!   without removing the `spf!` calls. The API is unchanged, so code using spannify compiles the
+//!   same with and without the feature.
 //!
 //! ## Example
 //!
 //! ```rust
+//! # #[cfg(feature = "std")] {
 //! use spannify::{config::Config, core::StdoutSpanner, spf};
 //! use std::sync::LazyLock;
 //!
@@ -37,10 +65,9 @@
 //!     }
 //! }
 //!
-//! fn main() {
-//!     fib(5);
-//! }
+//! fib(5);
 //!
+//! # }
 //!  ```
 //! ### Output
 //!
@@ -72,6 +99,7 @@
 //! entry and exit points of different sections of your code.
 //!
 //! ```rust
+//! # #[cfg(feature = "std")] {
 //! use spannify::{config::Config, core::{StdoutSpanner}};
 //! use std::io::stdout;
 //!
@@ -87,6 +115,7 @@
 //!     // Your code here...
 //! }
 //! // The span is automatically dropped here, and the exit message is generated
+//! # }
 //! ```
 //!
 //! ## Configuration
@@ -115,6 +144,7 @@
 //! Contributions are welcome! Please open an issue or submit a pull request
 
 #![doc(html_root_url = "https://docs.rs/spannify/latest")]
+#![cfg_attr(not(feature = "std"), no_std)]
 #![deny(unsafe_code)]
 #![warn(
     clippy::cognitive_complexity,
@@ -153,7 +183,34 @@
     clippy::wildcard_dependencies,
     clippy::wildcard_imports
 )]
+// The `disabled` feature compiles out entering and exiting spans, which leaves most of the
+// rendering machinery unused and lets the methods that enter spans be `const`
+#![cfg_attr(
+    feature = "disabled",
+    allow(dead_code, unused_imports, clippy::missing_const_for_fn)
+)]
+
+// `toml` is only used by the tests of the `serde` feature and `serde_json` by the tests that
+// need `std` and are disabled by the `disabled` feature
+#[cfg(all(test, any(feature = "disabled", not(feature = "std"))))]
+use serde_json as _;
+#[cfg(all(test, not(feature = "serde")))]
+use toml as _;
+
+extern crate alloc;
 
 pub mod config;
+#[cfg(feature = "std")]
 pub mod core;
+#[cfg(feature = "std")]
+pub mod global;
 pub mod level;
+pub mod local;
+mod macros;
+pub mod render;
+#[cfg(feature = "tracing")]
+pub mod tracing;
+pub mod writer;
+
+#[cfg(feature = "std")]
+pub use global::{global, init, set_global};
@@ -0,0 +1,238 @@
+//! Single-threaded span generators
+//!
+//! This module provides `LocalSpanner`, a lock-free alternative to `Spanner` for tracing code
+//! that runs on a single thread, and the `LocalSpan` guards it produces.
+
+use alloc::string::{String, ToString};
+use core::cell::{Cell, RefCell};
+use core::fmt::{Arguments, Write as _};
+
+use crate::config::Config;
+use crate::level::Level;
+use crate::render::{with_message, RenderState};
+use crate::writer::SpanOutput;
+
+/// A single-threaded structure that generates spans and keeps track of the span depth.
+///
+/// Unlike `Spanner`, `LocalSpanner` keeps its writer in a `RefCell` and its depth in a `Cell`, so
+/// entering and dropping spans involves no locking and no atomics. As a consequence it is `!Sync`
+/// and can only be used from one thread, which makes it a good fit for hot recursive code.
+///
+/// Spans are rendered exactly like `Spanner` renders them for the same `Config`. Spanner-level
+/// features, such as time windows, deferred spans, rate limiting or timing, are only available on
+/// `Spanner`.
+///
+/// `LocalSpanner` is available without the `std` feature, writing into any `SpanOutput`.
+#[derive(Debug)]
+pub struct LocalSpanner<T>
+where
+    T: SpanOutput,
+{
+    writer: RefCell<T>,
+    depth: Cell<usize>,
+    config: Config,
+    render: RefCell<RenderState>,
+    line: RefCell<String>,
+}
+
+impl<T> LocalSpanner<T>
+where
+    T: SpanOutput,
+{
+    /// Creates a new `LocalSpanner` instance from a writer.
+    ///
+    /// # Parameters
+    /// - `writer`: The writer to which the spans will be written.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::{local::LocalSpanner, writer::FmtWriter};
+    ///
+    /// let spanner = LocalSpanner::from_writer(FmtWriter::new(String::new()));
+    /// ```
+    pub fn from_writer(writer: T) -> Self {
+        Self {
+            writer: RefCell::new(writer),
+            depth: Cell::new(0),
+            config: Config::default(),
+            render: RefCell::new(RenderState::default()),
+            line: RefCell::new(String::new()),
+        }
+    }
+
+    /// Sets a custom configuration for the spanner.
+    ///
+    /// # Parameters
+    /// - `cfg`: The new configuration to use.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::{config::Config, local::LocalSpanner, writer::FmtWriter};
+    ///
+    /// let spanner = LocalSpanner::from_writer(FmtWriter::new(String::new()))
+    ///     .with_config(Config::new().with_skip(3));
+    /// ```
+    #[must_use]
+    pub fn with_config(self, cfg: Config) -> Self {
+        Self {
+            config: cfg.resolve_auto_color(false),
+            render: RefCell::new(RenderState::default()),
+            ..self
+        }
+    }
+
+    /// Enters a span with `Level::Info`, increasing the depth and writing the span's enter message.
+    ///
+    /// # Parameters
+    /// - `name`: The name of the span. It is displayed is span's enter and exit message
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::{local::LocalSpanner, writer::FmtWriter};
+    ///
+    /// let spanner = LocalSpanner::from_writer(FmtWriter::new(String::new()));
+    /// let span = spanner.enter_span("test");
+    /// ```
+    pub fn enter_span(&self, name: &str) -> LocalSpan<'_, T> {
+        self.enter_with_level(Level::Info, name)
+    }
+
+    /// Enters a span with `level`, increasing the depth and writing the span's enter message if
+    /// level is not less than `Config::level`.
+    ///
+    /// # Parameters
+    /// - `level`: The level of the span. It determines if the span would be outputted or not
+    /// - `name`: The name of the span. It is displayed is span's enter and exit message
+    pub fn enter_with_level(&self, level: Level, name: &str) -> LocalSpan<'_, T> {
+        self.enter_args(level, format_args!("{name}"))
+    }
+
+    /// Does the same thing as `enter_with_level`, but uses `std::fmt::Arguments` instead of `&str`
+    /// as a name to avoid allocations where possible in spf! macro.
+    pub fn enter_args(&self, level: Level, args: Arguments) -> LocalSpan<'_, T> {
+        LocalSpan::enter_args(self, level, args)
+    }
+
+    /// Returns the current depth, i.e. the number of spans that are currently entered.
+    #[must_use]
+    pub const fn depth(&self) -> usize {
+        self.depth.get()
+    }
+
+    /// Writes the enter or the drop message of a span to the writer, rendering it into a buffer
+    /// that is reused between messages.
+    fn write_message(&self, name: &str, depth: usize, exit: bool) {
+        let (Ok(mut writer), Ok(mut line)) =
+            (self.writer.try_borrow_mut(), self.line.try_borrow_mut())
+        else {
+            return;
+        };
+        line.clear();
+        with_message(
+            format_args!("{name}"),
+            depth,
+            &self.config,
+            &mut self.render.borrow_mut(),
+            exit,
+            |message| {
+                let _ = write!(line, "{message}");
+            },
+        );
+        let _ = writer.write_output(&line);
+    }
+}
+
+/// A span produced by a `LocalSpanner`. It writes its exit message and decrements the depth of
+/// its parent when dropped.
+#[derive(Debug)]
+pub struct LocalSpan<'a, T>
+where
+    T: SpanOutput,
+{
+    parent: &'a LocalSpanner<T>,
+    name: String,
+    depth: usize,
+    entered: bool,
+}
+
+impl<'a, T> LocalSpan<'a, T>
+where
+    T: SpanOutput,
+{
+    fn enter_args(parent: &'a LocalSpanner<T>, level: Level, args: Arguments) -> Self {
+        let entered = !cfg!(feature = "disabled")
+            && parent.config.level <= level
+            && parent.config.passes_name_filter(args);
+        let mut span = Self {
+            parent,
+            name: String::new(),
+            depth: 0,
+            entered,
+        };
+        if entered {
+            span.depth = parent.depth.get();
+            parent.depth.set(span.depth + 1);
+            span.name = args.to_string();
+            parent.write_message(&span.name, span.depth, false);
+        }
+        span
+    }
+}
+
+impl<T> Drop for LocalSpan<'_, T>
+where
+    T: SpanOutput,
+{
+    /// Regenerates the drop message, writes it to the writer and decrements the parent's depth.
+    fn drop(&mut self) {
+        if !self.entered {
+            return;
+        }
+        self.parent.depth.set(self.depth);
+        self.parent.write_message(&self.name, self.depth, true);
+    }
+}
+
+#[cfg(all(test, not(feature = "disabled")))]
+mod tests {
+    use super::*;
+    use crate::spf;
+    use crate::writer::FmtWriter;
+
+    fn helper<T>(spanner: &LocalSpanner<T>, current_depth: usize, target_depth: usize)
+    where
+        T: SpanOutput,
+    {
+        let _span = spf!(spanner, "Span({current_depth})");
+        if current_depth < target_depth {
+            helper(spanner, current_depth + 1, target_depth);
+        }
+    }
+
+    #[test]
+    fn it_works() {
+        let spanner = LocalSpanner::from_writer(FmtWriter::new(String::new()))
+            .with_config(Config::new().with_skip(3));
+
+        let expected = r#"┌Span(0)
+|  Span(1)
+|    Span(2)
+|     ┌Span(3)
+|     ┊  Span(4)
+|     ┊    Span(5)
+|     ┊    Span(5)
+|     ┊  Span(4)
+|     └Span(3)
+|    Span(2)
+|  Span(1)
+└Span(0)
+"#;
+
+        helper(&spanner, 0, 5);
+        {
+            let _filtered = spanner.enter_with_level(Level::Debug, "filtered");
+        }
+        assert_eq!(spanner.depth(), 0);
+        assert_eq!(expected, spanner.writer.into_inner().into_inner());
+    }
+}
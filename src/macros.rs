@@ -0,0 +1,341 @@
+//! Span macros
+//!
+//! This module provides the `spf!` family of macros, which work with every spanner.
+
+/// Creates a new span with a given spanner.
+///
+/// This macro is used to create and enter a new span.
+/// You can specify the level of span if needed.
+///
+/// # Examples
+///
+/// ```rust
+/// # #[cfg(feature = "std")] {
+/// use spannify::{spf, level::Level, core::StdoutSpanner};
+///
+/// let my_spanner = StdoutSpanner::new();
+///
+/// let timestamp = "12:21";
+///
+/// // Creates a span with a level and literal message
+/// spf!(my_spanner, Level::Info => "Starting process");
+///
+/// // Creates a span with a literal message
+/// spf!(my_spanner => "Starting process");
+///
+/// // Example of creating a span with a formatted message
+/// spf!(my_spanner, "Starting process at {}", timestamp);
+/// // Or
+/// spf!(my_spanner, "Starting process at {timestamp}");
+///
+/// // Example of creating a span with a level and formatted message
+/// spf!(my_spanner, Level::Info, "Starting process at {}", timestamp);
+///
+/// // Example of creating a span with a level and a name that is only built if the span is not
+/// // filtered out by its level
+/// spf!(my_spanner, Level::Trace, || format!("Starting process at {timestamp}"));
+///
+/// // Unlike the other arms, which return the span guard, this one wraps a single expression in
+/// // a span and returns the value of the expression. The span is exited right after the
+/// // expression is evaluated
+/// let sum = spf!(my_spanner, "compute" => 2 + 2);
+/// assert_eq!(sum, 4);
+/// # }
+/// ```
+///
+/// The spanner can be omitted when the name is a format string, in which case the span is
+/// entered with `Level::Info` through the spanner installed with `set_global`. The span is a
+/// no-op if no global spanner is set.
+///
+/// ```rust
+/// # #[cfg(feature = "std")] {
+/// use spannify::{spf, core::StdoutSpanner};
+///
+/// spannify::set_global(StdoutSpanner::new()).unwrap();
+///
+/// let timestamp = "12:21";
+/// let _span = spf!("Starting process at {timestamp}");
+/// let _span = spf!("Starting process at {}", timestamp);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! spf {
+    ($fmt:literal $(, $arg:expr)* $(,)?) => {{
+        let span = $crate::global::enter_args(
+            $crate::level::Level::Info,
+            format_args!($fmt $(, $arg)*),
+        );
+        span
+    }};
+
+    ($spa:expr, $level:path => $arg:literal) => {{
+        let span = $spa.enter_with_level($level, $arg);
+        span
+    }};
+
+    ($spa:expr, $name:literal => $body:expr) => {{
+        let _span = $spa.enter_span($name);
+        $body
+    }};
+
+    ($spa:expr => $arg:literal) => {{
+        let span = $spa.enter_span($arg);
+        span
+    }};
+
+    ($spa:expr, $level:path, || $name:expr) => {{
+        let span = $spa.enter_lazy($level, || $name);
+        span
+    }};
+
+    ($spa:expr, $level:path, $($arg:tt)*) => {{
+        let span = $spa.enter_args($level, format_args!($($arg)*));
+        span
+    }};
+
+    ($spa:expr, $($arg:tt)*) => {{
+        let span = $spa.enter_args($crate::level::Level::Info, format_args!($($arg)*));
+        span
+    }};
+}
+
+/// Creates a new span with the global spanner.
+///
+/// `gspf!(...)` is a shorthand for `spf!(spannify::global(), ...)` and supports the formatted
+/// forms of `spf!`, with and without a level, so that spans can be entered without any plumbing
+/// once a spanner is installed with `set_global`.
+///
+/// If no global spanner is set, `gspf!` is a no-op: it does not panic and the returned span does
+/// nothing, so libraries can use it and leave the choice of a spanner to the application.
+///
+/// # Examples
+///
+/// ```rust
+/// # #[cfg(feature = "std")] {
+/// use spannify::{core::StdoutSpanner, gspf, level::Level};
+///
+/// let tok = "ident";
+///
+/// // No global spanner is set yet, so the span is a no-op
+/// let span = gspf!("parse {tok:?}");
+/// assert!(!span.is_global());
+///
+/// spannify::set_global(StdoutSpanner::new()).unwrap();
+///
+/// // Creates a span with a formatted message
+/// let _span = gspf!("parse {tok:?}");
+///
+/// // Creates a span with a level and a formatted message
+/// let span = gspf!(Level::Warn, "parse {}", tok);
+/// assert!(span.is_global());
+/// # }
+/// ```
+#[macro_export]
+macro_rules! gspf {
+    ($level:path, $($arg:tt)*) => {{
+        let span = $crate::global::enter_args($level, format_args!($($arg)*));
+        span
+    }};
+
+    ($($arg:tt)*) => {{
+        let span = $crate::global::enter_args(
+            $crate::level::Level::Info,
+            format_args!($($arg)*),
+        );
+        span
+    }};
+}
+
+/// Creates a new span whose name is prefixed with the path of the module it is created in.
+///
+/// `spf_located!` supports the literal and the formatted forms of `spf!`, with and without a
+/// level, and prepends `module_path!()` followed by `::` to the name, so that spans of functions
+/// with the same name in different modules can be told apart.
+///
+/// # Examples
+///
+/// ```rust
+/// # #[cfg(feature = "std")] {
+/// use spannify::{core::VecSpanner, level::Level, spf_located};
+///
+/// mod parser {
+///     pub fn parse(spanner: &spannify::core::VecSpanner, input: &str) {
+///         let _span = spannify::spf_located!(spanner, "parse({input})");
+///     }
+/// }
+///
+/// let spanner = VecSpanner::new();
+/// parser::parse(&spanner, "1 + 2");
+/// drop(spf_located!(spanner, Level::Info => "main"));
+///
+/// let trace = spanner.into_string().unwrap();
+/// # #[cfg(not(feature = "disabled"))]
+/// assert!(trace.contains("::parser::parse(1 + 2)"));
+/// # #[cfg(not(feature = "disabled"))]
+/// assert!(trace.contains("::main"));
+/// # }
+/// ```
+#[macro_export]
+macro_rules! spf_located {
+    ($spa:expr, $level:path => $arg:literal) => {{
+        let span = $spa.enter_with_level($level, concat!(module_path!(), "::", $arg));
+        span
+    }};
+
+    ($spa:expr => $arg:literal) => {{
+        let span = $spa.enter_span(concat!(module_path!(), "::", $arg));
+        span
+    }};
+
+    ($spa:expr, $level:path, $($arg:tt)*) => {{
+        let span = $spa.enter_args(
+            $level,
+            format_args!("{}::{}", module_path!(), format_args!($($arg)*)),
+        );
+        span
+    }};
+
+    ($spa:expr, $($arg:tt)*) => {{
+        let span = $spa.enter_args(
+            $crate::level::Level::Info,
+            format_args!("{}::{}", module_path!(), format_args!($($arg)*)),
+        );
+        span
+    }};
+}
+
+/// Creates a new span with `Level::Trace` with a given spanner.
+///
+/// `spf_trace!(spanner, ...)` is a shorthand for `spf!(spanner, Level::Trace, ...)` and supports
+/// both the literal and the formatted forms of `spf!`.
+///
+/// # Examples
+///
+/// ```rust
+/// # #[cfg(feature = "std")] {
+/// use spannify::{config::Config, core::VecSpanner, level::Level, spf_trace};
+///
+/// let spanner = VecSpanner::new().with_config(Config::new().with_level(Level::Trace));
+/// let step = 3;
+///
+/// // Creates a span with a literal message
+/// let _span = spf_trace!(spanner => "Starting process");
+///
+/// // Creates a span with a formatted message
+/// let _span = spf_trace!(spanner, "Step {step}");
+/// # #[cfg(not(feature = "disabled"))]
+/// assert_eq!(spanner.depth(), 2);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! spf_trace {
+    ($spa:expr => $arg:literal) => {
+        $crate::spf!($spa, $crate::level::Level::Trace => $arg)
+    };
+
+    ($spa:expr, $($arg:tt)*) => {
+        $crate::spf!($spa, $crate::level::Level::Trace, $($arg)*)
+    };
+}
+
+/// Creates a new span with `Level::Debug` with a given spanner.
+///
+/// `spf_debug!(spanner, ...)` is a shorthand for `spf!(spanner, Level::Debug, ...)` and supports
+/// both the literal and the formatted forms of `spf!`.
+///
+/// # Examples
+///
+/// ```rust
+/// # #[cfg(feature = "std")] {
+/// use spannify::{config::Config, core::VecSpanner, level::Level, spf_debug};
+///
+/// let spanner = VecSpanner::new().with_config(Config::new().with_level(Level::Trace));
+/// let step = 3;
+///
+/// // Creates a span with a literal message
+/// let _span = spf_debug!(spanner => "Starting process");
+///
+/// // Creates a span with a formatted message
+/// let _span = spf_debug!(spanner, "Step {step}");
+/// # #[cfg(not(feature = "disabled"))]
+/// assert_eq!(spanner.depth(), 2);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! spf_debug {
+    ($spa:expr => $arg:literal) => {
+        $crate::spf!($spa, $crate::level::Level::Debug => $arg)
+    };
+
+    ($spa:expr, $($arg:tt)*) => {
+        $crate::spf!($spa, $crate::level::Level::Debug, $($arg)*)
+    };
+}
+
+/// Creates a new span with `Level::Warn` with a given spanner.
+///
+/// `spf_warn!(spanner, ...)` is a shorthand for `spf!(spanner, Level::Warn, ...)` and supports
+/// both the literal and the formatted forms of `spf!`.
+///
+/// # Examples
+///
+/// ```rust
+/// # #[cfg(feature = "std")] {
+/// use spannify::{config::Config, core::VecSpanner, level::Level, spf_warn};
+///
+/// let spanner = VecSpanner::new().with_config(Config::new().with_level(Level::Trace));
+/// let step = 3;
+///
+/// // Creates a span with a literal message
+/// let _span = spf_warn!(spanner => "Starting process");
+///
+/// // Creates a span with a formatted message
+/// let _span = spf_warn!(spanner, "Step {step}");
+/// # #[cfg(not(feature = "disabled"))]
+/// assert_eq!(spanner.depth(), 2);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! spf_warn {
+    ($spa:expr => $arg:literal) => {
+        $crate::spf!($spa, $crate::level::Level::Warn => $arg)
+    };
+
+    ($spa:expr, $($arg:tt)*) => {
+        $crate::spf!($spa, $crate::level::Level::Warn, $($arg)*)
+    };
+}
+
+/// Creates a new span with `Level::Error` with a given spanner.
+///
+/// `spf_error!(spanner, ...)` is a shorthand for `spf!(spanner, Level::Error, ...)` and supports
+/// both the literal and the formatted forms of `spf!`.
+///
+/// # Examples
+///
+/// ```rust
+/// # #[cfg(feature = "std")] {
+/// use spannify::{config::Config, core::VecSpanner, level::Level, spf_error};
+///
+/// let spanner = VecSpanner::new().with_config(Config::new().with_level(Level::Trace));
+/// let step = 3;
+///
+/// // Creates a span with a literal message
+/// let _span = spf_error!(spanner => "Starting process");
+///
+/// // Creates a span with a formatted message
+/// let _span = spf_error!(spanner, "Step {step}");
+/// # #[cfg(not(feature = "disabled"))]
+/// assert_eq!(spanner.depth(), 2);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! spf_error {
+    ($spa:expr => $arg:literal) => {
+        $crate::spf!($spa, $crate::level::Level::Error => $arg)
+    };
+
+    ($spa:expr, $($arg:tt)*) => {
+        $crate::spf!($spa, $crate::level::Level::Error, $($arg)*)
+    };
+}
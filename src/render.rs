@@ -0,0 +1,746 @@
+//! Rendering of span lines
+//!
+//! This module turns the name, the depth and the configuration of a span into the lines that are
+//! written on entering and dropping it, shared by all spanners. The `Formatter` exposes the same
+//! rendering for custom tools that track spans on their own.
+
+use alloc::borrow::Cow;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::{Arguments, Write as _};
+use core::time::Duration;
+
+use crate::config::{Config, Format, MAX_TABWIDTH};
+
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+/// Without `std` there is no clock, so every instant is the same and every span takes no time.
+#[cfg(not(feature = "std"))]
+#[derive(Clone, Copy, Debug)]
+struct Instant;
+
+#[cfg(not(feature = "std"))]
+impl Instant {
+    const fn now() -> Self {
+        Self
+    }
+
+    // Takes `self` to mirror `std::time::Instant::elapsed`
+    #[allow(clippy::unused_self)]
+    const fn elapsed(self) -> Duration {
+        Duration::ZERO
+    }
+}
+
+/// A standalone renderer of the enter and exit messages of spans, which renders them exactly like
+/// the spanners do for the same `Config`, e.g. to build a custom spanner-like tool on top of
+/// spannify's indentation and glyphs.
+///
+/// Like a spanner, it caches the indentation of every depth and keeps the state that
+/// `Format::ChromeTrace` and `Format::FoldedStacks` depend on, so the messages of those formats
+/// have to be rendered in the order the spans are entered and dropped in. Messages include their
+/// trailing newline.
+///
+/// # Examples
+/// ```
+/// use spannify::{config::Config, render::Formatter};
+///
+/// let mut formatter = Formatter::new(Config::new().with_skip(1));
+/// let (enter, exit) = formatter.messages("parse", 2);
+/// assert_eq!(enter, "| ¦ ┌parse\n");
+/// assert_eq!(exit, "| ¦ └parse\n");
+/// ```
+#[derive(Debug, Default)]
+pub struct Formatter {
+    config: Config,
+    state: RenderState,
+}
+
+impl Formatter {
+    /// Creates a new `Formatter` that renders messages with `cfg`.
+    ///
+    /// # Parameters
+    /// - `cfg`: The configuration to render the messages with.
+    #[must_use]
+    pub fn new(cfg: Config) -> Self {
+        Self {
+            config: cfg,
+            state: RenderState::default(),
+        }
+    }
+
+    /// Renders the message that is written when a span named `name` is entered at `depth`.
+    ///
+    /// # Parameters
+    /// - `name`: The name of the span.
+    /// - `depth`: The depth of the span, which is `0` for top-level spans.
+    pub fn enter_message(&mut self, name: &str, depth: usize) -> String {
+        self.render(name, depth, false)
+    }
+
+    /// Renders the message that is written when a span named `name` at `depth` is dropped.
+    ///
+    /// # Parameters
+    /// - `name`: The name of the span.
+    /// - `depth`: The depth of the span, which is `0` for top-level spans.
+    pub fn exit_message(&mut self, name: &str, depth: usize) -> String {
+        self.render(name, depth, true)
+    }
+
+    /// Renders both the enter and the exit message of a span named `name` at `depth`.
+    ///
+    /// # Parameters
+    /// - `name`: The name of the span.
+    /// - `depth`: The depth of the span, which is `0` for top-level spans.
+    pub fn messages(&mut self, name: &str, depth: usize) -> (String, String) {
+        (
+            self.enter_message(name, depth),
+            self.exit_message(name, depth),
+        )
+    }
+
+    fn render(&mut self, name: &str, depth: usize, exit: bool) -> String {
+        let message = with_message(
+            format_args!("{name}"),
+            depth,
+            &self.config,
+            &mut self.state,
+            exit,
+            |message| message.to_string(),
+        );
+        match translate_newlines(&message, &self.config) {
+            Cow::Borrowed(_) => message,
+            Cow::Owned(translated) => translated,
+        }
+    }
+}
+
+/// Renders the enter and the exit message of a span named `name` at `depth` with `cfg`, see
+/// `Formatter`.
+///
+/// # Parameters
+/// - `name`: The name of the span.
+/// - `depth`: The depth of the span, which is `0` for top-level spans.
+/// - `cfg`: The configuration to render the messages with.
+///
+/// # Examples
+/// ```
+/// use spannify::{config::Config, render::messages};
+///
+/// let (enter, exit) = messages("parse", 2, &Config::new().with_skip(1).with_tabwidth(3));
+/// assert_eq!(enter, "|  ¦  ┌parse\n");
+/// assert_eq!(exit, "|  ¦  └parse\n");
+/// ```
+#[must_use]
+pub fn messages(name: &str, depth: usize, cfg: &Config) -> (String, String) {
+    Formatter::new(cfg.clone()).messages(name, depth)
+}
+
+/// Prepares the entry or the drop message for a span based on its name, depth, and
+/// configuration, and passes it to `f`, which can either render it into a `String` or write it
+/// straight into a writer.
+///
+/// # Parameters
+///
+/// - `name`: The name of the span.
+/// - `depth`: The current depth of the span.
+/// - `cfg`: The configuration for formatting the messages.
+/// - `state`: The rendering state of the spanner, which must only be used with `cfg`.
+/// - `exit`: Whether to prepare the drop message instead of the entry message.
+/// - `f`: The function that consumes the message.
+///
+pub(crate) fn with_message<R>(
+    name: Arguments,
+    depth: usize,
+    cfg: &Config,
+    state: &mut RenderState,
+    exit: bool,
+    f: impl FnOnce(&Message<'_>) -> R,
+) -> R {
+    if cfg.sanitize_names || cfg.max_name_width.is_some() {
+        let mut name = name.to_string();
+        if cfg.sanitize_names {
+            if let Cow::Owned(sanitized) = sanitize_name(&name) {
+                name = sanitized;
+            }
+        }
+        if let Some(max_width) = cfg.max_name_width {
+            truncate_name(&mut name, max_width);
+        }
+        return with_prepared_message(format_args!("{name}"), depth, cfg, state, exit, f);
+    }
+    with_prepared_message(name, depth, cfg, state, exit, f)
+}
+
+/// Does the same thing as `with_message` for a name that is already sanitized and truncated.
+fn with_prepared_message<R>(
+    name: Arguments,
+    depth: usize,
+    cfg: &Config,
+    state: &mut RenderState,
+    exit: bool,
+    f: impl FnOnce(&Message<'_>) -> R,
+) -> R {
+    let (prefix, timestamp) = match cfg.format {
+        Format::Tree => (state.prefix(depth, cfg), 0),
+        Format::ChromeTrace => state.next_event(),
+        Format::Html => (state.html_header(), 0),
+        Format::Csv if exit => (state.csv_header(), 0),
+        Format::FoldedStacks => state.folded_frame(name, exit),
+        Format::Flat | Format::JsonLines | Format::Markdown | Format::Csv => ("", 0),
+    };
+    f(&Message {
+        name,
+        depth,
+        cfg,
+        prefix,
+        exit,
+        timestamp,
+    })
+}
+
+/// A single line of output of a span, which is rendered lazily so that it can be written into a
+/// writer without materializing it as a `String` first.
+#[derive(Debug)]
+pub(crate) struct Message<'m> {
+    name: Arguments<'m>,
+    depth: usize,
+    cfg: &'m Config,
+    /// The indentation in `Format::Tree`, the separator of events in `Format::ChromeTrace` and the
+    /// stack of enclosing spans in `Format::FoldedStacks`.
+    prefix: &'m str,
+    exit: bool,
+    /// The timestamp of the event in microseconds in `Format::ChromeTrace` and the self time of
+    /// the span in microseconds in `Format::FoldedStacks`.
+    timestamp: u128,
+}
+
+/// Renders `message` into a `String`, returning it along with the byte offset of the glyph.
+///
+/// It is a free function rather than a method, so that it can be passed to `with_message`, whose
+/// callback has to accept a message of any lifetime.
+#[cfg(feature = "std")]
+pub(crate) fn render_message(message: &Message<'_>) -> (String, usize) {
+    let mut rendered = String::new();
+    let _ = message.write_head(&mut rendered);
+    let glyph_offset = rendered.len();
+    let _ = message.write_tail(&mut rendered);
+    (rendered, glyph_offset)
+}
+
+impl Message<'_> {
+    /// Writes everything that precedes the glyph: the indentation in `Format::Tree`, the depth in
+    /// `Format::Flat`, the separator of events in `Format::ChromeTrace` and the enclosing spans in
+    /// `Format::FoldedStacks`.
+    fn write_head<W: core::fmt::Write>(&self, w: &mut W) -> core::fmt::Result {
+        let cfg = self.cfg;
+        match cfg.format {
+            Format::Tree => {
+                if let Some(color_map) = cfg.color_map {
+                    color_map(self.depth).write_escape(w)?;
+                }
+                let padding = self.head_width() - self.depth.saturating_mul(indent_width(cfg));
+                write!(w, "{}{:padding$}", self.prefix, "")?;
+                if let Some(color) = cfg.guide_color() {
+                    write!(w, "\x1b[38;5;{}m", color(self.depth))?;
+                }
+                Ok(())
+            }
+            Format::Flat => write!(w, "{} ", self.depth),
+            Format::Markdown if !self.exit => {
+                write!(w, "{:indentation$}", "", indentation = 2 * self.depth)
+            }
+            Format::Html => write!(
+                w,
+                "{}{:indentation$}",
+                self.prefix,
+                "",
+                indentation = 2 * self.depth
+            ),
+            Format::Csv | Format::ChromeTrace => w.write_str(self.prefix),
+            Format::FoldedStacks if self.exit => w.write_str(self.prefix),
+            Format::JsonLines | Format::Markdown | Format::FoldedStacks => Ok(()),
+        }
+    }
+
+    /// Returns the width of the head in `Format::Tree` in characters, i.e. the column of the glyph.
+    fn head_width(&self) -> usize {
+        let indentation = self.depth.saturating_mul(indent_width(self.cfg));
+        self.cfg
+            .glyph_column
+            .map_or(indentation, |column| column.max(indentation))
+    }
+
+    /// Writes the glyph and everything that follows it, including the trailing newline.
+    fn write_tail<W: core::fmt::Write>(&self, w: &mut W) -> core::fmt::Result {
+        let (cfg, name, depth, exit) = (self.cfg, self.name, self.depth, self.exit);
+        match cfg.format {
+            Format::Tree => {
+                let is_displayed = match cfg.skip {
+                    0 => false,
+                    _ => depth.is_multiple_of(cfg.skip),
+                };
+                let glyph = match (is_displayed, exit) {
+                    (false, _) => ' ',
+                    (true, false) => cfg.enter_char,
+                    (true, true) => cfg.exit_char,
+                };
+                write!(w, "{glyph}{name}")?;
+                if let Some(column) = cfg.name_column {
+                    let mut width = CharCount(self.head_width() + 1);
+                    let _ = width.write_fmt(name);
+                    let padding = column.saturating_sub(width.0);
+                    write!(w, "{:padding$}", "")?;
+                }
+                if let Some((enter_label, exit_label)) = cfg.labels {
+                    let label = if exit { exit_label } else { enter_label };
+                    write!(w, " {label}")?;
+                }
+                if cfg.color_map.is_some() || cfg.guide_color().is_some() {
+                    w.write_str("\x1b[0m")?;
+                }
+                w.write_char('\n')
+            }
+            Format::Flat => {
+                let marker = if exit { 'X' } else { 'E' };
+                writeln!(w, "{marker} {}", FlatName(&name.to_string()))
+            }
+            Format::JsonLines => {
+                let name = name.to_string();
+                let event = if exit { "exit" } else { "enter" };
+                writeln!(
+                    w,
+                    "{{\"event\":\"{event}\",\"name\":\"{}\",\"depth\":{depth}}}",
+                    json_escape(&name)
+                )
+            }
+            Format::ChromeTrace => {
+                let name = name.to_string();
+                let phase = if exit { 'E' } else { 'B' };
+                writeln!(
+                    w,
+                    "{{\"name\":\"{}\",\"ph\":\"{phase}\",\"ts\":{},\"pid\":{},\"tid\":{}}}",
+                    json_escape(&name),
+                    self.timestamp,
+                    process_id(),
+                    thread_number()
+                )
+            }
+            Format::FoldedStacks if exit => {
+                writeln!(w, "{} {}", FoldedName(name), self.timestamp)
+            }
+            Format::Markdown if !exit => writeln!(w, "- {name}"),
+            // Markdown has no exit line, and the row of a CSV needs the times of the span, so it
+            // is appended by the spanner
+            Format::FoldedStacks | Format::Markdown | Format::Csv => Ok(()),
+            Format::Html if exit => w.write_str("</details>\n"),
+            Format::Html => writeln!(
+                w,
+                "<details><summary>{}</summary>",
+                html_escape(&name.to_string())
+            ),
+        }
+    }
+}
+
+impl core::fmt::Display for Message<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.write_head(f)?;
+        self.write_tail(f)
+    }
+}
+
+/// A `core::fmt::Write` sink that only counts the columns of what is written to it.
+struct CharCount(usize);
+
+impl core::fmt::Write for CharCount {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.0 += s.chars().map(char_width).sum::<usize>();
+        Ok(())
+    }
+}
+
+/// Returns the number of columns `c` takes, which is `1` unless the `unicode-width` feature is
+/// enabled.
+#[cfg(not(feature = "unicode-width"))]
+const fn char_width(_c: char) -> usize {
+    1
+}
+
+/// Returns the number of columns `c` takes in a terminal, e.g. `2` for CJK characters and `0` for
+/// combining characters.
+#[cfg(feature = "unicode-width")]
+fn char_width(c: char) -> usize {
+    unicode_width::UnicodeWidthChar::width(c).unwrap_or(0)
+}
+
+/// Returns the longest prefix of `s` that fits into `max_width` columns along with its width.
+fn prefix_within(s: &str, max_width: usize) -> (&str, usize) {
+    let mut width = 0;
+    for (i, c) in s.char_indices() {
+        let next = width + char_width(c);
+        if next > max_width {
+            return (&s[..i], width);
+        }
+        width = next;
+    }
+    (s, width)
+}
+
+/// A span name in `Format::Flat`, quoted if it is empty or contains whitespace, quotes or
+/// backslashes.
+struct FlatName<'n>(&'n str);
+
+impl core::fmt::Display for FlatName<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let name = self.0;
+        let needs_quotes = name.is_empty()
+            || name
+                .chars()
+                .any(|c| c.is_whitespace() || c == '"' || c == '\\');
+        if !needs_quotes {
+            return f.write_str(name);
+        }
+        f.write_char('"')?;
+        for c in name.chars() {
+            if c == '"' || c == '\\' {
+                f.write_char('\\')?;
+            }
+            f.write_char(c)?;
+        }
+        f.write_char('"')
+    }
+}
+
+/// A span name in `Format::FoldedStacks`, with semicolons replaced so that they can not be
+/// confused with the separators of frames.
+struct FoldedName<'n>(Arguments<'n>);
+
+impl core::fmt::Display for FoldedName<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.0.as_str() {
+            Some(name) if !name.contains(';') => f.write_str(name),
+            _ => f.write_str(&self.0.to_string().replace(';', ":")),
+        }
+    }
+}
+
+/// An open span in `Format::FoldedStacks`.
+#[derive(Debug)]
+struct Frame {
+    /// The length of the stack including the name of the span.
+    end: usize,
+    start: Instant,
+    /// The total time spent in the child spans.
+    children: Duration,
+}
+
+/// The state that rendering messages depends on.
+///
+/// It caches the indentation segments of every depth rendered so far. The prefix of a span at
+/// depth `n` is the concatenation of the segments of depths `0..n`, so the segments are rendered
+/// once, the first time a depth is reached, and every following span only slices the cached
+/// string instead of rendering its prefix from scratch.
+///
+/// For `Format::ChromeTrace` it also tracks the epoch of the timestamps and whether the array of
+/// events has been opened, and for `Format::FoldedStacks` the stack of open spans.
+#[derive(Debug, Default)]
+pub(crate) struct RenderState {
+    rendered: String,
+    ends: Vec<usize>,
+    epoch: Option<Instant>,
+    opened: bool,
+    stack: String,
+    frames: Vec<Frame>,
+}
+
+impl RenderState {
+    /// Opens or closes a frame of `Format::FoldedStacks`. Closing returns the stack of the
+    /// enclosing spans, e.g. `main;parse;`, and the self time of the span in microseconds.
+    fn folded_frame(&mut self, name: Arguments, exit: bool) -> (&str, u128) {
+        // The name of the last closed span is only removed here, as the stack is borrowed when
+        // it is closed
+        self.stack
+            .truncate(self.frames.last().map_or(0, |frame| frame.end));
+        if !exit {
+            let _ = write!(self.stack, "{};", FoldedName(name));
+            self.frames.push(Frame {
+                end: self.stack.len(),
+                start: Instant::now(),
+                children: Duration::ZERO,
+            });
+            return ("", 0);
+        }
+        let Some(frame) = self.frames.pop() else {
+            return ("", 0);
+        };
+        let elapsed = frame.start.elapsed();
+        let parent_end = self.frames.last_mut().map_or(0, |parent| {
+            parent.children += elapsed;
+            parent.end
+        });
+        let self_time = elapsed.saturating_sub(frame.children);
+        (&self.stack[..parent_end], self_time.as_micros())
+    }
+
+    /// Returns the separator that precedes the next `Format::ChromeTrace` event, which opens the
+    /// array for the first event, and the timestamp of the event in microseconds since the first
+    /// event.
+    fn next_event(&mut self) -> (&'static str, u128) {
+        let epoch = *self.epoch.get_or_insert_with(Instant::now);
+        let separator = if core::mem::replace(&mut self.opened, true) {
+            ","
+        } else {
+            "[\n"
+        };
+        (separator, epoch.elapsed().as_micros())
+    }
+
+    /// Returns the header of the `Format::Html` document before the first span, and nothing
+    /// afterwards.
+    const fn html_header(&mut self) -> &'static str {
+        if core::mem::replace(&mut self.opened, true) {
+            ""
+        } else {
+            HTML_HEADER
+        }
+    }
+
+    /// Returns the header of `Format::Csv` before the first row, and nothing afterwards.
+    const fn csv_header(&mut self) -> &'static str {
+        if core::mem::replace(&mut self.opened, true) {
+            ""
+        } else {
+            "name,depth,enter_ts_us,exit_ts_us,duration_us\n"
+        }
+    }
+
+    /// Returns the footer that closes the array of `Format::ChromeTrace` events or the
+    /// `Format::Html` document, opening it first if no events have been written.
+    #[cfg(feature = "std")]
+    pub(crate) fn footer(&mut self, format: Format) -> Cow<'static, str> {
+        let opened = core::mem::replace(&mut self.opened, true);
+        match (format, opened) {
+            (Format::ChromeTrace, true) => Cow::Borrowed("]\n"),
+            (Format::ChromeTrace, false) => Cow::Borrowed("[\n]\n"),
+            (Format::Html, true) => Cow::Borrowed(HTML_FOOTER),
+            (Format::Html, false) => Cow::Owned([HTML_HEADER, HTML_FOOTER].concat()),
+            _ => Cow::Borrowed(""),
+        }
+    }
+
+    /// Returns the indentation prefix of a span at `depth`, rendering the missing segments.
+    pub(crate) fn prefix(&mut self, depth: usize, cfg: &Config) -> &str {
+        while self.ends.len() < depth {
+            push_segment(&mut self.rendered, self.ends.len(), cfg);
+            self.ends.push(self.rendered.len());
+        }
+        let end = depth.checked_sub(1).map_or(0, |last| self.ends[last]);
+        &self.rendered[..end]
+    }
+}
+
+/// Pushes the indentation segment of `depth`: a vertical bar, a guide string or nothing, padded
+/// with spaces to the tab width, or the indentation string if one is configured.
+fn push_segment(buf: &mut String, depth: usize, cfg: &Config) {
+    let is_displayed = match cfg.skip {
+        0 => false,
+        _ => depth.is_multiple_of(cfg.skip),
+    };
+    if let Some(indent) = cfg.indent_str {
+        if is_displayed {
+            push_colored(buf, cfg.guide_color(), depth, format_args!("{indent}"));
+        } else {
+            buf.extend(core::iter::repeat_n(' ', indent_width(cfg)));
+        }
+        return;
+    }
+    let tabwidth = tabwidth(cfg);
+    if tabwidth == 0 {
+        return;
+    }
+    let mut width = 0;
+    if is_displayed {
+        if let Some(depthmap_str) = cfg.depthmap_str {
+            let guide;
+            (guide, width) = prefix_within(depthmap_str(depth), tabwidth);
+            push_colored(buf, cfg.guide_color(), depth, format_args!("{guide}"));
+        } else {
+            width = 1;
+            push_colored(
+                buf,
+                cfg.guide_color(),
+                depth,
+                format_args!("{}", (cfg.depthmap)(depth)),
+            );
+        }
+    }
+    for _ in width..tabwidth {
+        buf.push(' ');
+    }
+}
+
+/// Returns `Config::tabwidth`, clamped to `MAX_TABWIDTH` so that extreme tab widths can not
+/// allocate absurd amounts of memory.
+fn tabwidth(cfg: &Config) -> usize {
+    cfg.tabwidth.min(MAX_TABWIDTH)
+}
+
+/// Returns the width of the indentation of a single depth, which is the width of
+/// `Config::indent_str` if it is set and the clamped `Config::tabwidth` otherwise.
+fn indent_width(cfg: &Config) -> usize {
+    cfg.indent_str
+        .map_or(tabwidth(cfg), |indent| indent.chars().map(char_width).sum())
+}
+
+/// Pushes `content` to `buf`, wrapped in the escape sequence of the color of `depth` if colors
+/// are configured.
+pub(crate) fn push_colored(
+    buf: &mut String,
+    color: Option<fn(usize) -> u8>,
+    depth: usize,
+    content: Arguments,
+) {
+    match color {
+        Some(color) => {
+            let _ = write!(buf, "\x1b[38;5;{}m{content}\x1b[0m", color(depth));
+        }
+        None => {
+            let _ = buf.write_fmt(content);
+        }
+    }
+}
+
+/// The beginning of a `Format::Html` document, up to the elements of the spans.
+const HTML_HEADER: &str = "<!DOCTYPE html>
+<html>
+<head><meta charset=\"utf-8\"><title>spannify</title></head>
+<body>
+";
+
+/// The end of a `Format::Html` document.
+#[cfg(feature = "std")]
+const HTML_FOOTER: &str = "</body>
+</html>
+";
+
+/// Escapes the characters of a string that are special in HTML text and attributes.
+fn html_escape(s: &str) -> Cow<'_, str> {
+    if !s.contains(['&', '<', '>', '"', '\'']) {
+        return Cow::Borrowed(s);
+    }
+    let mut escaped = String::with_capacity(s.len() + 8);
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Replaces the line breaks of rendered output with `Config::newline`.
+pub(crate) fn translate_newlines<'o>(output: &'o str, cfg: &Config) -> Cow<'o, str> {
+    if cfg.newline == "\n" || !output.contains('\n') {
+        return Cow::Borrowed(output);
+    }
+    Cow::Owned(output.replace('\n', cfg.newline))
+}
+
+/// Quotes a CSV field if it contains commas, quotes or line breaks, doubling its quotes.
+#[cfg(feature = "std")]
+pub(crate) fn csv_escape(s: &str) -> Cow<'_, str> {
+    if !s.contains([',', '"', '\n', '\r']) {
+        return Cow::Borrowed(s);
+    }
+    Cow::Owned(format!("\"{}\"", s.replace('"', "\"\"")))
+}
+
+/// Escapes a string so that it can be embedded in a JSON string literal.
+pub(crate) fn json_escape(s: &str) -> Cow<'_, str> {
+    if !s.chars().any(|c| c == '"' || c == '\\' || c.is_control()) {
+        return Cow::Borrowed(s);
+    }
+    let mut escaped = String::with_capacity(s.len() + 2);
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\u{:04x}", u32::from(c));
+            }
+            c => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Truncates a name that is wider than `max_width` columns so that it is at most `max_width`
+/// columns wide, the last of them being `…`. Without the `unicode-width` feature every character
+/// is one column wide, so the name is exactly `max_width` characters long.
+pub(crate) fn truncate_name(name: &mut String, max_width: usize) {
+    if prefix_within(name, max_width).0.len() == name.len() {
+        return;
+    }
+    let end = prefix_within(name, max_width.saturating_sub(1)).0.len();
+    name.truncate(end);
+    if max_width > 0 {
+        name.push('…');
+    }
+}
+
+/// Escapes the control characters of a span name as `\xNN` sequences.
+fn sanitize_name(name: &str) -> Cow<'_, str> {
+    if !name.chars().any(char::is_control) {
+        return Cow::Borrowed(name);
+    }
+    let mut sanitized = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_control() {
+            let _ = write!(sanitized, "\\x{:02x}", u32::from(c));
+        } else {
+            sanitized.push(c);
+        }
+    }
+    Cow::Owned(sanitized)
+}
+
+/// Returns a number that identifies the current thread, assigned in the order threads first ask
+/// for it, starting at `1`.
+#[cfg(feature = "std")]
+fn thread_number() -> usize {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    /// The number of the next thread that asks for its number
+    static NEXT: AtomicUsize = AtomicUsize::new(1);
+    thread_local! {
+        static NUMBER: usize = NEXT.fetch_add(1, Ordering::Relaxed);
+    }
+    NUMBER.with(|number| *number)
+}
+
+/// Without `std` there are no threads to tell apart, so every line comes from thread `1`.
+#[cfg(not(feature = "std"))]
+const fn thread_number() -> usize {
+    1
+}
+
+/// Returns the id of the current process.
+#[cfg(feature = "std")]
+fn process_id() -> u32 {
+    std::process::id()
+}
+
+/// Without `std` there are no processes to tell apart, so every line comes from process `0`.
+#[cfg(not(feature = "std"))]
+const fn process_id() -> u32 {
+    0
+}
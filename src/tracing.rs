@@ -0,0 +1,239 @@
+//! A `tracing` front-end
+//!
+//! This module provides `TracingSpanner`, which opens a real `tracing` span for every span instead
+//! of writing text, so that spannify can be used as a lightweight front-end to `tracing`
+//! subscribers.
+
+use core::fmt::Arguments;
+
+use ::tracing::span::EnteredSpan;
+
+use crate::config::Config;
+use crate::level::Level;
+
+/// The name of every `tracing` span opened by a `TracingSpanner`. The name of the spannify span
+/// is recorded in the `name` field, as `tracing` span names have to be known at compile time.
+pub const SPAN_NAME: &str = "span";
+
+/// The target of every `tracing` span opened by a `TracingSpanner`.
+pub const TARGET: &str = "spannify";
+
+/// A spanner that opens a `tracing` span on enter and closes it on drop, instead of writing text.
+///
+/// Every span becomes a `tracing` span named `SPAN_NAME`, with the `TARGET` target, the level of
+/// the span converted into a `tracing::Level`, and the formatted name of the span in its `name`
+/// field. The span is entered on the current thread for as long as its guard lives, so events and
+/// spans created inside of it are nested under it by the subscriber.
+///
+/// The level filter and the name filter of `Config` are applied before a span is opened, and the
+/// subscriber applies its own filters on top of them. The rendering options of `Config` have no
+/// effect, since the subscriber decides how spans are displayed.
+///
+/// # Examples
+/// ```
+/// use spannify::{spf, tracing::TracingSpanner};
+///
+/// let spanner = TracingSpanner::new();
+/// let _span = spf!(spanner, "request({})", 42);
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct TracingSpanner {
+    config: Config,
+}
+
+impl TracingSpanner {
+    /// Creates a new `TracingSpanner` instance with the default configuration.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets a custom configuration for the spanner.
+    ///
+    /// # Parameters
+    /// - `cfg`: The new configuration to use.
+    ///
+    /// # Examples
+    /// ```
+    /// use spannify::{config::Config, level::Level, tracing::TracingSpanner};
+    ///
+    /// let spanner = TracingSpanner::new().with_config(Config::new().with_level(Level::Debug));
+    /// ```
+    #[must_use]
+    pub fn with_config(self, cfg: Config) -> Self {
+        Self { config: cfg }
+    }
+
+    /// Enters a span with `Level::Info`, opening and entering a `tracing` span.
+    ///
+    /// # Parameters
+    /// - `name`: The name of the span. It is recorded in the `name` field of the `tracing` span
+    #[must_use]
+    pub fn enter_span(&self, name: &str) -> TracingSpan {
+        self.enter_with_level(Level::Info, name)
+    }
+
+    /// Enters a span with `level`, opening and entering a `tracing` span if level is not less than
+    /// `Config::level`.
+    ///
+    /// # Parameters
+    /// - `level`: The level of the span. It determines if the span would be opened or not
+    /// - `name`: The name of the span. It is recorded in the `name` field of the `tracing` span
+    #[must_use]
+    pub fn enter_with_level(&self, level: Level, name: &str) -> TracingSpan {
+        self.enter_args(level, format_args!("{name}"))
+    }
+
+    /// Does the same thing as `enter_with_level`, but uses `std::fmt::Arguments` instead of `&str`
+    /// as a name to avoid allocations in spf! macro.
+    #[must_use]
+    pub fn enter_args(&self, level: Level, args: Arguments) -> TracingSpan {
+        let entered = !cfg!(feature = "disabled")
+            && self.config.level <= level
+            && self.config.passes_name_filter(args);
+        TracingSpan {
+            inner: entered.then(|| open(level, args).entered()),
+        }
+    }
+}
+
+/// Opens a `tracing` span for a span with `level` and the name `args`.
+///
+/// `tracing` keeps the metadata of every call site in a static, so the level has to be a constant
+/// and there is a call site per level.
+fn open(level: Level, args: Arguments) -> ::tracing::Span {
+    macro_rules! span {
+        ($level:expr) => {
+            ::tracing::span!(target: TARGET, $level, SPAN_NAME, name = %args)
+        };
+    }
+    match level {
+        Level::Trace => span!(::tracing::Level::TRACE),
+        Level::Debug => span!(::tracing::Level::DEBUG),
+        Level::Info => span!(::tracing::Level::INFO),
+        Level::Warn => span!(::tracing::Level::WARN),
+        Level::Error => span!(::tracing::Level::ERROR),
+    }
+}
+
+/// A span produced by a `TracingSpanner`. It exits and closes its `tracing` span when dropped.
+///
+/// Like the guard of an entered `tracing` span, it has to be dropped on the thread that created
+/// it.
+#[derive(Debug)]
+pub struct TracingSpan {
+    inner: Option<EnteredSpan>,
+}
+
+impl TracingSpan {
+    /// Returns the `tracing` span, or `None` if the span was filtered out, e.g. to record fields
+    /// on it or to use it as the parent of spans created on other threads.
+    #[must_use]
+    pub fn tracing_span(&self) -> Option<&::tracing::Span> {
+        self.inner.as_deref()
+    }
+}
+
+#[cfg(all(test, not(feature = "disabled")))]
+mod tests {
+    use std::fmt::Debug;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use ::tracing::field::{Field, Visit};
+    use ::tracing::span::{Attributes, Id, Record};
+    use ::tracing::{Event, Metadata, Subscriber};
+
+    use super::*;
+    use crate::spf;
+
+    /// A subscriber that captures when spans are opened, entered, exited and closed.
+    #[derive(Default)]
+    struct Capture {
+        next_id: AtomicU64,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    /// Extracts the `name` field of a span.
+    struct NameField(String);
+
+    impl Visit for NameField {
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            if field.name() == "name" {
+                self.0 = format!("{value:?}");
+            }
+        }
+    }
+
+    impl Subscriber for Capture {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut name = NameField(String::new());
+            span.record(&mut name);
+            let metadata = span.metadata();
+            self.log.lock().unwrap().push(format!(
+                "new {}:{} {} {}",
+                metadata.target(),
+                metadata.name(),
+                metadata.level(),
+                name.0
+            ));
+            Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed) + 1)
+        }
+
+        fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+        fn event(&self, _event: &Event<'_>) {}
+
+        fn enter(&self, span: &Id) {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("enter {}", span.into_u64()));
+        }
+
+        fn exit(&self, span: &Id) {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("exit {}", span.into_u64()));
+        }
+
+        fn try_close(&self, id: Id) -> bool {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("close {}", id.into_u64()));
+            true
+        }
+    }
+
+    #[test]
+    fn tracing_spanner() {
+        let subscriber = Capture::default();
+        let log = Arc::clone(&subscriber.log);
+        let spanner = TracingSpanner::new().with_config(Config::new().with_level(Level::Debug));
+        ::tracing::subscriber::with_default(subscriber, || {
+            let _outer = spf!(spanner, Level::Warn, "fib({})", 2);
+            let _inner = spanner.enter_with_level(Level::Debug, "fib(1)");
+            let filtered = spanner.enter_with_level(Level::Trace, "filtered");
+            assert!(filtered.tracing_span().is_none());
+        });
+        let expected = [
+            "new spannify:span WARN fib(2)",
+            "enter 1",
+            "new spannify:span DEBUG fib(1)",
+            "enter 2",
+            "exit 2",
+            "close 2",
+            "exit 1",
+            "close 1",
+        ];
+        assert_eq!(*log.lock().unwrap(), expected);
+    }
+}
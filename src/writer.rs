@@ -0,0 +1,722 @@
+//! Writer adapters
+//!
+//! This module provides `std::io::Write` adapters that can be used as the writer of a `Spanner`,
+//! and the `SpanOutput` trait that lets spanners write into `core::fmt::Write` sinks as well.
+
+use core::fmt;
+#[cfg(feature = "std")]
+use std::fs::File;
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+
+/// The error of a `SpanOutput`, which is `std::io::Error` with the `std` feature and
+/// `core::fmt::Error` without it.
+#[cfg(feature = "std")]
+pub type OutputError = io::Error;
+
+/// The error of a `SpanOutput`, which is `std::io::Error` with the `std` feature and
+/// `core::fmt::Error` without it.
+#[cfg(not(feature = "std"))]
+pub type OutputError = fmt::Error;
+
+/// The output of a spanner, which receives chunks of whole rendered lines.
+///
+/// It is implemented for every `std::io::Write`, and for every `core::fmt::Write` wrapped in a
+/// `FmtWriter`, so it rarely needs to be implemented by hand, except for custom writers without
+/// the `std` feature.
+pub trait SpanOutput {
+    /// Writes a chunk of rendered output, which consists of whole lines.
+    ///
+    /// # Errors
+    /// Returns an error if the output could not be written.
+    fn write_output(&mut self, output: &str) -> Result<(), OutputError>;
+
+    /// Flushes the output, if it is buffered.
+    ///
+    /// # Errors
+    /// Returns an error if the output could not be flushed.
+    fn flush_output(&mut self) -> Result<(), OutputError>;
+}
+
+#[cfg(feature = "std")]
+impl<T> SpanOutput for T
+where
+    T: Write,
+{
+    fn write_output(&mut self, output: &str) -> io::Result<()> {
+        self.write_all(output.as_bytes())
+    }
+
+    fn flush_output(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+/// An adapter that lets a `Spanner` write into a `std::fmt::Write` sink, such as a `String`,
+/// without going through bytes and UTF-8 validation.
+///
+/// # Examples
+/// ```
+/// use spannify::{local::LocalSpanner, writer::FmtWriter};
+///
+/// let mut trace = String::new();
+/// {
+///     let spanner = LocalSpanner::from_writer(FmtWriter::new(&mut trace));
+///     let _span = spanner.enter_span("work");
+/// }
+/// # #[cfg(not(feature = "disabled"))]
+/// assert_eq!(trace, "┌work\n└work\n");
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct FmtWriter<W> {
+    inner: W,
+}
+
+impl<W> FmtWriter<W>
+where
+    W: fmt::Write,
+{
+    /// Creates a new `FmtWriter` from a `std::fmt::Write` sink.
+    ///
+    /// # Parameters
+    /// - `inner`: The sink that is written to.
+    pub const fn new(inner: W) -> Self {
+        Self { inner }
+    }
+
+    /// Returns a reference to the inner sink.
+    pub const fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Consumes the `FmtWriter`, returning the inner sink.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W> SpanOutput for FmtWriter<W>
+where
+    W: fmt::Write,
+{
+    fn write_output(&mut self, output: &str) -> Result<(), OutputError> {
+        let result = self.inner.write_str(output);
+        #[cfg(feature = "std")]
+        let result = result.map_err(|fmt::Error| io::Error::other("formatter error"));
+        result
+    }
+
+    fn flush_output(&mut self) -> Result<(), OutputError> {
+        Ok(())
+    }
+}
+
+/// A writer that forwards everything written to it to two inner writers, e.g. to show spans on
+/// screen and save them to a file at the same time.
+///
+/// Every write and flush is attempted on both writers, even if the first one fails, and the
+/// first error is returned afterwards.
+///
+/// # Examples
+/// ```
+/// use spannify::{core::Spanner, writer::Tee};
+///
+/// let spanner = Spanner::from_writer(Tee::new(std::io::stdout(), Vec::new()));
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Default, Clone)]
+pub struct Tee<A, B> {
+    first: A,
+    second: B,
+}
+
+#[cfg(feature = "std")]
+impl<A, B> Tee<A, B>
+where
+    A: Write,
+    B: Write,
+{
+    /// Creates a new `Tee` from two writers.
+    ///
+    /// # Parameters
+    /// - `first`: The writer that is written to first.
+    /// - `second`: The writer that is written to second.
+    pub const fn new(first: A, second: B) -> Self {
+        Self { first, second }
+    }
+
+    /// Returns references to the inner writers.
+    pub const fn get_ref(&self) -> (&A, &B) {
+        (&self.first, &self.second)
+    }
+
+    /// Consumes the `Tee`, returning the inner writers.
+    pub fn into_inner(self) -> (A, B) {
+        (self.first, self.second)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<A, B> Write for Tee<A, B>
+where
+    A: Write,
+    B: Write,
+{
+    /// Writes the whole buffer to both writers, so that they never diverge on partial writes.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let first = self.first.write_all(buf);
+        let second = self.second.write_all(buf);
+        first.and(second).map(|()| buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let first = self.first.flush();
+        let second = self.second.flush();
+        first.and(second)
+    }
+}
+
+/// A writer that caps the number of bytes written to an inner writer, e.g. to keep a runaway
+/// trace from filling up the disk.
+///
+/// Once a write would exceed the limit, `… output truncated` is written on a line of its own and
+/// that write and all the following ones are discarded. Writes are discarded whole, so a
+/// `Spanner`, which writes whole lines at once, never leaves a line cut off. The notice itself
+/// does not count towards the limit.
+///
+/// # Examples
+/// ```
+/// use spannify::{core::Spanner, writer::LimitedWriter};
+///
+/// let spanner = Spanner::from_writer(LimitedWriter::new(std::io::stdout(), 64 * 1024 * 1024));
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Default, Clone)]
+pub struct LimitedWriter<W> {
+    inner: W,
+    limit: usize,
+    written: usize,
+    truncated: bool,
+}
+
+#[cfg(feature = "std")]
+impl<W> LimitedWriter<W>
+where
+    W: Write,
+{
+    /// Creates a new `LimitedWriter` from a writer and a limit.
+    ///
+    /// # Parameters
+    /// - `inner`: The writer that is written to.
+    /// - `limit`: The maximum number of bytes written to `inner`, excluding the notice.
+    pub const fn new(inner: W, limit: usize) -> Self {
+        Self {
+            inner,
+            limit,
+            written: 0,
+            truncated: false,
+        }
+    }
+
+    /// Returns whether the limit was reached and writes have been discarded.
+    pub const fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Returns a reference to the inner writer.
+    pub const fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Consumes the `LimitedWriter`, returning the inner writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W> Write for LimitedWriter<W>
+where
+    W: Write,
+{
+    /// Writes the whole buffer if it fits into the limit, or discards it otherwise, writing the
+    /// notice the first time.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.truncated {
+            return Ok(buf.len());
+        }
+        if self.written.saturating_add(buf.len()) > self.limit {
+            self.truncated = true;
+            self.inner.write_all("… output truncated\n".as_bytes())?;
+            return Ok(buf.len());
+        }
+        self.inner.write_all(buf)?;
+        self.written += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A writer that sends every line written to it as a `String` over an `mpsc` channel, e.g. to
+/// collect spans on a background thread without sharing a writer.
+///
+/// Lines are sent once their newline is written, including the newline. If the receiver has been
+/// dropped, writes fail with `io::ErrorKind::BrokenPipe`.
+///
+/// # Examples
+/// ```
+/// use spannify::{core::Spanner, writer::ChannelWriter};
+/// use std::sync::mpsc;
+///
+/// let (tx, rx) = mpsc::channel();
+/// let printer = std::thread::spawn(move || {
+///     for line in rx {
+///         print!("{line}");
+///     }
+/// });
+///
+/// let spanner = Spanner::from_writer(ChannelWriter::new(tx));
+/// drop(spanner.enter_span("work"));
+/// drop(spanner);
+/// printer.join().unwrap();
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct ChannelWriter {
+    sender: std::sync::mpsc::Sender<String>,
+    buffer: Vec<u8>,
+}
+
+#[cfg(feature = "std")]
+impl ChannelWriter {
+    /// Creates a new `ChannelWriter` from the sending half of a channel.
+    ///
+    /// # Parameters
+    /// - `sender`: The sender that the lines are sent with.
+    #[must_use]
+    pub const fn new(sender: std::sync::mpsc::Sender<String>) -> Self {
+        Self {
+            sender,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Sends `line` over the channel, failing if the receiver has been dropped.
+    fn send(&self, line: &[u8]) -> io::Result<()> {
+        let line = String::from_utf8_lossy(line).into_owned();
+        self.sender
+            .send(line)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "receiver dropped"))
+    }
+}
+
+#[cfg(feature = "std")]
+impl Write for ChannelWriter {
+    /// Buffers `buf` and sends every line that it completes.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            self.send(&line)?;
+        }
+        Ok(buf.len())
+    }
+
+    /// Sends the incomplete last line, if there is one.
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let line = std::mem::take(&mut self.buffer);
+        self.send(&line)
+    }
+}
+
+/// A file writer that rolls over to a new file once the current one would exceed a size, e.g. to
+/// keep the files of long-running traces manageable.
+///
+/// The first file is created at the given path, e.g. `trace.log`, and the following ones get a
+/// number inserted before the extension, i.e. `trace.1.log`, `trace.2.log` and so on. Files are
+/// only rotated between writes, and a `Spanner` writes whole lines at once, so span lines are
+/// never split across files. A single write that is larger than the maximum size is written to
+/// a file of its own.
+///
+/// # Examples
+/// ```
+/// use spannify::{core::Spanner, writer::RotatingFile};
+///
+/// let file = RotatingFile::new("/tmp/trace.log", 1024 * 1024).unwrap();
+/// let spanner = Spanner::from_writer(file);
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    file: File,
+    written: u64,
+    index: usize,
+}
+
+#[cfg(feature = "std")]
+impl RotatingFile {
+    /// Creates the first file at `path`, truncating it if it exists.
+    ///
+    /// # Parameters
+    /// - `path`: The path of the first file, which the paths of the next files are derived from.
+    /// - `max_bytes`: The size that a file may not exceed, unless a single write is larger.
+    ///
+    /// # Errors
+    /// Returns an error if the file could not be created.
+    pub fn new(path: impl AsRef<Path>, max_bytes: u64) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = File::create(&path)?;
+        Ok(Self {
+            path,
+            max_bytes,
+            file,
+            written: 0,
+            index: 0,
+        })
+    }
+
+    /// Returns the path of the file that is currently written to.
+    #[must_use]
+    pub fn current_path(&self) -> PathBuf {
+        self.path_of(self.index)
+    }
+
+    /// Returns the path of the file with `index`, inserting the index before the extension.
+    fn path_of(&self, index: usize) -> PathBuf {
+        if index == 0 {
+            return self.path.clone();
+        }
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = self.path.extension().map_or_else(
+            || format!("{stem}.{index}"),
+            |extension| format!("{stem}.{index}.{}", extension.to_string_lossy()),
+        );
+        self.path.with_file_name(name)
+    }
+
+    /// Flushes the current file and creates the next one.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.file = File::create(self.path_of(self.index + 1))?;
+        self.index += 1;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl Write for RotatingFile {
+    /// Writes all of `buf` to the current file, rotating first if it would exceed the maximum
+    /// size, so that a line is never split across two files.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len() as u64;
+        if self.written > 0 && self.written.saturating_add(len) > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(buf)?;
+        self.written += len;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// A writer that forwards every line written to it to the `log` facade, so that spans end up in
+/// whatever `log` backend the application uses. It is the writer of a `LogSpanner`.
+///
+/// Each line is logged once its newline is written, with the trailing newline removed, at the
+/// level of the span that wrote it, e.g. `Level::Debug` spans are logged with `log::Level::Debug`.
+/// Lines that do not belong to a span, such as the summary of `Config::summary`, are logged with
+/// `log::Level::Info`. Lines that are held back, e.g. by `Config::compact_leaves`, are logged at
+/// the level of the span that writes them out.
+///
+/// # Examples
+/// ```
+/// use spannify::{core::Spanner, writer::LogWriter};
+///
+/// let spanner = Spanner::from_writer(LogWriter::new().with_target("my_app::spans"));
+/// let _span = spanner.enter_span("work");
+/// ```
+#[cfg(feature = "log")]
+#[derive(Debug, Clone)]
+pub struct LogWriter {
+    target: &'static str,
+    buffer: Vec<u8>,
+}
+
+#[cfg(feature = "log")]
+impl LogWriter {
+    /// Creates a new `LogWriter` that logs with the `spannify` target.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            target: "spannify",
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Sets the target that the lines are logged with, which loggers can filter on.
+    ///
+    /// # Parameters
+    /// - `target`: The target of the log records.
+    #[must_use]
+    pub fn with_target(self, target: &'static str) -> Self {
+        Self { target, ..self }
+    }
+
+    /// Logs a single line, without its newline.
+    fn log(&self, line: &[u8]) {
+        let level = LINE_LEVEL.get().unwrap_or(crate::level::Level::Info);
+        log::log!(
+            target: self.target,
+            level.into(),
+            "{}",
+            String::from_utf8_lossy(line)
+        );
+    }
+}
+
+#[cfg(feature = "log")]
+impl Default for LogWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "log")]
+impl Write for LogWriter {
+    /// Buffers `buf` and logs every line that it completes.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            self.log(&line[..end]);
+        }
+        Ok(buf.len())
+    }
+
+    /// Logs the incomplete last line, if there is one, and flushes the logger.
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            let line = std::mem::take(&mut self.buffer);
+            self.log(&line);
+        }
+        log::logger().flush();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "log")]
+std::thread_local! {
+    /// The level of the span whose lines the calling thread is writing, for `LogWriter`.
+    static LINE_LEVEL: core::cell::Cell<Option<crate::level::Level>> =
+        const { core::cell::Cell::new(None) };
+}
+
+/// A guard that makes `LogWriter` log the lines written by the calling thread at the level of a
+/// span, restoring the previous level when dropped.
+#[cfg(feature = "log")]
+pub(crate) struct LineLevel {
+    previous: Option<crate::level::Level>,
+}
+
+#[cfg(feature = "log")]
+impl LineLevel {
+    /// Sets the level of the lines written by the calling thread to `level`.
+    pub(crate) fn enter(level: crate::level::Level) -> Self {
+        Self {
+            previous: LINE_LEVEL.replace(Some(level)),
+        }
+    }
+}
+
+#[cfg(feature = "log")]
+impl Drop for LineLevel {
+    fn drop(&mut self) {
+        LINE_LEVEL.set(self.previous);
+    }
+}
+
+#[cfg(all(test, feature = "std", not(feature = "disabled")))]
+mod tests {
+    use super::*;
+    use crate::core::Spanner;
+
+    /// A writer that always fails.
+    struct Broken;
+
+    impl Write for Broken {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+    }
+
+    #[test]
+    fn tee() {
+        let mut tee = Tee::new(Vec::new(), Vec::new());
+        {
+            let spanner = Spanner::from_writer(&mut tee);
+            let _outer = spanner.enter_span("outer");
+            let _inner = spanner.enter_span("inner");
+        }
+        let (first, second) = tee.into_inner();
+        assert_eq!(first, "┌outer\n|  inner\n|  inner\n└outer\n".as_bytes());
+        assert_eq!(first, second);
+
+        let mut tee = Tee::new(Broken, Vec::new());
+        let err = tee.write_all(b"line\n").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        assert_eq!(tee.get_ref().1, b"line\n");
+        assert!(tee.flush().is_err());
+    }
+
+    #[test]
+    fn fmt_writer() {
+        fn fib(spanner: &Spanner<FmtWriter<&mut String>>, n: usize) -> usize {
+            let _span = crate::spf!(spanner, "fib({n})");
+            match n {
+                0 | 1 => n,
+                _ => fib(spanner, n - 1) + fib(spanner, n - 2),
+            }
+        }
+
+        let mut trace = String::new();
+        let spanner = Spanner::from_fmt_writer(&mut trace);
+        assert_eq!(fib(&spanner, 3), 2);
+        spanner.flush().unwrap();
+        drop(spanner);
+        let expected = "┌fib(3)\n|  fib(2)\n|   ┌fib(1)\n|   └fib(1)\n|   ┌fib(0)\n|   └fib(0)\n|  fib(2)\n|  fib(1)\n|  fib(1)\n└fib(3)\n";
+        assert_eq!(trace, expected);
+
+        /// A sink that always fails.
+        struct Broken;
+        impl fmt::Write for Broken {
+            fn write_str(&mut self, _s: &str) -> fmt::Result {
+                Err(fmt::Error)
+            }
+        }
+        let spanner = Spanner::from_fmt_writer(Broken);
+        drop(spanner.enter_span("work"));
+        assert_eq!(spanner.last_error(), Some(io::ErrorKind::Other));
+    }
+
+    #[test]
+    fn limited_writer() {
+        let mut writer = LimitedWriter::new(Vec::new(), 20);
+        {
+            let spanner = Spanner::from_writer(&mut writer);
+            {
+                let _outer = spanner.enter_span("outer");
+                let _inner = spanner.enter_span("inner");
+            }
+            drop(spanner.enter_span("discarded"));
+        }
+        assert!(writer.is_truncated());
+        assert_eq!(
+            std::str::from_utf8(writer.get_ref()).unwrap(),
+            "┌outer\n|  inner\n… output truncated\n"
+        );
+    }
+
+    #[test]
+    fn channel_writer() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let spanner = Spanner::from_writer(ChannelWriter::new(tx));
+        {
+            let _outer = spanner.enter_span("outer");
+            let _inner = spanner.enter_span("inner");
+        }
+        drop(spanner);
+        let lines: Vec<String> = rx.iter().collect();
+        assert_eq!(lines, ["┌outer\n", "|  inner\n", "|  inner\n", "└outer\n"]);
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        drop(rx);
+        let spanner = Spanner::from_writer(ChannelWriter::new(tx));
+        drop(spanner.enter_span("work"));
+        assert_eq!(spanner.last_error(), Some(io::ErrorKind::BrokenPipe));
+    }
+
+    #[test]
+    fn rotating_file() {
+        let dir = std::env::temp_dir().join(format!("spannify-rotating-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("trace.log");
+        // The first two lines take 16 bytes, so the third one goes into the next file
+        let spanner = Spanner::from_writer(RotatingFile::new(&path, 20).unwrap());
+        {
+            let _outer = spanner.enter_span("outer");
+            let _inner = spanner.enter_span("inn");
+        }
+        drop(spanner);
+        let first = std::fs::read_to_string(&path).unwrap();
+        let second = std::fs::read_to_string(dir.join("trace.1.log")).unwrap();
+        assert_eq!(first, "┌outer\n|  inn\n");
+        assert_eq!(second, "|  inn\n└outer\n");
+        assert!(!dir.join("trace.2.log").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn log_writer() {
+        use crate::{config::Config, core::LogSpanner, level::Level};
+        use std::sync::Mutex;
+
+        /// A logger that captures the records of the `spannify` target.
+        struct Capture(Mutex<Vec<(log::Level, String)>>);
+
+        impl log::Log for Capture {
+            fn enabled(&self, metadata: &log::Metadata) -> bool {
+                metadata.target() == "spannify"
+            }
+
+            fn log(&self, record: &log::Record) {
+                if self.enabled(record.metadata()) {
+                    let line = record.args().to_string();
+                    self.0.lock().unwrap().push((record.level(), line));
+                }
+            }
+
+            fn flush(&self) {}
+        }
+
+        static CAPTURE: Capture = Capture(Mutex::new(Vec::new()));
+        log::set_logger(&CAPTURE).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+
+        let spanner = LogSpanner::new()
+            .with_config(Config::new().with_level(Level::Trace).with_summary(true));
+        {
+            let _outer = spanner.enter_with_level(Level::Warn, "outer");
+            let _inner = spanner.enter_with_level(Level::Trace, "inner");
+        }
+        drop(spanner);
+        let expected = [
+            (log::Level::Warn, "┌outer"),
+            (log::Level::Trace, "|  inner"),
+            (log::Level::Trace, "|  inner"),
+            (log::Level::Warn, "└outer"),
+            (log::Level::Info, "— 2 spans, max depth 2 —"),
+        ];
+        let records = CAPTURE.0.lock().unwrap();
+        let records: Vec<_> = records.iter().map(|(l, s)| (*l, s.as_str())).collect();
+        assert_eq!(records, expected);
+    }
+}
@@ -0,0 +1,13 @@
+//! `Config::from_env` reads the process environment, which is shared by every test of a test
+//! binary, so it is tested in its own test binary.
+#![cfg(feature = "std")]
+
+use spannify::{config::Config, level::Level};
+
+#[test]
+fn from_env_reads_level() {
+    std::env::set_var("SPANNIFY_LEVEL", "debug");
+    assert_eq!(Config::from_env().level, Level::Debug);
+    std::env::remove_var("SPANNIFY_LEVEL");
+    assert_eq!(Config::from_env().level, Level::Info);
+}
@@ -0,0 +1,42 @@
+//! `spannify::init` installs the global spanner, which can only be set once per process, so it is
+//! tested in its own test binary.
+#![cfg(all(feature = "std", not(feature = "disabled")))]
+
+use spannify::{gspf, level::Level};
+
+/// Sets an environment variable and restores its previous value when dropped.
+struct EnvGuard {
+    key: &'static str,
+    previous: Option<std::ffi::OsString>,
+}
+
+impl EnvGuard {
+    fn set(key: &'static str, value: &str) -> Self {
+        let previous = std::env::var_os(key);
+        std::env::set_var(key, value);
+        Self { key, previous }
+    }
+}
+
+impl Drop for EnvGuard {
+    fn drop(&mut self) {
+        match &self.previous {
+            Some(value) => std::env::set_var(self.key, value),
+            None => std::env::remove_var(self.key),
+        }
+    }
+}
+
+#[test]
+fn init_reads_level_from_env() {
+    let spanner = {
+        let _guard = EnvGuard::set("SPANNIFY_LEVEL", "warn");
+        spannify::init().unwrap()
+    };
+    assert!(spannify::init().is_err());
+
+    let _info = gspf!("info");
+    assert_eq!(spanner.depth(), 0);
+    let _warn = gspf!(Level::Warn, "warn");
+    assert_eq!(spanner.depth(), 1);
+}